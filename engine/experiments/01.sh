@@ -1,9 +1,9 @@
 #!/usr/bin/env bash
 
-for i in {1..5}; do cargo run --release -- -a 10000 -d sars; done
+for i in {1..5}; do cargo run --release -- -c experiments/config/10000_agents.json --disease sars; done
 
-for i in {1..5}; do cargo run --release -- -a 100000 -d sars; done
+for i in {1..5}; do cargo run --release -- -c experiments/config/100000_agents.json --disease sars; done
 
-for i in {1..5}; do cargo run --release -- -a 10000 -d covid_19; done
+for i in {1..5}; do cargo run --release -- -c experiments/config/10000_agents.json --disease covid_19; done
 
-for i in {1..5}; do cargo run --release -- -a 100000 -d covid_19; done
+for i in {1..5}; do cargo run --release -- -c experiments/config/100000_agents.json --disease covid_19; done
@@ -1,3 +1,3 @@
 #!/usr/bin/env bash
 
-for i in {1..3}; do cargo run --release -- -a 1000000 -d covid_19; done
+for i in {1..3}; do cargo run --release -- -c experiments/config/1000000_agents.json --disease covid_19; done
@@ -0,0 +1,118 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::collections::BTreeMap;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::random_wrapper::RandomWrapper;
+
+/// The number of years an open-ended age group such as `80+` is taken to span
+const OPEN_AGE_GROUP_SPAN: u8 = 5;
+
+/// A range of ages in years, written as `60-64`, `80+` for an open-ended group, or `37` for a single age
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AgeGroup {
+    pub from: u8,
+    pub to: Option<u8>,
+}
+
+impl AgeGroup {
+    pub fn parse(label: &str) -> Result<AgeGroup, String> {
+        let label = label.trim();
+        let age = |text: &str| text.trim().parse::<u8>()
+            .map_err(|_| format!("{} is not an age group such as 60-64 or 80+", label));
+        if let Some(from) = label.strip_suffix('+') {
+            return Ok(AgeGroup { from: age(from)?, to: None });
+        }
+        match label.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (age(from)?, age(to)?);
+                if to < from {
+                    return Err(format!("{} ends before it starts", label));
+                }
+                Ok(AgeGroup { from, to: Some(to) })
+            }
+            None => {
+                let age = age(label)?;
+                Ok(AgeGroup { from: age, to: Some(age) })
+            }
+        }
+    }
+
+    /// An age in the group, with every year of the group equally likely
+    pub fn random_age(&self, rng: &mut RandomWrapper) -> u8 {
+        let to = self.to.unwrap_or_else(|| self.from.saturating_add(OPEN_AGE_GROUP_SPAN - 1));
+        rng.get().gen_range(self.from as u32, to as u32 + 1) as u8
+    }
+}
+
+/// The share of the population in each age group, such as `{"0-19": 0.3, "20-59": 0.55, "60+": 0.15}`. The shares are
+/// relative to each other, and don't need to add up to 1.
+pub struct AgeDistribution {
+    groups: Vec<AgeGroup>,
+    shares: WeightedIndex<f64>,
+}
+
+impl AgeDistribution {
+    pub fn new(shares: &BTreeMap<String, f64>) -> Result<AgeDistribution, String> {
+        let groups = shares.keys().map(|label| AgeGroup::parse(label)).collect::<Result<Vec<_>, _>>()?;
+        let shares = WeightedIndex::new(shares.values())
+            .map_err(|_| "should have shares that are not negative, and at least one above 0".to_string())?;
+        Ok(AgeDistribution { groups, shares })
+    }
+
+    pub fn random_age(&self, rng: &mut RandomWrapper) -> u8 {
+        self.groups[self.shares.sample(rng.get())].random_age(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_age_groups() {
+        assert_eq!(AgeGroup::parse("60-64"), Ok(AgeGroup { from: 60, to: Some(64) }));
+        assert_eq!(AgeGroup::parse("80+"), Ok(AgeGroup { from: 80, to: None }));
+        assert_eq!(AgeGroup::parse(" 37 "), Ok(AgeGroup { from: 37, to: Some(37) }));
+        assert!(AgeGroup::parse("64-60").is_err());
+        assert!(AgeGroup::parse("adult").is_err());
+    }
+
+    #[test]
+    fn should_draw_ages_within_the_groups_in_proportion_to_their_shares() {
+        let shares: BTreeMap<String, f64> = vec![("0-9".to_string(), 1.0), ("90+".to_string(), 3.0)].into_iter().collect();
+        let distribution = AgeDistribution::new(&shares).unwrap();
+        let mut rng = RandomWrapper::with_seed(Some(5));
+
+        let ages: Vec<u8> = (0..4000).map(|_| distribution.random_age(&mut rng)).collect();
+
+        assert!(ages.iter().all(|age| *age <= 9 || (90..=94).contains(age)));
+        let old = ages.iter().filter(|age| **age >= 90).count();
+        assert!((2850..3150).contains(&old), "{} citizens were 90 or older", old);
+    }
+
+    #[test]
+    fn should_not_accept_distribution_without_shares() {
+        let shares: BTreeMap<String, f64> = vec![("0-9".to_string(), 0.0)].into_iter().collect();
+        assert!(AgeDistribution::new(&shares).is_err());
+    }
+}
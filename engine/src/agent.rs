@@ -23,14 +23,17 @@ use rand::seq::SliceRandom;
 
 use crate::allocation_map::AgentLocationMap;
 use crate::constants;
+use crate::age::AgeGroup;
+use crate::contact_network::{Exposure, Layer, Mixing, Source};
 use crate::geography::{Area, Grid, Point};
 use crate::random_wrapper::RandomWrapper;
 use crate::disease::Disease;
+use crate::progression::{Course, CourseSampler};
 use serde::{Deserializer, Deserialize, de};
 use serde::de::{Unexpected};
 use crate::listeners::events::counts::Counts;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum State {
     Susceptible {},
     Infected {},
@@ -38,10 +41,13 @@ pub enum State {
     Deceased {},
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StateMachine {
     pub state: State,
     infection_day: i32,
+    /// The course the disease takes if the citizen gets infected, drawn at the start of the simulation
+    #[serde(default)]
+    course: Course,
 }
 
 impl StateMachine {
@@ -49,9 +55,14 @@ impl StateMachine {
         StateMachine {
             state: State::Susceptible {},
             infection_day: 0,
+            course: Course::default(),
         }
     }
 
+    pub fn get_course(&self) -> &Course {
+        &self.course
+    }
+
     pub fn get_infection_day(self) -> i32 {
         match self.state {
             State::Infected {} => {
@@ -88,9 +99,12 @@ fn bool_from_string<'de, D>(deserializer: D) -> Result<bool, D::Error>
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Citizen {
     pub id: i32,
+    /// In years
+    #[serde(default)]
+    pub age: u8,
     immunity: i32,
     pub home_location: Area,
     pub work_location: Area,
@@ -102,7 +116,10 @@ pub struct Citizen {
     pub state_machine: StateMachine,
     quarantined: bool,
     isolated: bool,
-    current_area: Area
+    current_area: Area,
+    /// The node of the citizen in the contact network of their region, if the infection spreads over one
+    #[serde(skip)]
+    pub contact_node: Option<u32>,
 }
 
 impl Citizen {
@@ -111,6 +128,7 @@ impl Citizen {
 
         Citizen {
             id,
+            age: 0,
             immunity: disease_randomness_factor,
             home_location,
             work_location,
@@ -122,16 +140,20 @@ impl Citizen {
             state_machine: StateMachine::new(),
             quarantined: false,
             isolated: false,
-            current_area: home_location
+            current_area: home_location,
+            contact_node: None,
         }
     }
 
     pub fn from_record(record: PopulationRecord, home_location: Area, work_location: Area,
                        transport_location: Point, rng: &mut RandomWrapper) -> Citizen {
         let disease_randomness_factor = Citizen::generate_disease_randomness_factor(rng);
+        let age_group = AgeGroup::parse(&record.age)
+            .unwrap_or_else(|e| panic!("Could not read the age of citizen {}: {}", record.ind, e));
 
         Citizen {
             id: record.ind,
+            age: age_group.from,
             immunity: disease_randomness_factor,
             home_location,
             work_location,
@@ -143,12 +165,17 @@ impl Citizen {
             state_machine: StateMachine::new(),
             quarantined: false,
             isolated: false,
-            current_area: home_location
+            current_area: home_location,
+            contact_node: None,
         }
     }
 
     pub fn get_infection_transmission_rate(&self, disease: &Disease) -> f64 {
-        disease.get_current_transmission_rate(self.state_machine.get_infection_day() + self.immunity)
+        disease.get_current_transmission_rate(&self.state_machine.course, self.state_machine.get_infection_day())
+    }
+
+    pub fn draw_course(&mut self, sampler: &CourseSampler, rng: &mut RandomWrapper) {
+        self.state_machine.course = sampler.draw(self.immunity, rng);
     }
 
     pub fn set_vaccination(&mut self, vaccinated: bool) {
@@ -170,7 +197,7 @@ impl Citizen {
     pub fn quarantine(&mut self, disease: &Disease) -> i32 {
         match self.state_machine.state {
             State::Infected {} => {
-                if disease.to_be_quarantined(self.state_machine.infection_day + self.immunity) {
+                if disease.to_be_quarantined(&self.state_machine.course, self.state_machine.infection_day) {
                     self.quarantined = true;
                     return 1;
                 }
@@ -185,7 +212,7 @@ impl Citizen {
     pub fn decease(&mut self, rng: &mut RandomWrapper, disease: &Disease) -> (i32, i32) {
         match self.state_machine.state {
             State::Infected {} => {
-                if self.state_machine.infection_day == disease.get_disease_last_day() {
+                if disease.to_be_recovered(&self.state_machine.course, self.state_machine.infection_day) {
                     self.hospitalized = false;
                     self.quarantined = false;
 
@@ -254,44 +281,78 @@ impl Citizen {
         self.isolated
     }
 
+    pub fn is_vaccinated(&self) -> bool {
+        self.vaccinated
+    }
+
+    pub fn is_working(&self) -> bool {
+        self.working
+    }
+
+    /// Whether the citizen can pass the infection on; citizens in the hospital are kept apart from everyone else
+    pub fn can_infect(&self) -> bool {
+        (self.is_infected() || self.is_quarantined()) && !self.hospitalized
+    }
+
+    /// Gives a citizen who travelled to another region a home (and a workplace, if working) in that region. They leave
+    /// the contact network of the region they came from, and are given a node in the one they arrive in, if any.
+    pub fn relocate(&mut self, home_location: Area, work_location: Area) {
+        self.home_location = home_location;
+        self.work_location = if self.working { work_location } else { home_location };
+        self.current_area = home_location;
+        self.contact_node = None;
+    }
+
+    /// Keeps the citizen in the area they were in at the start of the hour, when another citizen took the cell they
+    /// were moving to first
+    pub fn stay_in_area_of(&mut self, before: &Citizen) {
+        self.current_area = before.current_area;
+    }
+
+    pub fn get_current_area(&self) -> Area {
+        self.current_area
+    }
+
     fn generate_disease_randomness_factor(rng: &mut RandomWrapper) -> i32 {
         let option = constants::IMMUNITY_RANGE.choose(rng.get());
         *option.unwrap()
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn perform_operation(&mut self, cell: Point, simulation_hour: i32, grid: &Grid, map: &AgentLocationMap,
-                             counts: &mut Counts, rng: &mut RandomWrapper, disease: &Disease) -> Point {
-        self.routine(cell, simulation_hour, grid, map, counts, rng, disease)
+                             mixing: Mixing, counts: &mut Counts, rng: &mut RandomWrapper, disease: &Disease) -> Point {
+        self.routine(cell, simulation_hour, grid, map, mixing, counts, rng, disease)
     }
 
-    fn routine(&mut self, cell: Point, simulation_hour: i32, grid: &Grid, map: &AgentLocationMap, counts: &mut Counts,
-               rng: &mut RandomWrapper, disease: &Disease) -> Point {
+    #[allow(clippy::too_many_arguments)]
+    fn routine(&mut self, cell: Point, simulation_hour: i32, grid: &Grid, map: &AgentLocationMap, mixing: Mixing,
+               counts: &mut Counts, rng: &mut RandomWrapper, disease: &Disease) -> Point {
         let mut new_cell = cell;
+        let exposure = mixing.exposure(map, grid, self.current_area, Layer::active_at(simulation_hour, self.working));
         match simulation_hour % constants::NUMBER_OF_HOURS {
             constants::ROUTINE_START_TIME => {
                 self.update_infection_day();
-                new_cell = self.quarantine_all(cell, &grid.hospital_area, map, counts, disease);
+                new_cell = self.quarantine_all(cell, &grid.hospital_area, map, counts, rng, disease);
             }
             constants::SLEEP_START_TIME..=constants::SLEEP_END_TIME => {}
             constants::ROUTINE_TRAVEL_START_TIME | constants::ROUTINE_TRAVEL_END_TIME => {
                 new_cell = self.goto_area(grid.transport_area, map, cell, rng);
-                self.current_area = grid.transport_area;
-                self.update_infection(cell, map, counts, rng, disease);
+                self.update_infection(cell, &exposure, counts, rng, disease);
             }
             constants::ROUTINE_WORK_TIME => {
                 new_cell = self.goto_area(self.work_location, map, cell, rng);
-                self.update_infection(cell, map, counts, rng, disease);
+                self.update_infection(cell, &exposure, counts, rng, disease);
             }
             constants::ROUTINE_WORK_END_TIME => {
                 new_cell = self.goto_area(self.home_location, map, cell, rng);
-                self.update_infection(cell, map, counts, rng, disease);
+                self.update_infection(cell, &exposure, counts, rng, disease);
             }
             constants::ROUTINE_END_TIME => {
                 new_cell = self.deceased(map, cell, counts, rng, disease)
             }
             _ => {
                 new_cell = self.move_agent_from(map, cell, rng);
-                self.update_infection(cell, map, counts, rng, disease);
+                self.update_infection(cell, &exposure, counts, rng, disease);
             }
         }
         new_cell
@@ -304,14 +365,15 @@ impl Citizen {
     }
 
     fn quarantine_all(&mut self, cell: Point, hospital: &Area, map: &AgentLocationMap, counts: &mut Counts,
-                      disease: &Disease) -> Point {
+                      rng: &mut RandomWrapper, disease: &Disease) -> Point {
         let mut new_cell = cell;
         if self.is_infected() && !self.is_quarantined() {
             let number_of_quarantined = self.quarantine(disease);
             if number_of_quarantined > 0 {
-                new_cell = AgentLocationMap::goto_hospital(map, hospital, cell, self);
+                new_cell = AgentLocationMap::goto_hospital(map, hospital, cell, self, rng);
                 if new_cell != cell {
                     self.hospitalized = true;
+                    self.current_area = if hospital.contains(&new_cell) { *hospital } else { self.home_location };
                 }
                 counts.update_quarantined(number_of_quarantined);
                 counts.update_infected(-number_of_quarantined);
@@ -320,21 +382,35 @@ impl Citizen {
         new_cell
     }
 
-    fn update_infection(&mut self, cell: Point, map: &AgentLocationMap, counts: &mut Counts, rng: &mut RandomWrapper,
+    fn update_infection(&mut self, cell: Point, exposure: &Exposure, counts: &mut Counts, rng: &mut RandomWrapper,
                         disease: &Disease) {
         if self.is_susceptible() && !self.vaccinated {
-            let neighbours = self.current_area.get_neighbors_of(cell);
-
-            let neighbor_that_spreads_infection = neighbours
-                .filter(|p| map.is_point_in_grid(p))
-                .filter_map(|cell| { map.get_agent_for(&cell) })
-                .filter(|agent| (agent.is_infected() || agent.is_quarantined()) && !agent.hospitalized)
-                .find(|neighbor| rng.get().gen_bool(neighbor.get_infection_transmission_rate(disease)));
+            let got_infected = match exposure.source {
+                Source::Grid(map, area, contact_matrix) => {
+                    let neighbours = area.get_neighbors_of(cell);
+
+                    neighbours
+                        .filter(|p| map.is_point_in_grid(p))
+                        .filter_map(|cell| { map.get_agent_for(&cell) })
+                        .filter(|agent| agent.can_infect())
+                        .any(|neighbor| {
+                            let mut rate = neighbor.get_infection_transmission_rate(disease) * exposure.multiplier;
+                            if let Some(matrix) = contact_matrix {
+                                rate *= matrix.weight(self.age, neighbor.age);
+                            }
+                            rng.get().gen_bool(rate.min(1.0))
+                        })
+                }
+                Source::Network(network, layer) => {
+                    self.contact_node.is_some_and(|node| network.infects(node, layer, exposure.multiplier, rng))
+                }
+            };
 
-            if neighbor_that_spreads_infection.is_some() {
+            if got_infected {
                 self.infect();
                 counts.update_infected(1);
                 counts.update_susceptible(-1);
+                counts.update_infections_in(exposure.setting, 1);
             }
         }
     }
@@ -344,12 +420,11 @@ impl Citizen {
             return cell;
         }
         if self.working {
-            let mut new_cell: Point = target_area.get_random_point(rng);
-                if !map.is_cell_vacant(&new_cell){
-                    new_cell = cell;
-                }
-
-            return map.move_agent(cell, new_cell);
+            let new_cell = map.move_agent(cell, target_area.get_random_point(rng));
+            if new_cell != cell {
+                self.current_area = target_area;
+            }
+            return new_cell;
         }
         self.move_agent_from(map, cell, rng)
     }
@@ -361,6 +436,9 @@ impl Citizen {
             let result = self.decease(rng, disease);
             if result.1 == 1 {
                 new_cell = map.move_agent(cell, self.home_location.get_random_point(rng));
+                if new_cell != cell {
+                    self.current_area = self.home_location;
+                }
             }
             counts.update_deceased(result.0);
             counts.update_recovered(result.1);
@@ -415,6 +493,8 @@ pub fn citizen_factory(number_of_agents: i32, home_locations: &Vec<Area>, work_l
 
 #[cfg(test)]
 mod tests {
+    use crate::contact_matrix::ContactMatrices;
+
     use super::*;
 
     fn before_each() -> Vec<Citizen> {
@@ -455,4 +535,35 @@ mod tests {
 
         citizen_list[0].quarantine(&disease);
     }
+
+    #[test]
+    fn should_stay_in_hospital_area_when_home_is_full_on_recovery() {
+        let mut rng = RandomWrapper::new();
+        let grid = crate::geography::define_geography(10);
+        let disease = Disease::new(0, 0, 0, 0.0, 0.0, 0.0);
+        let home = Area::new(Point::new(0, 0), Point::new(1, 1));
+        let mut citizen = Citizen::new_citizen(1, home, home, Point::new(0, 0), false, false, &mut rng);
+        citizen.infect();
+        citizen.state_machine.course = Course { latent_period: 0, infectious_period: 10, time_to_quarantine: 0,
+                                                time_to_recovery: 0 };
+        let neighbour = Citizen::new_citizen(2, home, home, Point::new(0, 0), false, false, &mut rng);
+        let matrices = ContactMatrices::default();
+        let multipliers = Default::default();
+        let mixing = Mixing::new(None, &matrices, &multipliers);
+        let mut counts = Counts::new(2, 0);
+
+        let map = AgentLocationMap::new(10, &[citizen], &[Point::new(0, 0)]);
+        let hospital_cell = citizen.perform_operation(Point::new(0, 0), constants::ROUTINE_START_TIME, &grid, &map,
+                                                      mixing, &mut counts, &mut rng, &disease);
+        assert!(grid.hospital_area.contains(&hospital_cell));
+        assert_eq!(citizen.get_current_area(), grid.hospital_area);
+
+        let map = AgentLocationMap::new(10, &[citizen, neighbour], &[hospital_cell, Point::new(0, 0)]);
+        let cell = citizen.perform_operation(hospital_cell, constants::ROUTINE_END_TIME, &grid, &map, mixing,
+                                             &mut counts, &mut rng, &disease);
+
+        assert!(citizen.state_machine.state == State::Recovered {});
+        assert_eq!(cell, hospital_cell);
+        assert_eq!(citizen.get_current_area(), grid.hospital_area);
+    }
 }
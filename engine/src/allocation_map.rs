@@ -17,26 +17,44 @@
  *
  */
 
-use fxhash::FxHashMap;
-
 use crate::agent;
 use crate::geography::Area;
 use crate::geography::Point;
 use crate::random_wrapper::RandomWrapper;
 
+/// Marks a cell without a citizen in `AgentLocationMap::cells`
+const VACANT: u32 = u32::MAX;
+
+/// The citizens on the grid, and the cell each of them is in. The citizens are kept together in a `Vec`, next to their
+/// cells, and a dense array with an entry for every cell of the grid holds the index of the citizen in it, so that
+/// finding the citizen in a cell is a single lookup.
+///
+/// Some areas reach past the grid, such as the offices of a CSV population and a hospital grown to the edge of the
+/// grid, so the array grows to take in any cell a citizen is placed in.
 pub struct AgentLocationMap {
     pub grid_size: i32,
-    pub agent_cell: FxHashMap<Point, agent::Citizen>,
+    /// The number of columns and rows of `cells`, at least `grid_size`
+    width: i32,
+    height: i32,
+    cells: Vec<u32>,
+    citizens: Vec<agent::Citizen>,
+    locations: Vec<Point>,
 }
 
 impl AgentLocationMap {
     pub fn new(size: i32, agent_list: &[agent::Citizen], points: &[Point]) -> AgentLocationMap {
-        let mut map: FxHashMap<Point, agent::Citizen> = FxHashMap::default();
+        let mut map = AgentLocationMap {
+            grid_size: size,
+            width: size,
+            height: size,
+            cells: vec![VACANT; size as usize * size as usize],
+            citizens: Vec::with_capacity(agent_list.len()),
+            locations: Vec::with_capacity(agent_list.len()),
+        };
         for i in 0..agent_list.len() {
             map.insert(points[i], agent_list[i]);
         }
-
-        AgentLocationMap { grid_size: size, agent_cell: map }
+        map
     }
 
     pub fn move_agent(&self, old_cell: Point, new_cell: Point) -> Point {
@@ -46,21 +64,17 @@ impl AgentLocationMap {
         old_cell
     }
 
-    pub fn goto_hospital(&self, hospital_area: &Area, cell: Point, citizen: &mut agent::Citizen) -> Point {
+    /// Moves the citizen to a vacant cell of the hospital, or to a cell of their home when the hospital is full
+    pub fn goto_hospital(&self, hospital_area: &Area, cell: Point, citizen: &mut agent::Citizen,
+                         rng: &mut RandomWrapper) -> Point {
         let vacant_hospital_cell = hospital_area.into_iter().find(|cell| {
             self.is_cell_vacant(cell)
         });
-        self.move_agent(cell, vacant_hospital_cell.unwrap_or(citizen.home_location.get_random_point(&mut RandomWrapper::new())))
+        self.move_agent(cell, vacant_hospital_cell.unwrap_or_else(|| citizen.home_location.get_random_point(rng)))
     }
 
-//    pub fn print(&self){
-//        for (k,v) in self.agent_cell.iter(){
-//            println!("x:{}, y:{} - id:{} infected:{} working:{} Transport:{}", k.x, k.y, v.id, v.is_infected(), v.working, v.uses_public_transport);
-//        }
-//    }
-
     pub fn get_agent_for(&self, cell: &Point) -> Option<&agent::Citizen> {
-        self.agent_cell.get(cell)
+        self.citizen_index(cell).map(|i| &self.citizens[i])
     }
 
     pub fn is_point_in_grid(&self, point: &Point) -> bool {
@@ -68,7 +82,95 @@ impl AgentLocationMap {
     }
 
     pub fn is_cell_vacant(&self, cell: &Point) -> bool {
-        !self.agent_cell.contains_key(cell)
+        self.citizen_index(cell).is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        self.citizens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.citizens.is_empty()
+    }
+
+    /// The citizens with their cells, in the order they are stored
+    pub fn iter(&self) -> impl Iterator<Item=(&Point, &agent::Citizen)> {
+        self.locations.iter().zip(self.citizens.iter())
+    }
+
+    /// The citizens in the order they are stored, without their cells
+    pub fn citizens(&self) -> impl Iterator<Item=&agent::Citizen> {
+        self.citizens.iter()
+    }
+
+    pub fn citizens_mut(&mut self) -> impl Iterator<Item=&mut agent::Citizen> {
+        self.citizens.iter_mut()
+    }
+
+    /// Places a citizen in a cell, replacing the citizen already in it, if any
+    pub fn insert(&mut self, cell: Point, citizen: agent::Citizen) {
+        if self.cell_index(&cell).is_none() {
+            self.grow_to(&cell);
+        }
+        let cell_index = self.cell_index(&cell).unwrap();
+        match self.cells[cell_index] {
+            VACANT => {
+                self.cells[cell_index] = self.citizens.len() as u32;
+                self.citizens.push(citizen);
+                self.locations.push(cell);
+            }
+            i => self.citizens[i as usize] = citizen,
+        }
+    }
+
+    /// Takes the citizen out of a cell. The last citizen takes the place of the removed one, so the order of the
+    /// citizens changes.
+    pub fn remove(&mut self, cell: &Point) -> Option<agent::Citizen> {
+        let i = self.citizen_index(cell)?;
+        let cell_index = self.cell_index(cell).unwrap();
+        self.cells[cell_index] = VACANT;
+        let citizen = self.citizens.swap_remove(i);
+        self.locations.swap_remove(i);
+        if let Some(moved) = self.locations.get(i) {
+            let moved_cell = self.cell_index(moved).unwrap();
+            self.cells[moved_cell] = i as u32;
+        }
+        Some(citizen)
+    }
+
+    /// Removes all the citizens, only visiting the cells they were in, and keeps the memory for placing them again
+    pub fn clear(&mut self) {
+        for i in 0..self.locations.len() {
+            let cell_index = self.cell_index(&self.locations[i]).unwrap();
+            self.cells[cell_index] = VACANT;
+        }
+        self.citizens.clear();
+        self.locations.clear();
+    }
+
+    fn cell_index(&self, cell: &Point) -> Option<usize> {
+        if cell.x < 0 || cell.y < 0 || cell.x >= self.width || cell.y >= self.height {
+            return None;
+        }
+        Some(cell.y as usize * self.width as usize + cell.x as usize)
+    }
+
+    fn grow_to(&mut self, cell: &Point) {
+        assert!(cell.x >= 0 && cell.y >= 0, "Citizens can't be placed at negative cells, such as {:?}", cell);
+        self.width = self.width.max(cell.x + 1);
+        self.height = self.height.max(cell.y + 1);
+        self.cells = vec![VACANT; self.width as usize * self.height as usize];
+        for i in 0..self.locations.len() {
+            let cell_index = self.cell_index(&self.locations[i]).unwrap();
+            self.cells[cell_index] = i as u32;
+        }
+    }
+
+    fn citizen_index(&self, cell: &Point) -> Option<usize> {
+        match self.cells[self.cell_index(cell)?] {
+            VACANT => None,
+            i => Some(i as usize),
+        }
     }
 }
 
@@ -94,12 +196,57 @@ mod tests {
     #[test]
     fn new() {
         let map = before_each();
-        let actual_citizen = map.agent_cell.get(&Point { x: 0, y: 1 }).unwrap();
+        let actual_citizen = map.get_agent_for(&Point { x: 0, y: 1 }).unwrap();
 
         assert_eq!(map.grid_size, 5);
         assert_eq!(actual_citizen.id, 1);
     }
 
+    #[test]
+    fn should_find_citizens_after_another_one_is_removed() {
+        let mut map = before_each();
+
+        let removed = map.remove(&Point::new(0, 1)).unwrap();
+
+        assert_eq!(removed.id, 1);
+        assert_eq!(map.len(), 1);
+        assert!(map.is_cell_vacant(&Point::new(0, 1)));
+        assert_eq!(map.get_agent_for(&Point::new(1, 0)).unwrap().id, 2);
+        assert!(map.remove(&Point::new(0, 1)).is_none());
+        assert_eq!(map.iter().map(|(cell, citizen)| (*cell, citizen.id)).collect::<Vec<_>>(), vec![(Point::new(1, 0), 2)]);
+    }
+
+    #[test]
+    fn should_clear_and_place_citizens_again() {
+        let mut map = before_each();
+        let citizen = *map.get_agent_for(&Point::new(1, 0)).unwrap();
+
+        map.clear();
+        map.insert(Point::new(4, 4), citizen);
+
+        assert_eq!(map.len(), 1);
+        assert!(map.is_cell_vacant(&Point::new(0, 1)));
+        assert!(map.is_cell_vacant(&Point::new(1, 0)));
+        assert_eq!(map.get_agent_for(&Point::new(4, 4)).unwrap().id, 2);
+        assert!(map.get_agent_for(&Point::new(5, 4)).is_none());
+    }
+
+    #[test]
+    fn should_place_citizens_past_the_edge_of_the_grid() {
+        let mut map = before_each();
+        let citizen = map.remove(&Point::new(1, 0)).unwrap();
+
+        assert!(map.is_cell_vacant(&Point::new(5, 5)));
+        map.insert(Point::new(5, 5), citizen);
+
+        assert!(!map.is_point_in_grid(&Point::new(5, 5)));
+        assert!(!map.is_cell_vacant(&Point::new(5, 5)));
+        assert_eq!(map.get_agent_for(&Point::new(5, 5)).unwrap().id, 2);
+        assert_eq!(map.get_agent_for(&Point::new(0, 1)).unwrap().id, 1);
+        assert!(map.is_cell_vacant(&Point::new(1, 0)));
+        assert_eq!(map.len(), 2);
+    }
+
     #[test]
     fn should_goto_hospital() {
         let mut rng = RandomWrapper::new();
@@ -113,7 +260,7 @@ mod tests {
         let map = AgentLocationMap::new(5, &agents, &points);
         let hospital = Area::new(Point::new(2, 2), Point::new(4, 4));
 
-        assert_eq!(map.goto_hospital(&hospital, points[0], &mut citizen1), Point::new(2, 2));
+        assert_eq!(map.goto_hospital(&hospital, points[0], &mut citizen1, &mut rng), Point::new(2, 2));
     }
 
     #[test]
@@ -131,7 +278,29 @@ mod tests {
         let map = AgentLocationMap::new(5, &agents, &points);
         let hospital = Area::new(Point::new(0, 0), Point::new(1, 1));
 
-        assert_eq!(map.goto_hospital(&hospital, points[0], &mut citizen1), points[0]);
+        assert_eq!(map.goto_hospital(&hospital, points[0], &mut citizen1, &mut rng), points[0]);
+    }
+
+    #[test]
+    fn should_pick_the_same_home_cell_for_the_same_seed_when_hospital_full() {
+        let mut rng = RandomWrapper::new();
+        let points = vec![Point::new(0, 0), Point::new(0, 1), Point::new(1, 0), Point::new(1, 1)];
+        let home = Area::new(Point::new(2, 2), Point::new(5, 5));
+        let agents: Vec<agent::Citizen> = (0..4)
+            .map(|id| agent::Citizen::new_citizen(id, home, home, points[0], false, false, &mut rng))
+            .collect();
+        let map = AgentLocationMap::new(6, &agents, &points);
+        let hospital = Area::new(Point::new(0, 0), Point::new(1, 1));
+        let mut citizen = agents[0];
+        let mut home_cells = |seed| -> Vec<Point> {
+            let mut rng = RandomWrapper::with_seed(Some(seed));
+            (0..10).map(|_| map.goto_hospital(&hospital, points[0], &mut citizen, &mut rng)).collect()
+        };
+
+        let cells = home_cells(4);
+
+        assert!(cells.iter().all(|cell| home.contains(cell)));
+        assert_eq!(cells, home_cells(4));
     }
 
     #[test]
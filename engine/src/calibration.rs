@@ -0,0 +1,492 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+
+use csv::Writer;
+use rand::Rng;
+
+use crate::config;
+use crate::config::Config;
+use crate::constants;
+use crate::ensemble::{check_parallelism, run_all, Run};
+use crate::listeners::events::counts::Counts;
+use crate::parameter_space::ParameterSpace;
+use crate::random_wrapper::RandomWrapper;
+use crate::validation::ValidationErrors;
+
+fn one() -> usize {
+    1
+}
+
+fn no_scaling() -> f64 {
+    1.0
+}
+
+fn default_best_fits() -> usize {
+    5
+}
+
+/// Searches for the values of some parameters of a config that best reproduce the observed daily cases and deaths
+#[derive(Debug, Deserialize)]
+pub struct CalibrationConfig {
+    config: String,
+    observed: String,
+    parameters: BTreeMap<String, (f64, f64)>,
+    #[serde(default)]
+    objective: Objective,
+    method: Method,
+    #[serde(default = "one")]
+    replicates: usize,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "no_scaling")]
+    scale: f64,
+    #[serde(default = "default_best_fits")]
+    best_fits: usize,
+    output_dir: String,
+    parallelism: Option<usize>,
+}
+
+/// What the simulated epicurve is compared with, as the sum of squared errors over the observed days
+#[derive(Debug, PartialEq, Default, Deserialize)]
+pub enum Objective {
+    #[default]
+    Cases,
+    Deaths,
+    CasesAndDeaths,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub enum Method {
+    /// Approximate Bayesian computation by rejection: tries parameters drawn uniformly from their bounds, and keeps
+    /// the closest fits
+    Abc { samples: usize },
+    /// Nelder-Mead simplex search, starting from the middle of the bounds
+    NelderMead { max_iterations: usize },
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct ObservedDay {
+    day: i32,
+    cases: f64,
+    deaths: Option<f64>,
+}
+
+/// A set of parameter values, with how far their mean simulated epicurve is from the observed one
+#[derive(Debug, Clone)]
+struct Fit {
+    values: Vec<f64>,
+    error: f64,
+    cases: Vec<f64>,
+    deaths: Vec<f64>,
+}
+
+pub fn read(filename: &str) -> Result<CalibrationConfig, Box<dyn Error>> {
+    let reader = File::open(filename)?;
+    let calibration: CalibrationConfig = serde_json::from_reader(reader)?;
+    Ok(calibration)
+}
+
+fn read_observed(filename: &str) -> Result<Vec<ObservedDay>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_path(filename)?;
+    let mut observed = Vec::new();
+    for row in reader.deserialize() {
+        observed.push(row?);
+    }
+    Ok(observed)
+}
+
+impl CalibrationConfig {
+    /// Writes the best fits to `best_fits.csv`, and the simulated and observed epicurves of each of them to
+    /// `fit_<rank>.csv`
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let observed = read_observed(&self.observed)?;
+        self.validate(&observed)?;
+        let base_config = config::read(self.config.clone())?;
+        let space = ParameterSpace::new(&self.parameters, &base_config)?;
+        let mut calibration = Calibration { settings: self, base_config, observed, space, fits: Vec::new() };
+
+        match self.method {
+            Method::Abc { samples } => calibration.sample(samples),
+            Method::NelderMead { max_iterations } => calibration.search(max_iterations),
+        }
+
+        let mut fits = calibration.fits.clone();
+        fits.sort_by(|a, b| a.error.partial_cmp(&b.error).unwrap());
+        fits.truncate(self.best_fits);
+        fs::create_dir_all(&self.output_dir)?;
+        self.write_best_fits(&fits)?;
+        for (rank, fit) in fits.iter().enumerate() {
+            write_epicurve(&Path::new(&self.output_dir).join(format!("fit_{}.csv", rank)), &calibration.observed, fit)?;
+        }
+        if let Some(best) = fits.first() {
+            println!("Best fit with error {}: {:?}", best.error, self.named(&best.values));
+        }
+        Ok(())
+    }
+
+    fn validate(&self, observed: &[ObservedDay]) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check(self.replicates >= 1, "replicates".to_string(), "should be at least 1");
+        check_parallelism(self.parallelism, &mut errors);
+        for (i, day) in observed.iter().enumerate() {
+            if day.day < 1 {
+                errors.add(format!("observed[{}].day", i), format!("should be at least 1, but is {}", day.day));
+            }
+        }
+        errors.into_result()
+    }
+
+    fn named(&self, values: &[f64]) -> Vec<(String, f64)> {
+        self.parameters.keys().cloned().zip(values.iter().cloned()).collect()
+    }
+
+    fn write_best_fits(&self, fits: &[Fit]) -> Result<(), Box<dyn Error>> {
+        let mut writer = Writer::from_path(Path::new(&self.output_dir).join("best_fits.csv"))?;
+        let mut header = vec!["rank".to_string()];
+        header.extend(self.parameters.keys().cloned());
+        header.push("error".to_string());
+        writer.write_record(&header)?;
+        for (rank, fit) in fits.iter().enumerate() {
+            let mut record = vec![rank.to_string()];
+            record.extend(fit.values.iter().map(|v| v.to_string()));
+            record.push(fit.error.to_string());
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+struct Calibration<'a> {
+    settings: &'a CalibrationConfig,
+    base_config: Config,
+    observed: Vec<ObservedDay>,
+    space: ParameterSpace,
+    fits: Vec<Fit>,
+}
+
+impl Calibration<'_> {
+    fn sample(&mut self, samples: usize) {
+        let mut rng = RandomWrapper::with_seed(Some(self.settings.seed));
+        let candidates: Vec<Vec<f64>> = (0..samples)
+            .map(|_| self.settings.parameters.values().map(|(lower, upper)| {
+                if lower < upper { rng.get().gen_range(*lower, *upper) } else { *lower }
+            }).collect())
+            .collect();
+        let fits = self.evaluate(&candidates);
+        self.fits.extend(fits.into_iter().flatten());
+    }
+
+    /// Searches the scaled parameter space, where a point that makes an invalid config counts as infinitely far off
+    fn search(&mut self, max_iterations: usize) {
+        let start = vec![0.5; self.space.dimensions()];
+        nelder_mead(start, 0.25, max_iterations, &mut |point| {
+            match self.evaluate(&[self.space.scale(point)]).remove(0) {
+                Some(fit) => {
+                    let error = fit.error;
+                    self.fits.push(fit);
+                    error
+                }
+                None => f64::INFINITY,
+            }
+        });
+    }
+
+    /// Runs the replicates of every candidate together, with the same seeds for each candidate so that they are
+    /// compared on the same random events. A candidate that makes an invalid config is reported and has no fit.
+    fn evaluate(&self, candidates: &[Vec<f64>]) -> Vec<Option<Fit>> {
+        let replicates = self.settings.replicates;
+        let configs: Vec<Option<Config>> = candidates.iter().map(|values| {
+            self.base_config.with_parameters(&self.space.to_parameters(values))
+                .map_err(|e| println!("Skipping the parameters {:?}: {}", self.settings.named(values), e))
+                .ok()
+        }).collect();
+        let runs: Vec<Run> = configs.iter().flatten()
+            .flat_map(|config| (0..replicates).map(move |replicate| {
+                let mut config = config.clone();
+                config.set_seed(self.settings.seed + replicate as u64);
+                Run { config, output_file: None }
+            }))
+            .collect();
+        let counts = run_all(&runs, self.settings.parallelism);
+        let mut counts_by_candidate = counts.chunks(replicates);
+        candidates.iter().zip(configs.iter())
+            .map(|(values, config)| config.as_ref().map(|_| self.fit(values.clone(), counts_by_candidate.next().unwrap())))
+            .collect()
+    }
+
+    fn fit(&self, values: Vec<f64>, runs: &[Vec<Counts>]) -> Fit {
+        let days: Vec<i32> = self.observed.iter().map(|o| o.day).collect();
+        let mean = |metric: fn(&[Counts], i32) -> f64| -> Vec<f64> {
+            days.iter()
+                .map(|day| runs.iter().map(|run| metric(run, *day)).sum::<f64>() / runs.len() as f64 * self.settings.scale)
+                .collect()
+        };
+        let cases = mean(new_cases);
+        let deaths = mean(new_deaths);
+        let error = error(&self.settings.objective, &self.observed, &cases, &deaths);
+        Fit { values, error, cases, deaths }
+    }
+}
+
+fn error(objective: &Objective, observed: &[ObservedDay], cases: &[f64], deaths: &[f64]) -> f64 {
+    let squared_error = |simulated: f64, observed: f64| (simulated - observed).powi(2);
+    observed.iter().enumerate().map(|(i, day)| {
+        let case_error = squared_error(cases[i], day.cases);
+        let death_error = day.deaths.map(|d| squared_error(deaths[i], d)).unwrap_or(0.0);
+        match objective {
+            Objective::Cases => case_error,
+            Objective::Deaths => death_error,
+            Objective::CasesAndDeaths => case_error + death_error,
+        }
+    }).sum()
+}
+
+/// The counts at the end of an hour; a run that ended early keeps its last counts. There are no counts from before the
+/// first hour, so the counts at the end of hour 1 stand in for the start of the run, at hour 0.
+fn counts_at(run: &[Counts], hour: i32) -> Option<&Counts> {
+    let index = (hour.max(1) - 1) as usize;
+    run.get(index).or_else(|| run.last())
+}
+
+/// The citizens infected during a day, starting at day 1. Only infection makes citizens leave the susceptible state.
+fn new_cases(run: &[Counts], day: i32) -> f64 {
+    let susceptible = |hour| counts_at(run, hour).map(|c| c.get_susceptible()).unwrap_or(0);
+    (susceptible((day - 1) * constants::NUMBER_OF_HOURS) - susceptible(day * constants::NUMBER_OF_HOURS)) as f64
+}
+
+fn new_deaths(run: &[Counts], day: i32) -> f64 {
+    let deceased = |hour| counts_at(run, hour).map(|c| c.get_deceased()).unwrap_or(0);
+    (deceased(day * constants::NUMBER_OF_HOURS) - deceased((day - 1) * constants::NUMBER_OF_HOURS)) as f64
+}
+
+fn write_epicurve(file: &Path, observed: &[ObservedDay], fit: &Fit) -> Result<(), Box<dyn Error>> {
+    let mut writer = Writer::from_path(file)?;
+    writer.write_record(["day", "observed_cases", "simulated_cases", "observed_deaths", "simulated_deaths"])?;
+    for (i, day) in observed.iter().enumerate() {
+        let observed_deaths = day.deaths.map(|d| d.to_string()).unwrap_or_default();
+        writer.write_record(&[day.day.to_string(), day.cases.to_string(), fit.cases[i].to_string(),
+            observed_deaths, fit.deaths[i].to_string()])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Minimises `f` with the Nelder-Mead simplex method, starting from a simplex around `start`
+fn nelder_mead(start: Vec<f64>, step: f64, max_iterations: usize, f: &mut dyn FnMut(&[f64]) -> f64) -> (Vec<f64>, f64) {
+    let dimensions = start.len();
+    let mut simplex: Vec<(Vec<f64>, f64)> = Vec::with_capacity(dimensions + 1);
+    simplex.push((start.clone(), f(&start)));
+    for i in 0..dimensions {
+        let mut point = start.clone();
+        point[i] += step;
+        let value = f(&point);
+        simplex.push((point, value));
+    }
+    let towards = |from: &[f64], to: &[f64], t: f64| -> Vec<f64> {
+        from.iter().zip(to.iter()).map(|(a, b)| a + t * (b - a)).collect()
+    };
+
+    for _ in 0..max_iterations {
+        simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let (best, worst) = (simplex[0].1, simplex[dimensions].1);
+        if (worst - best).abs() <= 1e-12 * best.abs().max(1e-12) {
+            break;
+        }
+        let centroid: Vec<f64> = (0..dimensions)
+            .map(|d| simplex[..dimensions].iter().map(|(p, _)| p[d]).sum::<f64>() / dimensions as f64)
+            .collect();
+        let worst_point = simplex[dimensions].0.clone();
+
+        let reflected = towards(&centroid, &worst_point, -1.0);
+        let reflected_value = f(&reflected);
+        if reflected_value < best {
+            let expanded = towards(&centroid, &worst_point, -2.0);
+            let expanded_value = f(&expanded);
+            simplex[dimensions] = if expanded_value < reflected_value {
+                (expanded, expanded_value)
+            } else {
+                (reflected, reflected_value)
+            };
+        } else if reflected_value < simplex[dimensions - 1].1 {
+            simplex[dimensions] = (reflected, reflected_value);
+        } else {
+            let contracted = towards(&centroid, &worst_point, 0.5);
+            let contracted_value = f(&contracted);
+            if contracted_value < worst {
+                simplex[dimensions] = (contracted, contracted_value);
+            } else {
+                let best_point = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    let shrunk = towards(&best_point, &vertex.0, 0.5);
+                    let value = f(&shrunk);
+                    *vertex = (shrunk, value);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    simplex.remove(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_with_susceptible(susceptible_by_hour: &[i32]) -> Vec<Counts> {
+        susceptible_by_hour.iter().enumerate().map(|(hour, susceptible)| {
+            let mut counts = Counts::new(*susceptible, 0);
+            for _ in 0..=hour {
+                counts.increment_hour();
+            }
+            counts
+        }).collect()
+    }
+
+    #[test]
+    fn should_find_minimum_with_nelder_mead() {
+        let mut f = |x: &[f64]| (x[0] - 0.3).powi(2) + 2.0 * (x[1] - 0.7).powi(2);
+
+        let (point, value) = nelder_mead(vec![0.5, 0.5], 0.25, 200, &mut f);
+
+        assert!((point[0] - 0.3).abs() < 1e-3, "{:?}", point);
+        assert!((point[1] - 0.7).abs() < 1e-3, "{:?}", point);
+        assert!(value < 1e-6);
+    }
+
+    fn calibration(parameters: &[(&str, (f64, f64))], method: Method, output_dir: &Path) -> CalibrationConfig {
+        CalibrationConfig {
+            config: "config/test/calibration_base.json".to_string(),
+            observed: "config/test/observed_cases.csv".to_string(),
+            parameters: parameters.iter().map(|(name, range)| (name.to_string(), *range)).collect(),
+            objective: Objective::Cases,
+            method,
+            replicates: 2,
+            seed: 1,
+            scale: 1.0,
+            best_fits: 2,
+            output_dir: output_dir.to_string_lossy().to_string(),
+            parallelism: Some(2),
+        }
+    }
+
+    #[test]
+    fn should_count_new_cases_per_day() {
+        let mut susceptible: Vec<i32> = vec![100; 24];
+        susceptible.extend(vec![90; 24]);
+        susceptible.extend(vec![85; 10]);
+        let run = run_with_susceptible(&susceptible);
+
+        assert_eq!(new_cases(&run, 1), 0.0);
+        assert_eq!(new_cases(&run, 2), 10.0);
+        assert_eq!(new_cases(&run, 3), 5.0);
+        assert_eq!(new_cases(&run, 4), 0.0);
+    }
+
+    #[test]
+    fn should_read_the_counts_of_the_first_hour_for_the_start_of_the_run() {
+        let run = run_with_susceptible(&[100, 99, 98]);
+
+        assert_eq!(counts_at(&run, 0).unwrap().get_hour(), 1);
+        assert_eq!(counts_at(&run, 1).unwrap().get_hour(), 1);
+        assert_eq!(counts_at(&run, 2).unwrap().get_hour(), 2);
+        assert_eq!(counts_at(&run, 5).unwrap().get_hour(), 3);
+    }
+
+    #[test]
+    fn should_measure_error_for_objective() {
+        let observed = vec![ObservedDay { day: 1, cases: 10.0, deaths: Some(1.0) },
+                            ObservedDay { day: 2, cases: 20.0, deaths: None }];
+        let (cases, deaths) = (vec![12.0, 17.0], vec![3.0, 5.0]);
+
+        assert_eq!(error(&Objective::Cases, &observed, &cases, &deaths), 13.0);
+        assert_eq!(error(&Objective::Deaths, &observed, &cases, &deaths), 4.0);
+        assert_eq!(error(&Objective::CasesAndDeaths, &observed, &cases, &deaths), 17.0);
+    }
+
+    #[test]
+    fn should_read_calibration_config_and_observed_data() {
+        let calibration = read("config/calibration.json").unwrap();
+
+        assert_eq!(calibration.parameters["disease.regular_transmission_rate"], (0.01, 0.05));
+        assert_eq!(calibration.method, Method::NelderMead { max_iterations: 30 });
+        assert_eq!(calibration.objective, Objective::CasesAndDeaths);
+        let observed = read_observed(&calibration.observed).unwrap();
+        assert_eq!(observed[0], ObservedDay { day: 1, cases: 1.0, deaths: Some(0.0) });
+    }
+
+    #[test]
+    fn should_calibrate_with_abc() {
+        let output_dir = std::env::temp_dir().join("epirust_calibration_test");
+        let _ = fs::remove_dir_all(&output_dir);
+        let parameters = [("disease.regular_transmission_rate", (0.01, 0.05)),
+                          ("population.Auto.number_of_agents", (90.0, 110.0))];
+        let calibration = calibration(&parameters, Method::Abc { samples: 3 }, &output_dir);
+
+        calibration.run().unwrap();
+
+        let best_fits = fs::read_to_string(output_dir.join("best_fits.csv")).unwrap();
+        let lines: Vec<&str> = best_fits.lines().collect();
+        assert_eq!(lines[0], "rank,disease.regular_transmission_rate,population.Auto.number_of_agents,error");
+        assert_eq!(lines.len(), 3);
+        let epicurve = fs::read_to_string(output_dir.join("fit_0.csv")).unwrap();
+        assert_eq!(epicurve.lines().count(), 4);
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn should_skip_parameters_that_make_an_invalid_config() {
+        let output_dir = std::env::temp_dir().join("epirust_calibration_invalid_test");
+        let _ = fs::remove_dir_all(&output_dir);
+        let parameters = [("disease.death_rate", (0.5, 1.5))];
+
+        for method in [Method::Abc { samples: 6 }, Method::NelderMead { max_iterations: 3 }] {
+            calibration(&parameters, method, &output_dir).run().unwrap();
+
+            let best_fits = fs::read_to_string(output_dir.join("best_fits.csv")).unwrap();
+            let death_rates: Vec<f64> = best_fits.lines().skip(1)
+                .map(|line| line.split(',').nth(1).unwrap().parse().unwrap())
+                .collect();
+            assert!(!death_rates.is_empty());
+            assert!(death_rates.iter().all(|rate| *rate <= 1.0), "{:?}", death_rates);
+        }
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn should_report_invalid_replicates_and_observed_days() {
+        let observed = std::env::temp_dir().join("epirust_calibration_observed_test.csv");
+        fs::write(&observed, "day,cases,deaths\n0,1,0\n1,2,0\n").unwrap();
+        let mut calibration = calibration(&[], Method::Abc { samples: 1 }, Path::new("out"));
+        calibration.observed = observed.to_string_lossy().to_string();
+        calibration.replicates = 0;
+
+        let errors = calibration.run().unwrap_err();
+
+        assert_eq!(errors.to_string(), "Invalid config:\n  \
+            replicates: should be at least 1\n  \
+            observed[0].day: should be at least 1, but is 0");
+        fs::remove_file(&observed).unwrap();
+    }
+}
@@ -17,15 +17,29 @@
  *
  */
 
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs::File;
+use std::path::Path;
 
+use serde_json::Value;
+
+use crate::age::AgeDistribution;
+use crate::contact_matrix::ContactMatricesConfig;
+use crate::contact_network::Transmission;
 use crate::disease::{Disease, DiseaseOverride};
+use crate::geography;
+use crate::geography::Capacity;
 use crate::interventions::{Intervention};
+use crate::listeners::outputs::Output;
+use crate::setting::TransmissionMultipliers;
+use crate::travel_plan::TravelPlan;
+use crate::validation::{field, ValidationErrors};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Config {
     population: Population,
+    #[serde(deserialize_with = "Disease::deserialize_inline_or_from_library")]
     disease: Disease,
     #[serde(default)]
     disease_overrides: Vec<DiseaseOverride>,
@@ -35,11 +49,25 @@ pub struct Config {
     output_file: Option<String>,
     #[serde(default)]
     enable_citizen_state_messages: bool,
+    /// Where the output goes. When it is not set, the CLI writes CSV files and the daemon publishes to Kafka.
+    #[serde(default)]
+    outputs: Option<Vec<Output>>,
+    #[serde(default)]
+    seed: Option<u64>,
+    /// File to draw the grid and the homes of the citizens in, as an SVG. Nothing is drawn when it is not set.
+    #[serde(default)]
+    grid_svg: Option<String>,
+    #[serde(default)]
+    transmission: Transmission,
+    #[serde(default)]
+    contact_matrices: ContactMatricesConfig,
+    #[serde(default)]
+    transmission_multipliers: TransmissionMultipliers,
 }
 
 impl Config {
     pub fn get_disease(&self) -> Disease {
-        self.disease
+        self.disease.clone()
     }
 
     pub fn get_population(&self) -> Population {
@@ -70,6 +98,52 @@ impl Config {
         self.enable_citizen_state_messages
     }
 
+    pub fn get_outputs(&self) -> Vec<Output> {
+        self.outputs.clone().unwrap_or_else(Output::defaults)
+    }
+
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn get_grid_svg(&self) -> Option<String> {
+        self.grid_svg.clone()
+    }
+
+    pub fn get_transmission(&self) -> Transmission {
+        self.transmission.clone()
+    }
+
+    pub fn get_contact_matrices(&self) -> &ContactMatricesConfig {
+        &self.contact_matrices
+    }
+
+    pub fn get_transmission_multipliers(&self) -> &TransmissionMultipliers {
+        &self.transmission_multipliers
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    pub fn set_disease(&mut self, disease: Disease) {
+        self.disease = disease;
+    }
+
+    /// Sets the outputs of a config that doesn't name any
+    pub fn set_default_outputs(&mut self, outputs: Vec<Output>) {
+        self.outputs.get_or_insert(outputs);
+    }
+
+    pub fn set_output_file(&mut self, output_file: String) {
+        self.output_file = Some(output_file);
+    }
+
+    /// The config without anything it would write to files: its `outputs`, `output_file` and `grid_svg`
+    pub fn without_file_outputs(self) -> Config {
+        Config { outputs: Some(Vec::new()), output_file: None, grid_svg: None, ..self }
+    }
+
     pub fn new(population: Population, disease: Disease, disease_overrides: Vec<DiseaseOverride>,
                grid: i32, hours: i32, interventions: Vec<Intervention>, output_file: Option<String>)
                -> Config {
@@ -82,7 +156,106 @@ impl Config {
             interventions,
             output_file,
             enable_citizen_state_messages: false,
+            outputs: None,
+            seed: None,
+            grid_svg: None,
+            transmission: Transmission::Grid,
+            contact_matrices: ContactMatricesConfig::default(),
+            transmission_multipliers: TransmissionMultipliers::default(),
+        }
+    }
+
+    /// A copy of the config with some of its fields changed. The fields are named by their path, as in
+    /// `disease.death_rate` or `interventions[0].Vaccinate.percent`.
+    pub fn with_parameters(&self, parameters: &[(String, Value)]) -> Result<Config, Box<dyn Error>> {
+        let mut config = serde_json::to_value(self)?;
+        for (path, parameter) in parameters {
+            let field = field_at(&mut config, path).ok_or_else(|| format!("{} is not a field of the config", path))?;
+            *field = parameter.clone();
+        }
+        let config: Config = serde_json::from_value(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The value of a field, named by its path as in `with_parameters`
+    pub fn get_parameter(&self, path: &str) -> Option<Value> {
+        let mut config = serde_json::to_value(self).ok()?;
+        field_at(&mut config, path).cloned()
+    }
+
+    /// Checks the config for values that would make the simulation fail or never finish, and reports all of them
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        self.validate_at("", &mut errors);
+        errors.into_result()
+    }
+
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+        errors.check(self.hours > 0, field(path, "hours"), &format!("should be positive, but is {}", self.hours));
+        self.disease.validate(&field(path, "disease"), errors);
+        for (i, disease_override) in self.disease_overrides.iter().enumerate() {
+            disease_override.validate(&format!("{}[{}]", field(path, "disease_overrides"), i), errors);
+        }
+        for (i, intervention) in self.interventions.iter().enumerate() {
+            intervention.validate(&format!("{}[{}]", field(path, "interventions"), i), errors);
+        }
+        self.transmission.validate(&field(path, "transmission"), errors);
+        self.validate_contact_matrices(path, errors);
+        self.transmission_multipliers.validate(&field(path, "transmission_multipliers"), errors);
+        for (i, output) in self.get_outputs().iter().enumerate() {
+            output.validate(&format!("{}[{}]", field(path, "outputs"), i), self.grid_size, errors);
+        }
+        self.validate_grid_svg(path, errors);
+        let capacity = self.validate_grid_size(path, errors);
+        self.population.validate(&field(path, "population"), capacity.as_ref(), errors);
+    }
+
+    fn validate_grid_svg(&self, path: &str, errors: &mut ValidationErrors) {
+        if let Some(file) = &self.grid_svg {
+            let file_path = Path::new(file);
+            if file_path.extension().is_none_or(|extension| extension != "svg") {
+                errors.add(field(path, "grid_svg"), format!("should be an .svg file, but is {}", file));
+            }
+            let directory = file_path.parent().filter(|directory| !directory.as_os_str().is_empty());
+            if let Some(directory) = directory.filter(|directory| !directory.is_dir()) {
+                errors.add(field(path, "grid_svg"),
+                           format!("should be in an existing directory, but there is no {}", directory.display()));
+            }
+        }
+    }
+
+    fn validate_contact_matrices(&self, path: &str, errors: &mut ValidationErrors) {
+        let matrices_field = field(path, "contact_matrices");
+        self.contact_matrices.validate(&matrices_field, errors);
+        if self.contact_matrices.is_empty() {
+            return;
+        }
+        if let Transmission::Network(_) = self.transmission {
+            errors.add(matrices_field.clone(), "can't be used with a contact network, whose layers already decide who \
+                                                meets whom".to_string());
+        }
+        if let Population::Auto(auto_pop) = &self.population {
+            errors.check(auto_pop.age_distribution.is_some(), field(&field(path, "population.Auto"), "age_distribution"),
+                         "is needed to give the citizens ages for the contact_matrices");
+        }
+    }
+
+    fn validate_grid_size(&self, path: &str, errors: &mut ValidationErrors) -> Option<Capacity> {
+        let is_big_enough = |capacity: &Capacity| capacity.houses > 0 && capacity.offices > 0;
+        if self.grid_size <= 0 {
+            errors.add(field(path, "grid_size"), format!("should be positive, but is {}", self.grid_size));
+            return None;
+        }
+        let capacity = geography::capacity(self.grid_size);
+        if !is_big_enough(&capacity) {
+            let minimum = (1..).find(|grid_size| is_big_enough(&geography::capacity(*grid_size))).unwrap();
+            errors.add(field(path, "grid_size"),
+                       format!("is too small to fit houses and offices; it should be at least {}, but is {}",
+                               minimum, self.grid_size));
+            return None;
         }
+        Some(capacity)
     }
 }
 
@@ -92,22 +265,121 @@ pub enum Population {
     Auto(AutoPopulation),
 }
 
+impl Population {
+    fn validate(&self, path: &str, capacity: Option<&Capacity>, errors: &mut ValidationErrors) {
+        match self {
+            Population::Csv(csv_pop) => {
+                errors.check(!csv_pop.file.is_empty(), field(&field(path, "Csv"), "file"), "should not be empty");
+            }
+            Population::Auto(auto_pop) => auto_pop.validate(&field(path, "Auto"), capacity, errors),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct CsvPopulation {
     pub file: String,
     pub cols: Vec<String>,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct AutoPopulation {
     pub number_of_agents: i32,
     pub public_transport_percentage: f64,
     pub working_percentage: f64,
+    /// The share of the citizens in each age group, such as `{"0-19": 0.3, "20-59": 0.55, "60+": 0.15}`. The citizens
+    /// don't have ages when it is left out.
+    #[serde(default)]
+    pub age_distribution: Option<BTreeMap<String, f64>>,
+}
+
+impl AutoPopulation {
+    fn validate(&self, path: &str, capacity: Option<&Capacity>, errors: &mut ValidationErrors) {
+        let agents_field = field(path, "number_of_agents");
+        errors.check_fraction(self.public_transport_percentage, field(path, "public_transport_percentage"));
+        errors.check_fraction(self.working_percentage, field(path, "working_percentage"));
+        if let Some(Err(e)) = self.age_distribution.as_ref().map(AgeDistribution::new) {
+            errors.add(field(path, "age_distribution"), e);
+        }
+        if self.number_of_agents <= 0 {
+            errors.add(agents_field, format!("should be positive, but is {}", self.number_of_agents));
+            return;
+        }
+        if let Some(capacity) = capacity {
+            if self.number_of_agents as i64 > capacity.home_locations {
+                errors.add(agents_field, format!("is more than the {} homes the grid can hold; increase grid_size",
+                                                 capacity.home_locations));
+                return;
+            }
+            let transport_locations = geography::transport_locations_needed(
+                self.number_of_agents, self.public_transport_percentage, self.working_percentage);
+            if transport_locations > capacity.transport_locations {
+                errors.add(agents_field, format!("needs {} public transport locations, but the grid has {}; \
+                                                  increase grid_size", transport_locations, capacity.transport_locations));
+            }
+        }
+    }
+}
+
+/// Several regions, each with its own population, simulated together with travel between them
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct MultiRegionConfig {
+    pub regions: Vec<RegionConfig>,
+    pub travel_plan: TravelPlan,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct RegionConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub config: Config,
+}
+
+impl MultiRegionConfig {
+    pub fn get_hours(&self) -> i32 {
+        self.regions.first().map(|r| r.config.get_hours()).unwrap_or(0)
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check(!self.regions.is_empty(), "regions".to_string(), "should have at least one region");
+        for (i, region) in self.regions.iter().enumerate() {
+            let path = format!("regions[{}]", i);
+            errors.check(region.config.get_hours() == self.get_hours(), field(&path, "hours"),
+                         "should be the same for all regions");
+            region.config.validate_at(&path, &mut errors);
+        }
+        let names: Vec<String> = self.regions.iter().map(|r| r.name.clone()).collect();
+        if let Err(e) = self.travel_plan.check(&names) {
+            errors.add("travel_plan".to_string(), e);
+        }
+        errors.into_result()
+    }
+}
+
+fn field_at<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(value, |value, name| {
+        match name.find('[') {
+            None => value.get_mut(name),
+            Some(i) => {
+                let index: usize = name[i + 1..].strip_suffix(']')?.parse().ok()?;
+                value.get_mut(&name[..i])?.get_mut(index)
+            }
+        }
+    })
 }
 
 pub fn read(filename: String) -> Result<Config, Box<dyn Error>> {
     let reader = File::open(filename)?;
     let v: Config = serde_json::from_reader(reader)?;
+    v.validate()?;
+    Ok(v)
+}
+
+pub fn read_multi_region(filename: String) -> Result<MultiRegionConfig, Box<dyn Error>> {
+    let reader = File::open(filename)?;
+    let v: MultiRegionConfig = serde_json::from_reader(reader)?;
+    v.validate()?;
     Ok(v)
 }
 
@@ -140,7 +412,13 @@ mod tests {
             hours: 10000,
             interventions: vec![Intervention::Vaccinate(vaccinate)],
             output_file: None,
-            enable_citizen_state_messages: false
+            enable_citizen_state_messages: false,
+            outputs: None,
+            seed: None,
+            grid_svg: None,
+            transmission: Transmission::Grid,
+            contact_matrices: ContactMatricesConfig::default(),
+            transmission_multipliers: TransmissionMultipliers::default(),
         };
 
         assert_eq!(expected_config, read_config);
@@ -156,6 +434,7 @@ mod tests {
             number_of_agents: 10000,
             public_transport_percentage: 0.2,
             working_percentage: 0.7,
+            age_distribution: None,
         });
 
         let expected_config = Config {
@@ -166,9 +445,139 @@ mod tests {
             hours: 10000,
             interventions: vec![Intervention::Vaccinate(vaccinate)],
             output_file: Some("simulation_default_config".to_string()),
-            enable_citizen_state_messages: false
+            enable_citizen_state_messages: false,
+            outputs: None,
+            seed: None,
+            grid_svg: None,
+            transmission: Transmission::Grid,
+            contact_matrices: ContactMatricesConfig::default(),
+            transmission_multipliers: TransmissionMultipliers::default(),
         };
 
         assert_eq!(expected_config, read_config);
     }
+
+    #[test]
+    fn should_read_config_with_disease_from_library() {
+        let read_config = read(String::from("experiments/config/10000_agents.json")).unwrap();
+
+        assert_eq!(read_config.get_disease(), Disease::init("config/diseases.yaml", &String::from("covid_19")));
+        assert_eq!(read_config.get_grid_size(), 250);
+    }
+
+    #[test]
+    fn should_read_multi_region_config() {
+        let read_config = read_multi_region(String::from("config/test/multi_region.json")).unwrap();
+
+        assert_eq!(read_config.regions.len(), 2);
+        assert_eq!(read_config.regions[0].name, "pune");
+        assert_eq!(read_config.regions[1].name, "mumbai");
+        assert_eq!(read_config.regions[1].config.get_grid_size(), 100);
+        assert_eq!(read_config.get_hours(), 1000);
+        assert_eq!(read_config.travel_plan.get_travel_fraction("pune", "mumbai"), 0.01);
+    }
+
+    #[test]
+    fn should_report_all_problems_with_config() {
+        let population = Population::Auto(AutoPopulation {
+            number_of_agents: 10000,
+            public_transport_percentage: 1.2,
+            working_percentage: 0.7,
+            age_distribution: None,
+        });
+        let interventions = vec![Intervention::Vaccinate(Vaccinate::new(5000, 0.2)),
+                                 Intervention::Vaccinate(Vaccinate::new(-1, 0.2))];
+        let config = Config::new(population, Disease::new(5, 20, 10, 0.025, 0.25, 0.035), vec![], 100, 0,
+                                 interventions, None);
+
+        let errors = config.validate().unwrap_err();
+
+        let fields: Vec<&str> = errors.get_errors().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["hours", "disease.last_day", "interventions[1].Vaccinate.at_hour",
+                                "population.Auto.public_transport_percentage", "population.Auto.number_of_agents"]);
+    }
+
+    #[test]
+    fn should_report_grid_too_small_for_layout() {
+        let population = Population::Auto(AutoPopulation {
+            number_of_agents: 10,
+            public_transport_percentage: 0.2,
+            working_percentage: 0.7,
+            age_distribution: None,
+        });
+        let config = Config::new(population, Disease::new(5, 20, 40, 0.025, 0.25, 0.035), vec![], 20, 100,
+                                 vec![], None);
+
+        let errors = config.validate().unwrap_err();
+
+        assert_eq!(errors.to_string(),
+                   "Invalid config:\n  grid_size: is too small to fit houses and offices; it should be at least 46, but is 20");
+    }
+
+    #[test]
+    fn should_report_contact_matrices_without_ages_or_with_network() {
+        let config = read(String::from("config/default.json")).unwrap();
+        let matrices = serde_json::json!({"home": "config/test/contact_matrix_home.csv", "other": ""});
+
+        let without_ages = config.with_parameters(&[("contact_matrices".to_string(), matrices.clone())]).unwrap_err();
+        assert_eq!(without_ages.to_string(), "Invalid config:\n  contact_matrices.other: should not be empty\n  \
+                                              population.Auto.age_distribution: is needed to give the citizens ages for \
+                                              the contact_matrices");
+
+        let with_network = config.with_parameters(&[
+            ("contact_matrices".to_string(), serde_json::json!({"home": "config/test/contact_matrix_home.csv"})),
+            ("population.Auto.age_distribution".to_string(), serde_json::json!({"0-4": 1.0, "old": 2.0})),
+            ("transmission".to_string(), serde_json::json!({"Network": {}})),
+        ]).unwrap_err();
+        let message = with_network.to_string();
+        assert!(message.contains("\n  contact_matrices: can't be used with a contact network"), "{}", message);
+        assert!(message.contains("\n  population.Auto.age_distribution: old is not an age group"), "{}", message);
+    }
+
+    #[test]
+    fn should_report_grid_svg_that_cannot_be_written() {
+        let config = read(String::from("config/default.json")).unwrap();
+        let with_grid_svg = |file: &str| config.with_parameters(&[("grid_svg".to_string(), Value::from(file))]);
+
+        assert!(with_grid_svg("grid.svg").is_ok());
+        assert_eq!(with_grid_svg("config/default.json").unwrap_err().to_string(),
+                   "Invalid config:\n  grid_svg: should be an .svg file, but is config/default.json");
+        assert_eq!(with_grid_svg("no_such_directory/grid.svg").unwrap_err().to_string(),
+                   "Invalid config:\n  grid_svg: should be in an existing directory, but there is no no_such_directory");
+    }
+
+    #[test]
+    fn should_change_parameters_by_path() {
+        let config = read(String::from("config/default.json")).unwrap();
+
+        let parameters = vec![("disease.death_rate".to_string(), Value::from(0.1)),
+                              ("interventions[0].Vaccinate.percent".to_string(), Value::from(0.5)),
+                              ("grid_size".to_string(), Value::from(300))];
+        let changed = config.with_parameters(&parameters).unwrap();
+
+        assert_eq!(changed.get_disease(), Disease::new(5, 20, 40, 0.025, 0.25, 0.1));
+        assert_eq!(changed.get_interventions()[0], Intervention::Vaccinate(Vaccinate::new(5000, 0.5)));
+        assert_eq!(changed.get_grid_size(), 300);
+        assert_eq!(changed.get_hours(), config.get_hours());
+        assert_eq!(changed.get_parameter("disease.death_rate"), Some(Value::from(0.1)));
+        assert_eq!(changed.get_parameter("population.Csv"), None);
+    }
+
+    #[test]
+    fn should_not_change_unknown_or_invalid_parameters() {
+        let config = read(String::from("config/default.json")).unwrap();
+
+        let unknown = config.with_parameters(&[("disease.fatality".to_string(), Value::from(0.1))]);
+        assert_eq!(unknown.unwrap_err().to_string(), "disease.fatality is not a field of the config");
+        let out_of_range = config.with_parameters(&[("interventions[5].Vaccinate.percent".to_string(), Value::from(0.1))]);
+        assert!(out_of_range.is_err());
+        let invalid = config.with_parameters(&[("disease.death_rate".to_string(), Value::from(2.0))]);
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn should_accept_valid_configs() {
+        assert_eq!(read(String::from("config/default.json")).unwrap().validate(), Ok(()));
+        assert!(read_multi_region(String::from("config/multi_region.json")).is_ok());
+    }
 }
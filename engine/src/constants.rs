@@ -37,3 +37,8 @@ pub const IMMUNITY_RANGE: [i32; 5] = [-2, -1, 0, 1, 2];
 pub const BUILDING:&str = &"building";
 pub const HOME_SIZE: i32 = 2;
 pub const OFFICE_SIZE: i32 = 10;
+
+pub const MAX_ATTEMPTS_TO_FIND_VACANT_CELL: i32 = 100;
+
+/// Hours of output written between flushes of an output file
+pub const DEFAULT_FLUSH_INTERVAL: usize = 1;
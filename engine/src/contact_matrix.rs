@@ -0,0 +1,237 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::error::Error;
+
+use crate::age::AgeGroup;
+use crate::contact_network::Layer;
+use crate::validation::{field, ValidationErrors};
+
+/// CSV files with the age-by-age contact matrix of each setting, such as the ones published for the POLYMOD survey.
+/// A setting without a file has the same rate of transmission between all ages.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct ContactMatricesConfig {
+    #[serde(default)]
+    pub home: Option<String>,
+    #[serde(default)]
+    pub work: Option<String>,
+    #[serde(default)]
+    pub school: Option<String>,
+    #[serde(default)]
+    pub other: Option<String>,
+}
+
+impl ContactMatricesConfig {
+    pub fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        for layer in Layer::ALL.iter() {
+            if let Some(file) = self.file(*layer) {
+                errors.check(!file.is_empty(), field(path, ContactMatricesConfig::setting(*layer)), "should not be empty");
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        Layer::ALL.iter().all(|layer| self.file(*layer).is_none())
+    }
+
+    fn file(&self, layer: Layer) -> Option<&String> {
+        match layer {
+            Layer::Household => self.home.as_ref(),
+            Layer::Workplace => self.work.as_ref(),
+            Layer::School => self.school.as_ref(),
+            Layer::Community => self.other.as_ref(),
+        }
+    }
+
+    fn setting(layer: Layer) -> &'static str {
+        match layer {
+            Layer::Household => "home",
+            Layer::Workplace => "work",
+            Layer::School => "school",
+            Layer::Community => "other",
+        }
+    }
+}
+
+/// The contacts between age groups in one setting. Row `i` has the contacts of a citizen in age group `i` with citizens
+/// of each of the groups, and weighs how likely the citizen is to get the infection from them.
+///
+/// The weights are scaled to average 1 over the matrix, so that they shift transmission between ages without changing
+/// its overall rate in the setting.
+#[derive(Debug, PartialEq)]
+pub struct ContactMatrix {
+    groups: usize,
+    group_of_age: Vec<usize>,
+    weights: Vec<f64>,
+}
+
+impl ContactMatrix {
+    /// Reads a matrix from a CSV file with the age groups in its header, such as `0-4,5-9,...,75+`, followed by a row of
+    /// contacts for each of them
+    pub fn read(file: &str) -> Result<ContactMatrix, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(file)?;
+        let groups = reader.headers()?.iter().map(AgeGroup::parse).collect::<Result<Vec<_>, _>>()?;
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let row = record?.iter().map(|value| value.trim().parse::<f64>()).collect::<Result<Vec<_>, _>>()?;
+            rows.push(row);
+        }
+        let matrix = ContactMatrix::new(&groups, &rows).map_err(|e| format!("{}: {}", file, e))?;
+        Ok(matrix)
+    }
+
+    pub fn new(groups: &[AgeGroup], rows: &[Vec<f64>]) -> Result<ContactMatrix, String> {
+        if groups.windows(2).any(|pair| pair[0].from >= pair[1].from) {
+            return Err("the age groups should be in increasing order of age".to_string());
+        }
+        match groups.first() {
+            None => return Err("there should be at least one age group".to_string()),
+            Some(first) if first.from != 0 => {
+                return Err(format!("the first age group should start at 0, but starts at {}", first.from));
+            }
+            Some(_) => {}
+        }
+        if rows.len() != groups.len() || rows.iter().any(|row| row.len() != groups.len()) {
+            return Err(format!("there should be a row of {} contacts for each of the {} age groups", groups.len(),
+                               groups.len()));
+        }
+        let contacts: Vec<f64> = rows.iter().flatten().cloned().collect();
+        if contacts.iter().any(|c| !(c.is_finite() && *c >= 0.0)) {
+            return Err("the contacts should be finite and not negative".to_string());
+        }
+        let mean = contacts.iter().sum::<f64>() / contacts.len() as f64;
+        if mean <= 0.0 {
+            return Err("there should be some contacts between the age groups".to_string());
+        }
+
+        let group_of_age = (0..=u8::MAX)
+            .map(|age| groups.iter().rposition(|group| group.from <= age).expect("The first age group starts at 0"))
+            .collect();
+        Ok(ContactMatrix { groups: groups.len(), group_of_age, weights: contacts.iter().map(|c| c / mean).collect() })
+    }
+
+    /// How much more likely than average a citizen of the age is to get the infection from a contact of the other age
+    pub fn weight(&self, age: u8, contact_age: u8) -> f64 {
+        self.weights[self.group_of_age[age as usize] * self.groups + self.group_of_age[contact_age as usize]]
+    }
+}
+
+/// The contact matrix of the setting of each layer, for those that have one
+#[derive(Default)]
+pub struct ContactMatrices {
+    matrices: Vec<Option<ContactMatrix>>,
+}
+
+impl ContactMatrices {
+    pub fn read(config: &ContactMatricesConfig) -> Result<ContactMatrices, Box<dyn Error>> {
+        let matrices = Layer::ALL.iter()
+            .map(|layer| config.file(*layer).map(|file| ContactMatrix::read(file)).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ContactMatrices { matrices })
+    }
+
+    pub fn for_layer(&self, layer: Layer) -> Option<&ContactMatrix> {
+        self.matrices.get(layer as usize).and_then(|matrix| matrix.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{AutoPopulation, Config, Population};
+    use crate::disease::Disease;
+    use crate::simulation::Simulation;
+
+    use super::*;
+
+    fn groups(labels: &[&str]) -> Vec<AgeGroup> {
+        labels.iter().map(|label| AgeGroup::parse(label).unwrap()).collect()
+    }
+
+    #[test]
+    fn should_weigh_contacts_by_age_group() {
+        let matrix = ContactMatrix::new(&groups(&["0-19", "20-59", "60+"]),
+                                        &[vec![6.0, 2.0, 1.0], vec![2.0, 4.0, 1.0], vec![1.0, 1.0, 0.0]]).unwrap();
+
+        assert_eq!(matrix.weight(5, 19), 3.0);
+        assert_eq!(matrix.weight(30, 4), 1.0);
+        assert_eq!(matrix.weight(85, 61), 0.0);
+        assert_eq!(matrix.weight(255, 20), 0.5);
+    }
+
+    #[test]
+    fn should_not_accept_ages_below_the_first_group() {
+        let error = ContactMatrix::new(&groups(&["5-9", "10+"]), &[vec![1.0, 3.0], vec![3.0, 1.0]]).unwrap_err();
+        assert_eq!(error, "the first age group should start at 0, but starts at 5");
+    }
+
+    #[test]
+    fn should_not_accept_malformed_matrices() {
+        assert!(ContactMatrix::new(&groups(&["20+", "0-19"]), &[vec![1.0, 1.0], vec![1.0, 1.0]]).is_err());
+        assert!(ContactMatrix::new(&groups(&["0-19", "20+"]), &[vec![1.0, 1.0]]).is_err());
+        assert!(ContactMatrix::new(&[], &[]).is_err());
+        assert!(ContactMatrix::new(&groups(&["0-19", "20+"]), &[vec![1.0, -1.0], vec![1.0, 1.0]]).is_err());
+        assert!(ContactMatrix::new(&groups(&["0-19", "20+"]), &[vec![0.0, 0.0], vec![0.0, 0.0]]).is_err());
+        let error = ContactMatrix::new(&groups(&["0-19", "20+"]), &[vec![1.0, f64::NAN], vec![1.0, 1.0]]).unwrap_err();
+        assert_eq!(error, "the contacts should be finite and not negative");
+        assert!(ContactMatrix::new(&groups(&["0-19", "20+"]), &[vec![1.0, f64::INFINITY], vec![1.0, 1.0]]).is_err());
+    }
+
+    fn susceptible_at_end(matrix: &str) -> i32 {
+        let file = std::env::temp_dir().join(format!("epirust_contact_matrix_{}.csv", matrix.len()));
+        std::fs::write(&file, matrix).unwrap();
+        let file = file.to_str().unwrap().to_string();
+        let matrices = ContactMatricesConfig { home: Some(file.clone()), work: Some(file.clone()),
+            school: Some(file.clone()), other: Some(file.clone()) };
+        let population = Population::Auto(AutoPopulation {
+            number_of_agents: 1000,
+            public_transport_percentage: 0.2,
+            working_percentage: 0.5,
+            age_distribution: Some(vec![("20-29".to_string(), 1.0)].into_iter().collect()),
+        });
+        let mut config = Config::new(population, Disease::new(0, 50, 60, 0.99, 1.0, 0.0), vec![], 60, 120, vec![], None);
+        config.set_seed(3);
+        let config = config.with_parameters(&[("contact_matrices".to_string(), serde_json::to_value(matrices).unwrap())])
+            .unwrap();
+
+        let mut simulation = Simulation::builder(config).build().unwrap();
+        while !simulation.is_finished() {
+            simulation.step();
+        }
+        std::fs::remove_file(file).unwrap();
+        simulation.get_counts().get_susceptible()
+    }
+
+    #[test]
+    fn should_not_spread_between_ages_without_contacts() {
+        assert_eq!(susceptible_at_end("0-19,20+\n1.0,0.0\n0.0,0.0\n"), 999);
+        assert!(susceptible_at_end("0-19,20+\n0.0,0.0\n0.0,1.0\n") < 999);
+    }
+
+    #[test]
+    fn should_read_matrices_of_settings_from_csv() {
+        let config = ContactMatricesConfig { home: Some("config/test/contact_matrix_home.csv".to_string()),
+            ..ContactMatricesConfig::default() };
+
+        let matrices = ContactMatrices::read(&config).unwrap();
+
+        let home = matrices.for_layer(Layer::Household).unwrap();
+        assert!(home.weight(2, 3) > home.weight(2, 40));
+        assert!(matrices.for_layer(Layer::Workplace).is_none());
+    }
+}
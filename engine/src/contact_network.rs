@@ -0,0 +1,623 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use fxhash::FxHashMap;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use rand::seq::{index, SliceRandom};
+
+use crate::agent::Citizen;
+use crate::allocation_map::AgentLocationMap;
+use crate::constants;
+use crate::contact_matrix::{ContactMatrices, ContactMatrix};
+use crate::disease::Disease;
+use crate::geography::{Area, Grid, Point};
+use crate::random_wrapper::RandomWrapper;
+use crate::setting::{Setting, TransmissionMultipliers};
+use crate::validation::{field, ValidationErrors};
+
+/// Poisson degrees are drawn for at most this much of the mean at a time, as `exp(-mean)` underflows for large means
+const POISSON_MEAN_CHUNK: f64 = 500.0;
+
+/// How the infection spreads between citizens, selected with `transmission` in the config
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub enum Transmission {
+    /// To the citizens in the neighbouring cells of the grid
+    #[default]
+    Grid,
+    /// To the contacts of a citizen in the layer of the contact network that is active in the hour
+    Network(Box<ContactNetworkConfig>),
+}
+
+impl Transmission {
+    pub fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        if let Transmission::Network(network) = self {
+            network.validate(&field(path, "Network"), errors);
+        }
+    }
+}
+
+/// The degree distributions of the layers of the contact network. A layer that is left out has no contacts.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct ContactNetworkConfig {
+    #[serde(default)]
+    pub household: Option<DegreeDistribution>,
+    #[serde(default)]
+    pub workplace: Option<DegreeDistribution>,
+    #[serde(default)]
+    pub school: Option<DegreeDistribution>,
+    #[serde(default)]
+    pub community: Option<DegreeDistribution>,
+}
+
+impl ContactNetworkConfig {
+    fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        for layer in Layer::ALL.iter() {
+            if let Some(distribution) = self.distribution(*layer) {
+                distribution.validate(&field(path, layer.name()), errors);
+            }
+        }
+    }
+
+    fn distribution(&self, layer: Layer) -> Option<&DegreeDistribution> {
+        match layer {
+            Layer::Household => self.household.as_ref(),
+            Layer::Workplace => self.workplace.as_ref(),
+            Layer::School => self.school.as_ref(),
+            Layer::Community => self.community.as_ref(),
+        }
+    }
+}
+
+/// The number of contacts a citizen has in a layer. Citizens get at most as many contacts as there are other citizens
+/// in their group, and a few fewer when the pairing of contacts leaves some unmatched.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub enum DegreeDistribution {
+    Fixed(u32),
+    Poisson { mean: f64 },
+    /// `weights[k]` is the share of citizens with `k` contacts, such as the answers to a contact survey
+    Empirical { weights: Vec<f64> },
+}
+
+impl DegreeDistribution {
+    fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        match self {
+            DegreeDistribution::Fixed(_) => {}
+            DegreeDistribution::Poisson { mean } => {
+                if !(mean.is_finite() && *mean >= 0.0) {
+                    errors.add(field(&field(path, "Poisson"), "mean"),
+                               format!("should be a number that is not negative, but is {}", mean));
+                }
+            }
+            DegreeDistribution::Empirical { weights } => {
+                let weights_field = field(&field(path, "Empirical"), "weights");
+                if weights.iter().any(|w| !(w.is_finite() && *w >= 0.0)) {
+                    errors.add(weights_field, "should not be negative".to_string());
+                } else if !weights.iter().any(|w| *w > 0.0) {
+                    errors.add(weights_field, "should have at least one weight above 0".to_string());
+                }
+            }
+        }
+    }
+
+    fn sampler(&self) -> DegreeSampler {
+        match self {
+            DegreeDistribution::Fixed(degree) => DegreeSampler::Fixed(*degree as usize),
+            DegreeDistribution::Poisson { mean } => DegreeSampler::Poisson(*mean),
+            DegreeDistribution::Empirical { weights } => {
+                DegreeSampler::Empirical(WeightedIndex::new(weights).expect("Invalid weights of empirical degrees"))
+            }
+        }
+    }
+}
+
+enum DegreeSampler {
+    Fixed(usize),
+    Poisson(f64),
+    Empirical(WeightedIndex<f64>),
+}
+
+impl DegreeSampler {
+    fn sample(&self, rng: &mut RandomWrapper) -> usize {
+        match self {
+            DegreeSampler::Fixed(degree) => *degree,
+            DegreeSampler::Poisson(mean) => poisson(*mean, rng),
+            DegreeSampler::Empirical(weights) => weights.sample(rng.get()),
+        }
+    }
+}
+
+/// Knuth's method, for a chunk of the mean at a time; the sum of Poisson draws is a Poisson draw with the summed mean
+fn poisson(mean: f64, rng: &mut RandomWrapper) -> usize {
+    let mut remaining = mean;
+    let mut count = 0;
+    while remaining > 0.0 {
+        let chunk = remaining.min(POISSON_MEAN_CHUNK);
+        remaining -= chunk;
+        let limit = (-chunk).exp();
+        let mut product: f64 = rng.get().gen();
+        while product > limit {
+            count += 1;
+            product *= rng.get().gen::<f64>();
+        }
+    }
+    count
+}
+
+/// The settings citizens meet their contacts in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Layer {
+    /// The citizens living in the same house
+    Household,
+    /// The working citizens with the same office
+    Workplace,
+    /// The citizens who don't work, who are in school while the others are at work
+    School,
+    /// Anyone in the region, met while travelling
+    Community,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 4] = [Layer::Household, Layer::Workplace, Layer::School, Layer::Community];
+
+    /// The layer a citizen meets their contacts in at an hour of the day: the community while travelling, their
+    /// workplace or school during the day, and their household otherwise
+    pub fn active_at(simulation_hour: i32, working: bool) -> Layer {
+        match simulation_hour % constants::NUMBER_OF_HOURS {
+            constants::ROUTINE_TRAVEL_START_TIME | constants::ROUTINE_TRAVEL_END_TIME => Layer::Community,
+            constants::ROUTINE_WORK_TIME..=constants::ROUTINE_WORK_END_TIME if working => Layer::Workplace,
+            constants::ROUTINE_WORK_TIME..=constants::ROUTINE_WORK_END_TIME => Layer::School,
+            _ => Layer::Household,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layer::Household => "household",
+            Layer::Workplace => "workplace",
+            Layer::School => "school",
+            Layer::Community => "community",
+        }
+    }
+
+    /// The setting of the layer, with the community layer of the travel hours counting as transport
+    pub fn setting(&self) -> Setting {
+        match self {
+            Layer::Household => Setting::Home,
+            Layer::Workplace => Setting::Work,
+            Layer::School => Setting::School,
+            Layer::Community => Setting::Transport,
+        }
+    }
+}
+
+/// Who a susceptible citizen can get the infection from in an hour, and the setting they meet them in
+pub struct Exposure<'a> {
+    pub setting: Setting,
+    /// Scales the rate of transmission in the setting
+    pub multiplier: f64,
+    pub source: Source<'a>,
+}
+
+pub enum Source<'a> {
+    /// The citizens in the neighbouring cells of the area the citizen is in, weighted by their ages if the setting has
+    /// a contact matrix
+    Grid(&'a AgentLocationMap, Area, Option<&'a ContactMatrix>),
+    /// The contacts of the citizen in one layer of the network
+    Network(&'a ContactNetwork, Layer),
+}
+
+/// What the infection spreads over in a region: the contact network if it has one, and the grid otherwise
+#[derive(Clone, Copy)]
+pub struct Mixing<'a> {
+    network: Option<&'a ContactNetwork>,
+    matrices: &'a ContactMatrices,
+    multipliers: &'a TransmissionMultipliers,
+}
+
+impl<'a> Mixing<'a> {
+    pub fn new(network: Option<&'a ContactNetwork>, matrices: &'a ContactMatrices,
+               multipliers: &'a TransmissionMultipliers) -> Mixing<'a> {
+        Mixing { network, matrices, multipliers }
+    }
+
+    /// The exposure of a citizen in an area of the grid, during an hour when the layer is active
+    pub fn exposure(&self, map: &'a AgentLocationMap, grid: &Grid, area: Area, layer: Layer) -> Exposure<'a> {
+        let (setting, source) = match self.network {
+            Some(network) => (layer.setting(), Source::Network(network, layer)),
+            None => (grid.setting_of(&area), Source::Grid(map, area, self.matrices.for_layer(layer))),
+        };
+        Exposure { setting, multiplier: self.multipliers.multiplier(setting), source }
+    }
+}
+
+/// The contacts of every node in one layer, with the contacts of a node stored together. The contacts made after the
+/// layer was generated, with citizens who travelled into the region, are kept aside.
+#[derive(Default)]
+struct Contacts {
+    offsets: Vec<usize>,
+    contacts: Vec<u32>,
+    joined: FxHashMap<u32, Vec<u32>>,
+}
+
+impl Contacts {
+    /// Builds the contacts from the pairs of nodes in touch, leaving out repeated pairs
+    fn from_pairs(number_of_nodes: usize, pairs: &[(u32, u32)]) -> Contacts {
+        let mut offsets = vec![0; number_of_nodes + 1];
+        for (a, b) in pairs {
+            offsets[*a as usize + 1] += 1;
+            offsets[*b as usize + 1] += 1;
+        }
+        for i in 0..number_of_nodes {
+            offsets[i + 1] += offsets[i];
+        }
+        let mut next = offsets.clone();
+        let mut contacts = vec![0; offsets[number_of_nodes]];
+        for (a, b) in pairs {
+            contacts[next[*a as usize]] = *b;
+            next[*a as usize] += 1;
+            contacts[next[*b as usize]] = *a;
+            next[*b as usize] += 1;
+        }
+
+        let mut distinct = Contacts {
+            offsets: Vec::with_capacity(number_of_nodes + 1),
+            contacts: Vec::with_capacity(contacts.len()),
+            joined: FxHashMap::default(),
+        };
+        distinct.offsets.push(0);
+        for node in 0..number_of_nodes {
+            let node_contacts = &mut contacts[offsets[node]..offsets[node + 1]];
+            node_contacts.sort_unstable();
+            let mut previous = None;
+            for contact in node_contacts.iter() {
+                if previous != Some(*contact) {
+                    distinct.contacts.push(*contact);
+                    previous = Some(*contact);
+                }
+            }
+            distinct.offsets.push(distinct.contacts.len());
+        }
+        distinct
+    }
+
+    fn of(&self, node: u32) -> impl Iterator<Item=&u32> {
+        let generated = match self.offsets.get(node as usize + 1) {
+            Some(end) => &self.contacts[self.offsets[node as usize]..*end],
+            None => &[],
+        };
+        generated.iter().chain(self.joined.get(&node).into_iter().flatten())
+    }
+
+    fn add(&mut self, a: u32, b: u32) {
+        self.joined.entry(a).or_default().push(b);
+        self.joined.entry(b).or_default().push(a);
+    }
+}
+
+/// The contacts of the citizens of a region, in a layer for each setting they meet in. The layers are random graphs
+/// with the configured degrees, generated once, and the infection spreads along them instead of across the grid.
+pub struct ContactNetwork {
+    layers: Vec<Contacts>,
+    /// The transmission rate of each node in the current hour, or 0 for nodes that can't infect anyone
+    transmission_rates: Vec<f64>,
+    /// The degrees of each layer, kept to give contacts to the citizens who travel into the region
+    samplers: Vec<Option<DegreeSampler>>,
+    /// The nodes living in each house, by the corner of the house
+    households: FxHashMap<Point, Vec<u32>>,
+}
+
+impl ContactNetwork {
+    /// Generates the layers for the citizens, who become the nodes of the network in the order they are given
+    pub fn generate(config: &ContactNetworkConfig, citizens: &mut [Citizen], rng: &mut RandomWrapper) -> ContactNetwork {
+        for (node, citizen) in citizens.iter_mut().enumerate() {
+            citizen.contact_node = Some(node as u32);
+        }
+        let layers = Layer::ALL.iter()
+            .map(|layer| match config.distribution(*layer) {
+                Some(distribution) => {
+                    let pairs = ContactNetwork::pair_contacts(&ContactNetwork::groups(*layer, citizens),
+                                                              &distribution.sampler(), rng);
+                    Contacts::from_pairs(citizens.len(), &pairs)
+                }
+                None => Contacts::default(),
+            })
+            .collect();
+        let samplers = Layer::ALL.iter().map(|layer| config.distribution(*layer).map(DegreeDistribution::sampler)).collect();
+        let mut households: FxHashMap<Point, Vec<u32>> = FxHashMap::default();
+        for (node, citizen) in citizens.iter().enumerate() {
+            households.entry(citizen.home_location.start_offset).or_default().push(node as u32);
+        }
+        ContactNetwork { layers, transmission_rates: vec![0.0; citizens.len()], samplers, households }
+    }
+
+    /// Makes a citizen who travelled into the region a new node, in touch with the people of their new household and
+    /// of the community. They have no workplace or school contacts, as those groups were made before they arrived.
+    pub fn join(&mut self, citizen: &mut Citizen, rng: &mut RandomWrapper) {
+        let node = self.transmission_rates.len() as u32;
+        citizen.contact_node = Some(node);
+        self.transmission_rates.push(0.0);
+
+        let household = self.households.entry(citizen.home_location.start_offset).or_default();
+        if let Some(sampler) = &self.samplers[Layer::Household as usize] {
+            let degree = sampler.sample(rng).min(household.len());
+            for contact in household.choose_multiple(rng.get(), degree) {
+                self.layers[Layer::Household as usize].add(node, *contact);
+            }
+        }
+        household.push(node);
+        if let Some(sampler) = &self.samplers[Layer::Community as usize] {
+            let degree = sampler.sample(rng).min(node as usize);
+            for contact in index::sample(rng.get(), node as usize, degree).iter() {
+                self.layers[Layer::Community as usize].add(node, contact as u32);
+            }
+        }
+    }
+
+    /// The groups of nodes that contacts in a layer are made within
+    fn groups(layer: Layer, citizens: &[Citizen]) -> Vec<Vec<u32>> {
+        let nodes = citizens.iter().enumerate();
+        match layer {
+            Layer::Household => ContactNetwork::group_by(nodes, |c| Some(c.home_location.start_offset)),
+            Layer::Workplace => ContactNetwork::group_by(nodes, |c| {
+                if c.is_working() { Some(c.work_location.start_offset) } else { None }
+            }),
+            Layer::School => vec![nodes.filter(|(_, c)| !c.is_working()).map(|(node, _)| node as u32).collect()],
+            Layer::Community => vec![(0..citizens.len() as u32).collect()],
+        }
+    }
+
+    fn group_by<'a>(nodes: impl Iterator<Item=(usize, &'a Citizen)>, key: impl Fn(&Citizen) -> Option<Point>)
+                    -> Vec<Vec<u32>> {
+        let mut groups: FxHashMap<Point, Vec<u32>> = FxHashMap::default();
+        for (node, citizen) in nodes {
+            if let Some(key) = key(citizen) {
+                groups.entry(key).or_default().push(node as u32);
+            }
+        }
+        let mut groups: Vec<(Point, Vec<u32>)> = groups.into_iter().collect();
+        groups.sort_unstable_by_key(|(key, _)| (key.y, key.x));
+        groups.into_iter().map(|(_, nodes)| nodes).collect()
+    }
+
+    /// Pairs up the nodes of each group at random, after drawing the number of contacts of each of them (the
+    /// configuration model). Pairs of a node with itself are dropped.
+    fn pair_contacts(groups: &[Vec<u32>], sampler: &DegreeSampler, rng: &mut RandomWrapper) -> Vec<(u32, u32)> {
+        let mut pairs = Vec::new();
+        let mut stubs = Vec::new();
+        for group in groups {
+            let max_degree = group.len().saturating_sub(1);
+            stubs.clear();
+            for node in group {
+                let degree = sampler.sample(rng).min(max_degree);
+                stubs.extend(std::iter::repeat_n(*node, degree));
+            }
+            stubs.shuffle(rng.get());
+            pairs.extend(stubs.chunks_exact(2)
+                .filter(|pair| pair[0] != pair[1])
+                .map(|pair| (pair[0], pair[1])));
+        }
+        pairs
+    }
+
+    /// Takes note of the citizens who can pass the infection on at the start of the hour, and how likely they are to
+    pub fn update_transmission_rates(&mut self, map: &AgentLocationMap, disease: &Disease) {
+        for rate in self.transmission_rates.iter_mut() {
+            *rate = 0.0;
+        }
+        for citizen in map.citizens() {
+            if let Some(node) = citizen.contact_node {
+                if citizen.can_infect() {
+                    self.transmission_rates[node as usize] = citizen.get_infection_transmission_rate(disease);
+                }
+            }
+        }
+    }
+
+    /// Whether any of the contacts of the node in the layer passes the infection on to it, with their rates of
+    /// transmission scaled by the multiplier
+    pub fn infects(&self, node: u32, layer: Layer, multiplier: f64, rng: &mut RandomWrapper) -> bool {
+        self.layers[layer as usize].of(node)
+            .map(|contact| self.transmission_rates[*contact as usize])
+            .filter(|rate| *rate > 0.0)
+            .any(|rate| rng.get().gen_bool((rate * multiplier).min(1.0)))
+    }
+
+    pub fn contacts_of(&self, node: u32, layer: Layer) -> Vec<u32> {
+        self.layers[layer as usize].of(node).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::config::{AutoPopulation, Config, Population};
+    use crate::geography::Area;
+    use crate::simulation::Simulation;
+
+    use super::*;
+
+    fn citizen(home: Area, office: Area, working: bool) -> Citizen {
+        Citizen::new_citizen(0, home, office, home.start_offset, false, working, &mut RandomWrapper::new())
+    }
+
+    fn citizens() -> Vec<Citizen> {
+        let homes = [Area::new(Point::new(0, 0), Point::new(1, 1)), Area::new(Point::new(2, 0), Point::new(3, 1))];
+        let office = Area::new(Point::new(10, 0), Point::new(20, 10));
+        (0..20).map(|i| citizen(homes[i % 2], office, i < 12)).collect()
+    }
+
+    fn network_config(layer: &str, distribution: DegreeDistribution) -> ContactNetworkConfig {
+        let mut config = ContactNetworkConfig::default();
+        match layer {
+            "household" => config.household = Some(distribution),
+            "workplace" => config.workplace = Some(distribution),
+            "school" => config.school = Some(distribution),
+            _ => config.community = Some(distribution),
+        }
+        config
+    }
+
+    #[test]
+    fn should_make_contacts_within_the_group_of_each_layer() {
+        let mut citizens = citizens();
+        for layer in Layer::ALL.iter() {
+            let config = network_config(layer.name(), DegreeDistribution::Fixed(3));
+            let network = ContactNetwork::generate(&config, &mut citizens, &mut RandomWrapper::new());
+
+            for node in 0..citizens.len() as u32 {
+                let contacts = network.contacts_of(node, *layer);
+                assert!(contacts.len() <= 3);
+                for contact in &contacts {
+                    let (a, b) = (&citizens[node as usize], &citizens[*contact as usize]);
+                    assert_ne!(node, *contact);
+                    assert!(network.contacts_of(*contact, *layer).contains(&node));
+                    match layer {
+                        Layer::Household => assert_eq!(a.home_location, b.home_location),
+                        Layer::Workplace => assert!(a.is_working() && b.is_working()),
+                        Layer::School => assert!(!a.is_working() && !b.is_working()),
+                        Layer::Community => {}
+                    }
+                }
+                for other in Layer::ALL.iter().filter(|other| *other != layer) {
+                    assert!(network.contacts_of(node, *other).is_empty());
+                }
+            }
+        }
+        assert_eq!(citizens[7].contact_node, Some(7));
+    }
+
+    #[test]
+    fn should_give_contacts_to_a_traveller_who_joins() {
+        let mut citizens = citizens();
+        let mut config = network_config("household", DegreeDistribution::Fixed(3));
+        config.community = Some(DegreeDistribution::Fixed(2));
+        let mut rng = RandomWrapper::new();
+        let mut network = ContactNetwork::generate(&config, &mut citizens, &mut rng);
+        let mut traveller = citizens[1];
+        traveller.relocate(citizens[0].home_location, citizens[0].work_location);
+
+        network.join(&mut traveller, &mut rng);
+
+        assert_eq!(traveller.contact_node, Some(20));
+        let housemates = network.contacts_of(20, Layer::Household);
+        assert_eq!(housemates.len(), 3);
+        for housemate in &housemates {
+            assert_eq!(citizens[*housemate as usize].home_location, citizens[0].home_location);
+            assert!(network.contacts_of(*housemate, Layer::Household).contains(&20));
+        }
+        let community = network.contacts_of(20, Layer::Community);
+        assert_eq!(community.len(), 2);
+        assert!(network.contacts_of(community[0], Layer::Community).contains(&20));
+        assert!(network.contacts_of(20, Layer::Workplace).is_empty());
+
+        network.transmission_rates[20] = 1.0;
+        assert!(network.infects(housemates[0], Layer::Household, 1.0, &mut rng));
+    }
+
+    #[test]
+    fn should_give_contacts_close_to_the_configured_degrees() {
+        let mut citizens: Vec<Citizen> = (0..2000).map(|_| citizens()[0]).collect();
+        let mut rng = RandomWrapper::with_seed(Some(7));
+        let mean_degree = |network: &ContactNetwork| {
+            (0..2000).map(|node| network.contacts_of(node, Layer::Community).len()).sum::<usize>() as f64 / 2000.0
+        };
+
+        let poisson = network_config("community", DegreeDistribution::Poisson { mean: 4.0 });
+        let network = ContactNetwork::generate(&poisson, &mut citizens, &mut rng);
+        assert!((mean_degree(&network) - 4.0).abs() < 0.2, "mean degree was {}", mean_degree(&network));
+
+        let empirical = network_config("community", DegreeDistribution::Empirical { weights: vec![0.0, 0.5, 0.0, 0.5] });
+        let network = ContactNetwork::generate(&empirical, &mut citizens, &mut rng);
+        assert!((mean_degree(&network) - 2.0).abs() < 0.2, "mean degree was {}", mean_degree(&network));
+    }
+
+    #[test]
+    fn should_draw_poisson_numbers_for_large_means() {
+        let mut rng = RandomWrapper::with_seed(Some(3));
+        let mean = (0..200).map(|_| poisson(1200.0, &mut rng)).sum::<usize>() as f64 / 200.0;
+        assert!((mean - 1200.0).abs() < 10.0, "mean was {}", mean);
+    }
+
+    #[test]
+    fn should_pick_layer_by_hour_of_day() {
+        assert_eq!(Layer::active_at(24 + constants::ROUTINE_TRAVEL_START_TIME, true), Layer::Community);
+        assert_eq!(Layer::active_at(constants::ROUTINE_WORK_TIME + 2, true), Layer::Workplace);
+        assert_eq!(Layer::active_at(constants::ROUTINE_WORK_TIME + 2, false), Layer::School);
+        assert_eq!(Layer::active_at(constants::ROUTINE_TRAVEL_END_TIME, false), Layer::Community);
+        assert_eq!(Layer::active_at(constants::ROUTINE_WORK_END_TIME + 1, true), Layer::Household);
+    }
+
+    #[test]
+    fn should_only_be_infected_by_infectious_contacts() {
+        let mut citizens = citizens();
+        let config = network_config("household", DegreeDistribution::Fixed(9));
+        let mut network = ContactNetwork::generate(&config, &mut citizens, &mut RandomWrapper::new());
+        let mut rng = RandomWrapper::new();
+
+        let contact = network.contacts_of(1, Layer::Household)[0];
+        assert!(!network.infects(contact, Layer::Household, 1.0, &mut rng));
+
+        network.transmission_rates[1] = 1.0;
+        assert!(network.infects(contact, Layer::Household, 1.0, &mut rng));
+        assert!(!network.infects(contact, Layer::Household, 0.0, &mut rng));
+        assert!(!network.infects(contact, Layer::Community, 1.0, &mut rng));
+        assert!(!network.infects(0, Layer::Household, 1.0, &mut rng));
+    }
+
+    #[test]
+    fn should_report_invalid_degree_distributions() {
+        let mut config = network_config("household", DegreeDistribution::Poisson { mean: -1.0 });
+        config.school = Some(DegreeDistribution::Empirical { weights: vec![0.0, 0.0] });
+        let mut errors = ValidationErrors::new();
+
+        Transmission::Network(Box::new(config)).validate("transmission", &mut errors);
+
+        let fields: Vec<&str> = errors.get_errors().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["transmission.Network.household.Poisson.mean", "transmission.Network.school.Empirical.weights"]);
+    }
+
+    fn simulation_config(transmission: serde_json::Value) -> Config {
+        let population = Population::Auto(AutoPopulation {
+            number_of_agents: 100,
+            public_transport_percentage: 0.2,
+            working_percentage: 0.5,
+            age_distribution: None,
+        });
+        let disease = Disease::new(0, 50, 60, 0.99, 1.0, 0.0);
+        let mut config = Config::new(population, disease, vec![], 100, 120, vec![], None);
+        config.set_seed(11);
+        config.with_parameters(&[("transmission".to_string(), transmission)]).unwrap()
+    }
+
+    fn susceptible_at_end(config: Config) -> i32 {
+        let mut simulation = Simulation::builder(config).build().unwrap();
+        while !simulation.is_finished() {
+            simulation.step();
+        }
+        simulation.get_counts().get_susceptible()
+    }
+
+    #[test]
+    fn should_spread_infection_over_contacts_only() {
+        assert_eq!(susceptible_at_end(simulation_config(json!({"Network": {}}))), 99);
+        assert!(susceptible_at_end(simulation_config(json!({"Network": {"community": {"Fixed": 99}}}))) < 99);
+    }
+}
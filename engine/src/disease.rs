@@ -18,14 +18,20 @@
  */
 
 use std::collections::HashMap;
+use std::error::Error;
 use std::fs::File;
 
 use rand::Rng;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serde::de;
 
+use crate::progression::{Course, CourseSampler, DaysDistribution, Stage};
 use crate::random_wrapper::RandomWrapper;
+use crate::validation::{field, ValidationErrors};
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+/// The stages of a disease are set by fixed days since infection, made a day or two shorter or longer for each citizen
+/// by their immunity. A stage with a distribution is drawn from it for each citizen instead.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Disease {
     regular_transmission_start_day: i32,
     high_transmission_start_day: i32,
@@ -33,14 +39,53 @@ pub struct Disease {
     regular_transmission_rate: f64,
     high_transmission_rate: f64,
     death_rate: f64,
+    /// Days before a citizen becomes infectious, in place of `regular_transmission_start_day`
+    #[serde(default)]
+    latent_period: Option<DaysDistribution>,
+    /// Days a citizen is infectious for, in place of the days from `regular_transmission_start_day` to `last_day`
+    #[serde(default)]
+    infectious_period: Option<DaysDistribution>,
+    /// Days from infection to quarantine, in place of `high_transmission_start_day`
+    #[serde(default)]
+    time_to_quarantine: Option<DaysDistribution>,
+    /// Days from infection to recovery or death, in place of `last_day`
+    #[serde(default)]
+    time_to_recovery: Option<DaysDistribution>,
 }
 
 impl Disease {
-    pub fn init(config_file_path: &str, disease_name: &String) -> Disease {
-        let reader = File::open(config_file_path).expect("Failed to open disease config file");
-        let yaml: HashMap<String, Disease> = serde_yaml::from_reader(reader).expect("Failed to parse disease config file");
-        let disease = yaml.get(disease_name).expect("Failed to find disease");
-        *disease
+    pub fn init(config_file_path: &str, disease_name: &str) -> Disease {
+        Disease::from_library(config_file_path, disease_name).expect("Failed to read disease")
+    }
+
+    /// Reads a named disease from a library of diseases, like `config/diseases.yaml`
+    pub fn from_library(config_file_path: &str, disease_name: &str) -> Result<Disease, Box<dyn Error>> {
+        let reader = File::open(config_file_path)
+            .map_err(|e| format!("Failed to open disease library {}: {}", config_file_path, e))?;
+        let yaml: HashMap<String, Disease> = serde_yaml::from_reader(reader)
+            .map_err(|e| format!("Failed to parse disease library {}: {}", config_file_path, e))?;
+        match yaml.get(disease_name) {
+            Some(disease) => Ok(disease.clone()),
+            None => {
+                let mut names: Vec<&String> = yaml.keys().collect();
+                names.sort();
+                Err(format!("Disease {} is not in {}; it has {:?}", disease_name, config_file_path, names).into())
+            }
+        }
+    }
+
+    /// Reads either a disease written out in full, or a reference to one in a library, like
+    /// `{"from_library": "covid_19", "file": "config/diseases.yaml", "death_rate": 0.05}`. The other fields of a
+    /// reference override those of the disease in the library.
+    pub fn deserialize_inline_or_from_library<'de, D>(deserializer: D) -> Result<Disease, D::Error>
+        where D: Deserializer<'de> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        if value.get("from_library").is_none() {
+            return serde_json::from_value(value).map_err(de::Error::custom);
+        }
+        let reference: DiseaseReference = serde_json::from_value(value).map_err(de::Error::custom)?;
+        let disease = Disease::from_library(&reference.file, &reference.from_library).map_err(de::Error::custom)?;
+        Ok(reference.overrides.apply(disease))
     }
 
     pub fn new(regular_transmission_start_day: i32, high_transmission_start_day: i32, last_day: i32,
@@ -52,28 +97,45 @@ impl Disease {
             regular_transmission_rate,
             high_transmission_rate,
             death_rate,
+            latent_period: None,
+            infectious_period: None,
+            time_to_quarantine: None,
+            time_to_recovery: None,
         }
     }
 
-    pub fn get_current_transmission_rate(&self, infection_day: i32) -> f64 {
-        if self.regular_transmission_start_day < infection_day && infection_day <= self.high_transmission_start_day {
-            return self.regular_transmission_rate;
-        } else if self.high_transmission_start_day < infection_day && infection_day <= self.last_day {
-            return self.high_transmission_rate;
+    /// Draws the courses of the disease for citizens. Without distributions, the course has the fixed days of the
+    /// disease, which the immunity of a citizen shifts except for their recovery.
+    pub fn course_sampler(&self) -> CourseSampler {
+        CourseSampler {
+            latent_period: Stage::new(&self.latent_period,
+                                      Stage::ShiftedByImmunity(self.regular_transmission_start_day)),
+            infectious_period: Stage::new(&self.infectious_period,
+                                          Stage::Fixed(self.last_day - self.regular_transmission_start_day)),
+            time_to_quarantine: Stage::new(&self.time_to_quarantine,
+                                           Stage::ShiftedByImmunity(self.high_transmission_start_day)),
+            time_to_recovery: Stage::new(&self.time_to_recovery, Stage::Fixed(self.last_day)),
         }
-        0.0
     }
 
-    pub fn to_be_quarantined(&self, infection_day: i32) -> bool {
-        let transmission_rate = self.get_current_transmission_rate(infection_day);
-        if transmission_rate >= self.high_transmission_rate {
-            return true;
+    /// The rate of transmission on a day of a citizen's course of the disease: none before they become infectious or
+    /// after they stop being so, and the high rate once they are due to be quarantined
+    pub fn get_current_transmission_rate(&self, course: &Course, infection_day: i32) -> f64 {
+        if infection_day <= course.latent_period || infection_day > course.latent_period + course.infectious_period {
+            return 0.0;
         }
-        false
+        if infection_day > course.time_to_quarantine {
+            return self.high_transmission_rate;
+        }
+        self.regular_transmission_rate
+    }
+
+    pub fn to_be_quarantined(&self, course: &Course, infection_day: i32) -> bool {
+        infection_day > course.time_to_quarantine
     }
 
-    pub fn get_disease_last_day(&self) -> i32 {
-        self.last_day
+    pub fn to_be_recovered(&self, course: &Course, infection_day: i32) -> bool {
+        infection_day >= course.time_to_recovery
     }
 
     pub fn to_be_deceased(&self, rng: &mut RandomWrapper) -> bool {
@@ -82,6 +144,73 @@ impl Disease {
         }
         false
     }
+
+    pub fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        errors.check_non_negative(self.regular_transmission_start_day, field(path, "regular_transmission_start_day"));
+        errors.check(self.regular_transmission_start_day <= self.high_transmission_start_day,
+                     field(path, "high_transmission_start_day"),
+                     &format!("should not be before regular_transmission_start_day ({}), but is {}",
+                              self.regular_transmission_start_day, self.high_transmission_start_day));
+        errors.check(self.high_transmission_start_day <= self.last_day,
+                     field(path, "last_day"),
+                     &format!("should not be before high_transmission_start_day ({}), but is {}",
+                              self.high_transmission_start_day, self.last_day));
+        errors.check_fraction(self.regular_transmission_rate, field(path, "regular_transmission_rate"));
+        errors.check_fraction(self.high_transmission_rate, field(path, "high_transmission_rate"));
+        errors.check_fraction(self.death_rate, field(path, "death_rate"));
+        let stages = [("latent_period", &self.latent_period), ("infectious_period", &self.infectious_period),
+            ("time_to_quarantine", &self.time_to_quarantine), ("time_to_recovery", &self.time_to_recovery)];
+        for (name, distribution) in stages.iter() {
+            if let Some(distribution) = distribution {
+                distribution.validate(&field(path, name), errors);
+            }
+        }
+    }
+}
+
+fn default_disease_library() -> String {
+    "config/diseases.yaml".to_string()
+}
+
+#[derive(Deserialize)]
+struct DiseaseReference {
+    from_library: String,
+    #[serde(default = "default_disease_library")]
+    file: String,
+    #[serde(flatten)]
+    overrides: DiseaseParameters,
+}
+
+/// Parameters to change in a disease read from a library
+#[derive(Deserialize)]
+struct DiseaseParameters {
+    regular_transmission_start_day: Option<i32>,
+    high_transmission_start_day: Option<i32>,
+    last_day: Option<i32>,
+    regular_transmission_rate: Option<f64>,
+    high_transmission_rate: Option<f64>,
+    death_rate: Option<f64>,
+    latent_period: Option<DaysDistribution>,
+    infectious_period: Option<DaysDistribution>,
+    time_to_quarantine: Option<DaysDistribution>,
+    time_to_recovery: Option<DaysDistribution>,
+}
+
+impl DiseaseParameters {
+    fn apply(self, disease: Disease) -> Disease {
+        Disease {
+            regular_transmission_start_day: self.regular_transmission_start_day.unwrap_or(disease.regular_transmission_start_day),
+            high_transmission_start_day: self.high_transmission_start_day.unwrap_or(disease.high_transmission_start_day),
+            last_day: self.last_day.unwrap_or(disease.last_day),
+            regular_transmission_rate: self.regular_transmission_rate.unwrap_or(disease.regular_transmission_rate),
+            high_transmission_rate: self.high_transmission_rate.unwrap_or(disease.high_transmission_rate),
+            death_rate: self.death_rate.unwrap_or(disease.death_rate),
+            latent_period: self.latent_period.or(disease.latent_period),
+            infectious_period: self.infectious_period.or(disease.infectious_period),
+            time_to_quarantine: self.time_to_quarantine.or(disease.time_to_quarantine),
+            time_to_recovery: self.time_to_recovery.or(disease.time_to_recovery),
+        }
+    }
 }
 
 /// Override disease parameters for a specific population trait
@@ -98,32 +227,141 @@ impl DiseaseOverride {
             population_param, values, disease
         }
     }
+
+    pub fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        self.disease.validate(&field(path, "disease"), errors);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn course_of(disease: &Disease, immunity: i32) -> Course {
+        disease.course_sampler().draw(immunity, &mut RandomWrapper::new())
+    }
+
     #[test]
     fn get_current_transmission_rate() {
         let disease = Disease::init("config/diseases.yaml", &String::from("small_pox"));
-        let infection_rate = disease.get_current_transmission_rate(12);
+        let course = course_of(&disease, 0);
+        let infection_rate = disease.get_current_transmission_rate(&course, 12);
         assert_eq!(infection_rate, 0.05);
 
-        let infection_rate = disease.get_current_transmission_rate(22);
+        let infection_rate = disease.get_current_transmission_rate(&course, 22);
         assert_eq!(infection_rate, 0.5);
+
+        let infection_rate = disease.get_current_transmission_rate(&course, 23);
+        assert_eq!(infection_rate, 0.0);
     }
 
     #[test]
     fn to_be_quarantined() {
         let disease = Disease::init("config/diseases.yaml", &String::from("small_pox"));
-        let actual = disease.to_be_quarantined(12);
+        let course = course_of(&disease, 0);
+        let actual = disease.to_be_quarantined(&course, 12);
         assert_eq!(actual, false);
 
-        let actual = disease.to_be_quarantined(22);
+        let actual = disease.to_be_quarantined(&course, 22);
         assert_eq!(actual, true);
     }
 
+    #[test]
+    fn should_shift_fixed_course_by_immunity_except_recovery() {
+        let disease = Disease::init("config/diseases.yaml", &String::from("small_pox"));
+
+        let course = course_of(&disease, 2);
+
+        assert_eq!(course, Course { latent_period: 8, infectious_period: 12, time_to_quarantine: 14, time_to_recovery: 22 });
+        assert_eq!(disease.get_current_transmission_rate(&course, 9), 0.05);
+        assert_eq!(disease.get_current_transmission_rate(&course, 15), 0.5);
+        assert_eq!(disease.get_current_transmission_rate(&course, 21), 0.0);
+    }
+
+    #[test]
+    fn should_draw_stages_with_distributions_for_each_citizen() {
+        let disease = read_disease(r#"{"disease": {"from_library": "small_pox",
+            "latent_period": {"Uniform": {"min": 3, "max": 5}}, "time_to_recovery": {"Empirical": {"weights": [0, 0, 1]}},
+            "time_to_quarantine": {"Gamma": {"shape": 4.0, "scale": 1.5}}}}"#).unwrap();
+        let sampler = disease.course_sampler();
+        let mut rng = RandomWrapper::new();
+
+        let courses: Vec<Course> = (0..1000).map(|_| sampler.draw(1, &mut rng)).collect();
+
+        assert!(courses.iter().all(|c| (3..=5).contains(&c.latent_period)));
+        assert!((3..=5).all(|days| courses.iter().any(|c| c.latent_period == days)));
+        assert!(courses.iter().all(|c| c.infectious_period == 12));
+        let mean_time_to_quarantine = courses.iter().map(|c| c.time_to_quarantine).sum::<i32>() as f64 / 1000.0;
+        assert!((mean_time_to_quarantine - 6.0).abs() < 0.5, "{}", mean_time_to_quarantine);
+        assert!(courses.iter().all(|c| c.time_to_recovery == 2.max(c.time_to_quarantine + 1)));
+    }
+
+    #[test]
+    fn should_read_distributions_from_library() {
+        let disease = Disease::init("config/diseases.yaml", &String::from("covid_19_variable"));
+
+        assert_eq!(disease.latent_period, Some(DaysDistribution::Gamma { shape: 4.0, scale: 1.25 }));
+        let course = course_of(&disease, 0);
+        assert!((10..=20).contains(&course.infectious_period));
+        assert!(course.time_to_recovery > course.time_to_quarantine);
+    }
+
+    #[test]
+    fn should_report_invalid_distributions() {
+        let disease = read_disease(r#"{"disease": {"from_library": "sars",
+            "latent_period": {"Uniform": {"min": 5, "max": 3}}, "infectious_period": {"LogNormal": {"meanlog": 1.0, "sdlog": -1.0}},
+            "time_to_recovery": {"Empirical": {"weights": [0, 0]}}}}"#).unwrap();
+        let mut errors = ValidationErrors::new();
+        disease.validate("disease", &mut errors);
+
+        let fields: Vec<&str> = errors.get_errors().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["disease.latent_period.Uniform.max", "disease.infectious_period.LogNormal.sdlog",
+                                "disease.time_to_recovery.Empirical.weights"]);
+    }
+
+    #[test]
+    fn should_report_invalid_disease() {
+        let disease = Disease::new(5, 20, 15, 0.025, 1.25, 0.2);
+        let mut errors = ValidationErrors::new();
+        disease.validate("disease", &mut errors);
+
+        let fields: Vec<&str> = errors.get_errors().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["disease.last_day", "disease.high_transmission_rate"]);
+    }
+
+    #[derive(Deserialize)]
+    struct WithDisease {
+        #[serde(deserialize_with = "Disease::deserialize_inline_or_from_library")]
+        disease: Disease,
+    }
+
+    fn read_disease(json: &str) -> Result<Disease, serde_json::Error> {
+        serde_json::from_str::<WithDisease>(json).map(|d| d.disease)
+    }
+
+    #[test]
+    fn should_read_disease_from_library_with_overrides() {
+        let disease = read_disease(r#"{"disease": {"from_library": "sars", "death_rate": 0.3, "last_day": 30}}"#).unwrap();
+
+        assert_eq!(disease, Disease::new(5, 10, 30, 0.025, 0.25, 0.3));
+    }
+
+    #[test]
+    fn should_read_inline_disease() {
+        let disease = read_disease(r#"{"disease": {"regular_transmission_start_day": 5, "high_transmission_start_day": 20,
+            "last_day": 40, "regular_transmission_rate": 0.025, "high_transmission_rate": 0.25, "death_rate": 0.035}}"#);
+
+        assert_eq!(disease.unwrap(), Disease::new(5, 20, 40, 0.025, 0.25, 0.035));
+    }
+
+    #[test]
+    fn should_report_unknown_disease_in_library() {
+        let error = read_disease(r#"{"disease": {"from_library": "flu", "file": "config/diseases.yaml"}}"#).unwrap_err();
+
+        assert!(error.to_string().starts_with(
+            r#"Disease flu is not in config/diseases.yaml; it has ["covid_19", "covid_19_variable", "sars", "small_pox"]"#));
+    }
+
     #[test]
     fn read_smallpox_config() {
         let disease = Disease::init("config/diseases.yaml", &String::from("small_pox"));
@@ -134,6 +372,10 @@ mod tests {
             regular_transmission_rate: 0.05,
             high_transmission_rate: 0.5,
             death_rate: 0.2,
+            latent_period: None,
+            infectious_period: None,
+            time_to_quarantine: None,
+            time_to_recovery: None,
         };
         assert_eq!(expected, disease)
     }
@@ -0,0 +1,247 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::error::Error;
+
+use futures::StreamExt;
+use fxhash::FxHashSet;
+use kafka_config::KafkaConfig;
+use rdkafka::consumer::{Consumer, DefaultConsumerContext, MessageStream, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::{BorrowedMessage, Message};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+
+use crate::agent::Citizen;
+use crate::config::Config;
+use crate::constants;
+use crate::epidemiology_simulation::{Epidemiology, SimulationRun};
+use crate::geography::Point;
+use crate::listeners::listener::Listeners;
+use crate::travel_plan::TravelPlan;
+
+/// Sent by the orchestrator to ask every engine to simulate the given hour
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tick {
+    pub sim_id: String,
+    pub hour: i32,
+}
+
+/// Sent by an engine once it has simulated the hour and published its travellers
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TickAck {
+    pub sim_id: String,
+    pub engine_id: String,
+    pub hour: i32,
+}
+
+/// Citizens handed off from one engine to another at the end of a travel hour. Every engine sends one
+/// of these to every other engine each travel hour, even when no one travels, so that the receiver
+/// knows when it has heard from everyone
+#[derive(Serialize, Deserialize)]
+pub struct Travellers {
+    pub sim_id: String,
+    pub from_engine: String,
+    pub to_engine: String,
+    pub hour: i32,
+    pub citizens: Vec<Citizen>,
+}
+
+enum DistributedMessage {
+    Tick(Tick),
+    Travellers(Travellers),
+    Ignored,
+}
+
+/// Simulates the region owned by this engine, one hour per tick from the orchestrator. Citizens
+/// travelling to regions owned by other engines are sent to them over Kafka
+pub struct DistributedSimulation {
+    engine_id: String,
+    sim_id: String,
+    config: Config,
+    travel_plan: TravelPlan,
+    epidemiology: Epidemiology,
+    producer: FutureProducer,
+    kafka_config: KafkaConfig,
+}
+
+impl DistributedSimulation {
+    pub fn new(engine_id: String, sim_id: String, config: Config, travel_plan: TravelPlan,
+               kafka_config: KafkaConfig) -> DistributedSimulation {
+        let epidemiology = Epidemiology::new(&config, sim_id.clone());
+        let producer = kafka_config.client_config()
+            .create()
+            .expect("Could not create Kafka Producer");
+        DistributedSimulation { engine_id, sim_id, config, travel_plan, epidemiology, producer, kafka_config }
+    }
+
+    fn other_engines(&self) -> Vec<String> {
+        self.travel_plan.get_regions().iter().filter(|r| **r != self.engine_id).cloned().collect()
+    }
+
+    pub async fn run(&mut self) {
+        // A group per engine and simulation, reading from the beginning, so that no tick published
+        // before the subscription completes is missed. Messages from other simulations are skipped
+        let consumer: StreamConsumer = self.kafka_config.client_config()
+            .set("group.id", &format!("{}_{}", self.engine_id, self.sim_id))
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .expect("Consumer creation failed");
+        consumer.subscribe(&[self.kafka_config.topics.ticks.as_str(), self.kafka_config.topics.travellers.as_str()])
+            .expect("Can't subscribe to specified topics");
+        let mut message_stream: MessageStream<DefaultConsumerContext> = consumer.start();
+
+        let listeners = Listeners::from(self.epidemiology.create_listeners(&self.config, &self.kafka_config));
+        let mut run = self.epidemiology.start_run(&self.config, listeners);
+        let mut inbox: Vec<Travellers> = Vec::new();
+        let other_engines = self.other_engines();
+
+        while let Some(message) = message_stream.next().await {
+            match self.parse_message(message) {
+                Ok(DistributedMessage::Ignored) => {}
+                Ok(DistributedMessage::Travellers(travellers)) => inbox.push(travellers),
+                Ok(DistributedMessage::Tick(tick)) => {
+                    let travel_hour = tick.hour - 1;
+                    if travel_hour > 0 && travel_hour % constants::NUMBER_OF_HOURS == 0 {
+                        while inbox.iter().filter(|t| t.hour == travel_hour).count() < other_engines.len() {
+                            match message_stream.next().await.map(|m| self.parse_message(m)) {
+                                Some(Ok(DistributedMessage::Travellers(travellers))) => inbox.push(travellers),
+                                Some(Ok(DistributedMessage::Tick(t))) => println!("Unexpected tick for hour {} while waiting for travellers", t.hour),
+                                Some(Ok(DistributedMessage::Ignored)) => {}
+                                Some(Err(e)) => println!("Could not parse message: {}", e),
+                                None => return,
+                            }
+                        }
+                        let arrivals = inbox.drain(..).filter(|t| t.hour == travel_hour).collect();
+                        self.receive_travellers(&mut run, arrivals, travel_hour);
+                    }
+
+                    self.epidemiology.run_hour(&self.config, &mut run, tick.hour);
+
+                    if tick.hour % constants::NUMBER_OF_HOURS == 0 {
+                        self.send_travellers(&mut run, tick.hour, &other_engines).await;
+                    }
+                    self.acknowledge(tick.hour).await;
+
+                    if tick.hour >= self.config.get_hours() - 1 {
+                        break;
+                    }
+                }
+                Err(e) => println!("Could not parse message: {}", e),
+            }
+        }
+        Epidemiology::end_run(&mut run);
+    }
+
+    fn receive_travellers(&mut self, run: &mut SimulationRun, arrivals: Vec<Travellers>, simulation_hour: i32) {
+        for citizen in arrivals.into_iter().flat_map(|t| t.citizens) {
+            match self.epidemiology.find_vacant_home(simulation_hour, &mut run.rng) {
+                Some(cell) => self.epidemiology.settle(&mut run.counts_at_hr, simulation_hour, citizen, cell, &mut run.rng),
+                None => println!("No vacant home for citizen {} arriving at {}", citizen.id, self.engine_id)
+            }
+        }
+    }
+
+    async fn send_travellers(&mut self, run: &mut SimulationRun, simulation_hour: i32, other_engines: &[String]) {
+        let mut picked: FxHashSet<Point> = FxHashSet::default();
+        for to_engine in other_engines {
+            let fraction = self.travel_plan.get_travel_fraction(&self.engine_id, to_engine);
+            let cells = self.epidemiology.pick_travellers(simulation_hour, fraction, &picked, &mut run.rng);
+            let citizens = cells.iter().map(|cell| {
+                picked.insert(*cell);
+                self.epidemiology.depart(&mut run.counts_at_hr, simulation_hour, cell)
+            }).collect();
+
+            let travellers = Travellers {
+                sim_id: self.sim_id.clone(),
+                from_engine: self.engine_id.clone(),
+                to_engine: to_engine.clone(),
+                hour: simulation_hour,
+                citizens,
+            };
+            let message = serde_json::to_string(&travellers).expect("Failed to serialize travellers");
+            self.send(&self.kafka_config.topics.travellers, &message).await;
+        }
+    }
+
+    async fn acknowledge(&self, hour: i32) {
+        let ack = TickAck { sim_id: self.sim_id.clone(), engine_id: self.engine_id.clone(), hour };
+        let message = serde_json::to_string(&ack).expect("Failed to serialize tick acknowledgement");
+        self.send(&self.kafka_config.topics.ticks_ack, &message).await;
+    }
+
+    async fn send(&self, topic: &str, message: &String) {
+        let record: FutureRecord<String, String> = FutureRecord::to(topic)
+            .key(&self.sim_id)
+            .payload(message);
+        match self.producer.send(record, 0).await {
+            Ok(Ok(_)) => {}
+            Ok(Err((e, _))) => println!("Failed to deliver message to {}: {}", topic, e),
+            Err(e) => println!("Failed to deliver message to {}: {}", topic, e),
+        }
+    }
+
+    fn parse_message(&self, message: Result<BorrowedMessage, KafkaError>) -> Result<DistributedMessage, Box<dyn Error>> {
+        let borrowed_message = message?;
+        let payload = borrowed_message.payload_view::<str>().unwrap()?;
+        if borrowed_message.topic() == self.kafka_config.topics.ticks {
+            let tick: Tick = serde_json::from_str(payload)?;
+            if tick.sim_id == self.sim_id {
+                return Ok(DistributedMessage::Tick(tick));
+            }
+        } else {
+            let travellers: Travellers = serde_json::from_str(payload)?;
+            if travellers.sim_id == self.sim_id && travellers.to_engine == self.engine_id {
+                return Ok(DistributedMessage::Travellers(travellers));
+            }
+        }
+        Ok(DistributedMessage::Ignored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geography::Area;
+    use crate::random_wrapper::RandomWrapper;
+
+    use super::*;
+
+    #[test]
+    fn should_serialize_and_deserialize_travellers() {
+        let area = Area::new(Point::new(0, 0), Point::new(1, 1));
+        let mut citizen = Citizen::new_citizen(7, area, area, Point::new(2, 2), false, true, &mut RandomWrapper::new());
+        citizen.infect();
+        let travellers = Travellers {
+            sim_id: "sim".to_string(),
+            from_engine: "engine1".to_string(),
+            to_engine: "engine2".to_string(),
+            hour: 24,
+            citizens: vec![citizen],
+        };
+
+        let message = serde_json::to_string(&travellers).unwrap();
+        let parsed: Travellers = serde_json::from_str(&message).unwrap();
+
+        assert_eq!(parsed.to_engine, "engine2");
+        assert_eq!(parsed.hour, 24);
+        assert_eq!(parsed.citizens.len(), 1);
+        assert_eq!(parsed.citizens[0].id, 7);
+        assert!(parsed.citizens[0].is_infected());
+        assert_eq!(parsed.citizens[0].home_location, area);
+    }
+}
@@ -0,0 +1,411 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use csv::Writer;
+use serde_json::Value;
+
+use crate::{config, constants};
+use crate::config::Config;
+use crate::listeners::csv_service::CsvListener;
+use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary;
+use crate::listeners::listener::Listener;
+use crate::simulation::Simulation;
+use crate::validation::ValidationErrors;
+
+fn default_replicates() -> usize {
+    1
+}
+
+fn default_percentiles() -> Vec<f64> {
+    vec![5.0, 25.0, 75.0, 95.0]
+}
+
+/// Runs a config several times, for every combination of the values in `sweep`, and aggregates the counts of the
+/// replicates of each combination
+#[derive(Debug, Deserialize)]
+pub struct EnsembleConfig {
+    config: String,
+    #[serde(default = "default_replicates")]
+    replicates: usize,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    sweep: BTreeMap<String, Vec<Value>>,
+    output_dir: String,
+    parallelism: Option<usize>,
+    #[serde(default = "default_percentiles")]
+    percentiles: Vec<f64>,
+}
+
+pub fn read(filename: &str) -> Result<EnsembleConfig, Box<dyn Error>> {
+    let reader = File::open(filename)?;
+    let ensemble: EnsembleConfig = serde_json::from_reader(reader)?;
+    Ok(ensemble)
+}
+
+type Parameters = Vec<(String, Value)>;
+
+impl EnsembleConfig {
+    /// Writes `scenarios.csv` with the parameters of each scenario, and for each scenario a directory with the counts
+    /// of every run in `run_<n>.csv` and their aggregate in `aggregate.csv`
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        self.validate()?;
+        let base_config = config::read(self.config.clone())?;
+        let scenarios = scenarios(&self.sweep);
+        let configs = scenarios.iter()
+            .map(|parameters| base_config.with_parameters(parameters))
+            .collect::<Result<Vec<Config>, Box<dyn Error>>>()?;
+
+        fs::create_dir_all(&self.output_dir)?;
+        self.write_scenarios(&scenarios)?;
+        for i in 0..configs.len() {
+            fs::create_dir_all(self.scenario_dir(i))?;
+        }
+
+        let runs: Vec<Run> = configs.iter().enumerate()
+            .flat_map(|(scenario, config)| (0..self.replicates).map(move |replicate| (scenario, replicate, config)))
+            .enumerate()
+            .map(|(i, (scenario, replicate, config))| {
+                let mut config = config.clone();
+                config.set_seed(self.seed + i as u64);
+                let output_file = Path::new(&self.scenario_dir(scenario)).join(format!("run_{}.csv", replicate));
+                Run { config, output_file: Some(output_file.to_string_lossy().to_string()) }
+            })
+            .collect();
+        let counts = run_all(&runs, self.parallelism);
+        for (i, scenario_runs) in counts.chunks(self.replicates).enumerate() {
+            let file = Path::new(&self.scenario_dir(i)).join("aggregate.csv");
+            write_aggregate(&file, &aggregate(scenario_runs, &self.percentiles), &self.percentiles)?;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check(self.replicates >= 1, "replicates".to_string(), "should be at least 1");
+        check_parallelism(self.parallelism, &mut errors);
+        for (i, percentile) in self.percentiles.iter().enumerate() {
+            if !(0.0..=100.0).contains(percentile) {
+                errors.add(format!("percentiles[{}]", i), format!("should be between 0 and 100, but is {}", percentile));
+            }
+        }
+        errors.into_result()
+    }
+
+    fn scenario_dir(&self, scenario: usize) -> String {
+        Path::new(&self.output_dir).join(format!("scenario_{}", scenario)).to_string_lossy().to_string()
+    }
+
+    fn write_scenarios(&self, scenarios: &[Parameters]) -> Result<(), Box<dyn Error>> {
+        let mut writer = Writer::from_path(Path::new(&self.output_dir).join("scenarios.csv"))?;
+        let mut header = vec!["scenario".to_string()];
+        header.extend(self.sweep.keys().cloned());
+        writer.write_record(&header)?;
+        for (i, parameters) in scenarios.iter().enumerate() {
+            let mut record = vec![format!("scenario_{}", i)];
+            record.extend(parameters.iter().map(|(_, value)| value.to_string()));
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A single run of a simulation, with its counts optionally written to a CSV file
+pub struct Run {
+    pub config: Config,
+    pub output_file: Option<String>,
+}
+
+/// Checks the number of threads given to `run_all`, which would run nothing on none
+pub fn check_parallelism(parallelism: Option<usize>, errors: &mut ValidationErrors) {
+    if let Some(threads) = parallelism {
+        errors.check(threads >= 1, "parallelism".to_string(), "should be at least 1");
+    }
+}
+
+/// Runs the simulations on a pool of threads, by default one per core, and returns the counts of each of them
+pub fn run_all(runs: &[Run], parallelism: Option<usize>) -> Vec<Vec<Counts>> {
+    let next_run = AtomicUsize::new(0);
+    let counts = Mutex::new(vec![Vec::new(); runs.len()]);
+    let parallelism = parallelism
+        .unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.min(runs.len()) {
+            scope.spawn(|| {
+                loop {
+                    let i = next_run.fetch_add(1, Ordering::SeqCst);
+                    if i >= runs.len() {
+                        break;
+                    }
+                    let run_counts = run_once(&runs[i]);
+                    println!("Finished run {} of {}", i + 1, runs.len());
+                    counts.lock().unwrap()[i] = run_counts;
+                }
+            });
+        }
+    });
+    counts.into_inner().unwrap()
+}
+
+fn run_once(run: &Run) -> Vec<Counts> {
+    let counts = Rc::new(RefCell::new(Vec::new()));
+    let mut builder = Simulation::builder(run.config.clone())
+        .listener(Box::new(CountsCollector { counts: counts.clone() }));
+    if let Some(output_file) = &run.output_file {
+        builder = builder.listener(Box::new(CsvListener::new(output_file.clone(), constants::DEFAULT_FLUSH_INTERVAL)));
+    }
+    builder.build().expect("The config of an ensemble run should be valid").run();
+    let counts = counts.borrow().clone();
+    counts
+}
+
+/// Every combination of the values to sweep over, or a single scenario without any changes when there is nothing to
+/// sweep
+fn scenarios(sweep: &BTreeMap<String, Vec<Value>>) -> Vec<Parameters> {
+    sweep.iter().fold(vec![Vec::new()], |scenarios, (path, values)| {
+        scenarios.iter()
+            .flat_map(|parameters| values.iter().map(move |value| {
+                let mut parameters = parameters.clone();
+                parameters.push((path.clone(), value.clone()));
+                parameters
+            }))
+            .collect()
+    })
+}
+
+struct CountsCollector {
+    counts: Rc<RefCell<Vec<Counts>>>,
+}
+
+impl Listener for CountsCollector {
+    fn counts_updated(&mut self, counts: Counts) {
+        self.counts.borrow_mut().push(counts);
+    }
+
+    fn simulation_ended(&mut self, _summary: &summary::Summary) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+type Metric = (&'static str, fn(&Counts) -> i32);
+
+const METRICS: [Metric; 5] = [
+    ("susceptible", Counts::get_susceptible),
+    ("infected", Counts::get_infected),
+    ("quarantined", Counts::get_quarantined),
+    ("recovered", Counts::get_recovered),
+    ("deceased", Counts::get_deceased),
+];
+
+/// The statistics of one metric across the replicates at one hour
+#[derive(Debug, PartialEq)]
+struct Summary {
+    mean: f64,
+    median: f64,
+    percentiles: Vec<f64>,
+}
+
+struct AggregateRow {
+    hour: i32,
+    metrics: Vec<Summary>,
+}
+
+/// Summarises the counts of the runs hour by hour. A run that ended early, with nobody left infected, keeps its last
+/// counts for the remaining hours.
+fn aggregate(runs: &[Vec<Counts>], percentiles: &[f64]) -> Vec<AggregateRow> {
+    let longest_run = match runs.iter().max_by_key(|run| run.len()) {
+        Some(run) => run,
+        None => return Vec::new(),
+    };
+    longest_run.iter().enumerate().map(|(i, row)| {
+        let counts_at_hour: Vec<&Counts> = runs.iter()
+            .filter_map(|run| run.get(i).or_else(|| run.last()))
+            .collect();
+        let metrics = METRICS.iter().map(|(_, metric)| {
+            let values: Vec<f64> = counts_at_hour.iter().map(|counts| metric(counts) as f64).collect();
+            summarise(values, percentiles)
+        }).collect();
+        AggregateRow { hour: row.get_hour(), metrics }
+    }).collect()
+}
+
+fn summarise(mut values: Vec<f64>, percentiles: &[f64]) -> Summary {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    Summary {
+        mean: values.iter().sum::<f64>() / values.len() as f64,
+        median: percentile(&values, 50.0),
+        percentiles: percentiles.iter().map(|p| percentile(&values, *p)).collect(),
+    }
+}
+
+/// The percentile of sorted values, interpolating linearly between the closest ranks
+fn percentile(sorted_values: &[f64], percentile: f64) -> f64 {
+    let rank = percentile / 100.0 * (sorted_values.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted_values[lower] + (sorted_values[upper] - sorted_values[lower]) * (rank - lower as f64)
+}
+
+fn write_aggregate(file: &Path, rows: &[AggregateRow], percentiles: &[f64]) -> Result<(), Box<dyn Error>> {
+    let mut writer = Writer::from_path(file)?;
+    let mut header = vec!["hour".to_string()];
+    for (name, _) in METRICS.iter() {
+        header.push(format!("{}_mean", name));
+        header.push(format!("{}_median", name));
+        header.extend(percentiles.iter().map(|p| format!("{}_p{}", name, p)));
+    }
+    writer.write_record(&header)?;
+    for row in rows {
+        let mut record = vec![row.hour.to_string()];
+        for summary in &row.metrics {
+            record.push(summary.mean.to_string());
+            record.push(summary.median.to_string());
+            record.extend(summary.percentiles.iter().map(|p| p.to_string()));
+        }
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(hour: i32, infected: i32) -> Counts {
+        let mut counts = Counts::new(100 - infected, infected);
+        for _ in 0..hour {
+            counts.increment_hour();
+        }
+        counts
+    }
+
+    #[test]
+    fn should_build_every_combination_of_sweep() {
+        let mut sweep = BTreeMap::new();
+        sweep.insert("grid_size".to_string(), vec![Value::from(100), Value::from(200)]);
+        sweep.insert("disease.death_rate".to_string(), vec![Value::from(0.1), Value::from(0.2), Value::from(0.3)]);
+
+        let scenarios = scenarios(&sweep);
+
+        assert_eq!(scenarios.len(), 6);
+        assert_eq!(scenarios[0], vec![("disease.death_rate".to_string(), Value::from(0.1)),
+                                      ("grid_size".to_string(), Value::from(100))]);
+        assert_eq!(scenarios[5], vec![("disease.death_rate".to_string(), Value::from(0.3)),
+                                      ("grid_size".to_string(), Value::from(200))]);
+        assert_eq!(super::scenarios(&BTreeMap::new()), vec![Vec::new()]);
+    }
+
+    #[test]
+    fn should_read_ensemble_config() {
+        let ensemble = read("config/ensemble.json").unwrap();
+
+        assert_eq!(ensemble.replicates, 10);
+        assert_eq!(ensemble.sweep["disease.regular_transmission_rate"].len(), 3);
+        assert_eq!(ensemble.parallelism, None);
+    }
+
+    #[test]
+    fn should_interpolate_percentiles() {
+        let values = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 50.0), 3.0);
+        assert_eq!(percentile(&values, 100.0), 5.0);
+        assert_eq!(percentile(&values, 25.0), 2.0);
+        assert_eq!(percentile(&values, 90.0), 4.6);
+        assert_eq!(percentile(&[7.0], 95.0), 7.0);
+    }
+
+    #[test]
+    fn should_aggregate_runs_carrying_the_last_counts_of_shorter_runs() {
+        let runs = vec![
+            vec![counts(1, 1), counts(2, 3), counts(3, 5)],
+            vec![counts(1, 1), counts(2, 0)],
+            vec![counts(1, 4), counts(2, 6), counts(3, 9)],
+        ];
+
+        let rows = aggregate(&runs, &[50.0]);
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2].hour, 3);
+        let infected = &rows[2].metrics[1];
+        assert_eq!(infected, &Summary { mean: 14.0 / 3.0, median: 5.0, percentiles: vec![5.0] });
+        assert_eq!(rows[0].metrics[1].mean, 2.0);
+    }
+
+    #[test]
+    fn should_run_ensemble_and_write_aggregates() {
+        let output_dir = std::env::temp_dir().join("epirust_ensemble_test");
+        let _ = fs::remove_dir_all(&output_dir);
+        let mut sweep = BTreeMap::new();
+        sweep.insert("hours".to_string(), vec![Value::from(24), Value::from(48)]);
+        sweep.insert("population.Auto.number_of_agents".to_string(), vec![Value::from(100)]);
+        sweep.insert("grid_size".to_string(), vec![Value::from(50)]);
+        let ensemble = EnsembleConfig {
+            config: "config/default.json".to_string(),
+            replicates: 2,
+            seed: 1,
+            sweep,
+            output_dir: output_dir.to_string_lossy().to_string(),
+            parallelism: Some(2),
+            percentiles: default_percentiles(),
+        };
+
+        ensemble.run().unwrap();
+
+        let scenarios = fs::read_to_string(output_dir.join("scenarios.csv")).unwrap();
+        assert_eq!(scenarios, "scenario,grid_size,hours,population.Auto.number_of_agents\n\
+                               scenario_0,50,24,100\nscenario_1,50,48,100\n");
+        assert!(output_dir.join("scenario_1").join("run_1.csv").exists());
+        let aggregate = fs::read_to_string(output_dir.join("scenario_0").join("aggregate.csv")).unwrap();
+        let header = aggregate.lines().next().unwrap();
+        assert!(header.starts_with("hour,susceptible_mean,susceptible_median,susceptible_p5,susceptible_p25"));
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn should_report_invalid_replicates_parallelism_and_percentiles() {
+        let ensemble: EnsembleConfig = serde_json::from_str(r#"{"config": "config/default.json", "output_dir": "out",
+            "replicates": 0, "parallelism": 0, "percentiles": [50, 101, -1]}"#).unwrap();
+
+        let errors = ensemble.run().unwrap_err();
+
+        assert_eq!(errors.to_string(), "Invalid config:\n  \
+            replicates: should be at least 1\n  \
+            parallelism: should be at least 1\n  \
+            percentiles[1]: should be between 0 and 100, but is 101\n  \
+            percentiles[2]: should be between 0 and 100, but is -1");
+    }
+}
@@ -23,21 +23,25 @@ use std::collections::HashMap;
 use std::time::{Instant, SystemTime};
 
 use chrono::{DateTime, Local};
-use fxhash::{FxBuildHasher, FxHashMap};
+use fxhash::FxHashSet;
+use kafka_config::KafkaConfig;
 use rand::Rng;
+use rand::seq::{IteratorRandom, SliceRandom};
 
 use crate::{allocation_map, constants};
 use crate::allocation_map::AgentLocationMap;
 use crate::config::{Config, Population};
+use crate::contact_matrix::ContactMatrices;
+use crate::contact_network::{ContactNetwork, Mixing, Transmission};
 use crate::disease::Disease;
 use crate::geography;
-use crate::geography::Grid;
-use crate::interventions::{Intervention, Lockdown};
-use crate::listeners::csv_service::CsvListener;
-use crate::listeners::disease_tracker::Hotspot;
+use crate::agent::Citizen;
+use crate::geography::{Grid, Point};
+use crate::interventions::{BuildNewHospital, Intervention, Lockdown};
 use crate::listeners::events::counts::Counts;
-use crate::listeners::kafka_producer::KafkaProducer;
-use crate::listeners::listener::Listeners;
+use crate::listeners::events::simulation_started::SimulationStarted;
+use crate::listeners::events::summary::Summary;
+use crate::listeners::listener::{Listener, Listeners};
 use crate::random_wrapper::RandomWrapper;
 
 pub struct Epidemiology {
@@ -46,6 +50,24 @@ pub struct Epidemiology {
     pub grid: Grid,
     pub disease: Disease,
     pub sim_id: String,
+    pub contact_network: Option<ContactNetwork>,
+    pub contact_matrices: ContactMatrices,
+    rng: RandomWrapper,
+}
+
+/// State that lives for the duration of a single run of the simulation
+pub struct SimulationRun {
+    listeners: Listeners,
+    pub counts_at_hr: Counts,
+    pub rng: RandomWrapper,
+    pub summary: Summary,
+    started_at: Instant,
+    vaccinations: HashMap<i32, f64>,
+    lock_down_details: Option<Lockdown>,
+    hospital_intervention: Option<BuildNewHospital>,
+    infection_count_for_yesterday: i32,
+    city_to_be_locked_till: i32,
+    is_city_locked_down: bool,
 }
 
 impl Epidemiology {
@@ -53,116 +75,219 @@ impl Epidemiology {
         let start = Instant::now();
         let disease = config.get_disease();
         let grid = geography::define_geography(config.get_grid_size());
-        let mut rng = RandomWrapper::new();
-        let (start_locations, agent_list) = match config.get_population() {
+        let mut rng = RandomWrapper::with_seed(config.get_seed());
+        let phase_start = Epidemiology::phase_completed("Defined the geography", start);
+
+        let (start_locations, mut agent_list) = match config.get_population() {
             Population::Csv(csv_pop) => grid.read_population(&csv_pop, &mut rng),
             Population::Auto(auto_pop) => grid.generate_population(&auto_pop, &mut rng),
         };
+        let course_sampler = disease.course_sampler();
+        for citizen in agent_list.iter_mut() {
+            citizen.draw_course(&course_sampler, &mut rng);
+        }
+        let mut phase_start = Epidemiology::phase_completed("Created the population", phase_start);
+
+        let contact_network = match config.get_transmission() {
+            Transmission::Network(network_config) => {
+                let network = ContactNetwork::generate(&network_config, &mut agent_list, &mut rng);
+                phase_start = Epidemiology::phase_completed("Generated the contact network", phase_start);
+                Some(network)
+            }
+            Transmission::Grid => None,
+        };
+
+        let contact_matrices = if config.get_contact_matrices().is_empty() {
+            ContactMatrices::default()
+        } else {
+            let matrices = ContactMatrices::read(config.get_contact_matrices())
+                .unwrap_or_else(|e| panic!("Could not read the contact matrices: {}", e));
+            phase_start = Epidemiology::phase_completed("Read the contact matrices", phase_start);
+            matrices
+        };
+
+        if let Some(file) = config.get_grid_svg() {
+            grid.draw(&file, &agent_list, &start_locations);
+            phase_start = Epidemiology::phase_completed(&format!("Drew the grid to {}", file), phase_start);
+        }
 
         let agent_location_map = allocation_map::AgentLocationMap::new(config.get_grid_size(), &agent_list, &start_locations);
         let write_agent_location_map = allocation_map::AgentLocationMap::new(config.get_grid_size(), &agent_list, &start_locations);
+        Epidemiology::phase_completed("Placed the citizens on the grid", phase_start);
 
         println!("Initialization completed in {} seconds", start.elapsed().as_secs_f32());
-        Epidemiology { agent_location_map, write_agent_location_map, grid, disease, sim_id }
+        Epidemiology { agent_location_map, write_agent_location_map, grid, disease, sim_id, contact_network,
+                       contact_matrices, rng }
+    }
+
+    /// A generator drawn from the seeded one of the simulation, for choices made outside it such as who travels
+    pub fn split_rng(&mut self) -> RandomWrapper {
+        self.rng.split()
+    }
+
+    /// Reports how long a phase of the initialization took, and returns the start of the next one
+    fn phase_completed(phase: &str, phase_start: Instant) -> Instant {
+        println!("{} in {} seconds", phase, phase_start.elapsed().as_secs_f32());
+        Instant::now()
     }
 
-    fn stop_simulation(row: Counts) -> bool {
+    pub fn stop_simulation(row: Counts) -> bool {
         row.get_infected() == 0 && row.get_quarantined() == 0
     }
 
-    pub fn run(&mut self, config: &Config) {
+    /// Listeners for the `outputs` in the config
+    pub fn create_listeners(&self, config: &Config, kafka_config: &KafkaConfig) -> Vec<Box<dyn Listener>> {
         let now: DateTime<Local> = SystemTime::now().into();
-        let output_file_prefix = config.get_output_file().unwrap_or("simulation".to_string());
-        let output_file_name = format!("{}_{}.csv", output_file_prefix, now.format("%Y-%m-%dT%H:%M:%S"));
-        let csv_listener = CsvListener::new(output_file_name);
-        let kafka_listener = KafkaProducer::new(self.sim_id.clone(), self.agent_location_map.agent_cell.len(),
-                                                config.enable_citizen_state_messages());
-        let hotspot_tracker = Hotspot::new();
-        let mut listeners = Listeners::from(vec![Box::new(csv_listener), Box::new(kafka_listener), Box::new(hotspot_tracker)]);
-
-        let mut counts_at_hr = Counts::new((self.agent_location_map.agent_cell.len() - 1) as i32, 1);
-        let mut rng = RandomWrapper::new();
-        let start_time = Instant::now();
+        let population_size = self.agent_location_map.len();
+        config.get_outputs().iter()
+            .map(|output| output.create_listener(config, &self.sim_id, population_size, now, kafka_config))
+            .collect()
+    }
 
-        self.write_agent_location_map.agent_cell = FxHashMap::with_capacity_and_hasher(self.agent_location_map.agent_cell.len(), FxBuildHasher::default());
+    pub fn start_run(&mut self, config: &Config, listeners: Listeners) -> SimulationRun {
+        let counts_at_hr = Counts::new((self.agent_location_map.len() - 1) as i32, 1);
+        self.write_agent_location_map.clear();
+
+        let mut run = SimulationRun {
+            listeners,
+            counts_at_hr,
+            rng: self.rng.split(),
+            summary: Summary::new(),
+            started_at: Instant::now(),
+            vaccinations: Epidemiology::prepare_vaccinations(config),
+            lock_down_details: Intervention::get_lock_down_intervention(config),
+            hospital_intervention: Intervention::get_hospital_intervention(config),
+            infection_count_for_yesterday: 0,
+            city_to_be_locked_till: 0,
+            is_city_locked_down: false,
+        };
+        run.listeners.simulation_started(&SimulationStarted {
+            sim_id: &self.sim_id,
+            config,
+            population: self.agent_location_map.len(),
+            started_at: Local::now(),
+        });
+        run.listeners.grid_updated(&self.grid);
+        run
+    }
 
-        let vaccinations = Epidemiology::prepare_vaccinations(config);
-        let lock_down_details = Intervention::get_lock_down_intervention(config);
-        let hospital_intervention = Intervention::get_hospital_intervention(config);
-        let mut infection_count_for_yesterday = 0;
-        let mut city_to_be_locked_till: i32 = 0;
-        let mut is_city_locked_down = false;
+    pub fn run_hour(&mut self, config: &Config, run: &mut SimulationRun, simulation_hour: i32) -> Counts {
+        run.counts_at_hr.increment_hour();
+        run.listeners.hour_started(simulation_hour);
+        let start_of_day = simulation_hour % 24 == 0;
 
-        listeners.grid_updated(&self.grid);
+        let mut read_buffer_reference = self.agent_location_map.borrow();
+        let mut write_buffer_reference = self.write_agent_location_map.borrow_mut();
 
-        for simulation_hour in 1..config.get_hours() {
-            counts_at_hr.increment_hour();
-            let start_of_day = simulation_hour % 24 == 0;
+        if simulation_hour % 2 == 0 {
+            read_buffer_reference = self.write_agent_location_map.borrow();
+            write_buffer_reference = self.agent_location_map.borrow_mut();
+        }
 
-            let mut read_buffer_reference = self.agent_location_map.borrow();
-            let mut write_buffer_reference = self.write_agent_location_map.borrow_mut();
+        if start_of_day {
+            let rate_of_spread = run.counts_at_hr.get_infected() - run.infection_count_for_yesterday;
+            match run.hospital_intervention {
+                Some(x) if rate_of_spread >= x.spread_rate_threshold => {
+                    println!("Increasing the hospital size");
+                    self.grid.increase_hospital_size(config.get_grid_size());
 
-            if simulation_hour % 2 == 0 {
-                read_buffer_reference = self.write_agent_location_map.borrow();
-                write_buffer_reference = self.agent_location_map.borrow_mut();
+                    run.listeners.grid_updated(&self.grid);
+                }
+                _ => {}
             }
+        }
 
-            if start_of_day {
-                let rate_of_spread = counts_at_hr.get_infected() - infection_count_for_yesterday;
-                match hospital_intervention {
-                    Some(x) if rate_of_spread >= x.spread_rate_threshold => {
-                        println!("Increasing the hospital size");
-                        self.grid.increase_hospital_size(config.get_grid_size());
-
-                        listeners.grid_updated(&self.grid);
-                    }
-                    _ => {}
-                }
+        if let Some(network) = self.contact_network.as_mut() {
+            network.update_transmission_rates(read_buffer_reference, &self.disease);
+        }
+        let mixing = Mixing::new(self.contact_network.as_ref(), &self.contact_matrices,
+                                 config.get_transmission_multipliers());
+        Epidemiology::simulate(&mut run.counts_at_hr, simulation_hour, read_buffer_reference, write_buffer_reference,
+                               &self.grid, mixing, &mut run.listeners, &mut run.rng, &self.disease);
+        run.listeners.counts_updated(run.counts_at_hr);
+        run.summary.counts_updated(&run.counts_at_hr);
+
+        match run.lock_down_details {
+            Some(x) if Epidemiology::should_lock_city(&run.counts_at_hr, run.is_city_locked_down, x) => {
+                Epidemiology::lock_city(write_buffer_reference, &mut run.rng, &x);
+                run.is_city_locked_down = true;
+                run.city_to_be_locked_till = simulation_hour + x.lock_down_period * constants::NUMBER_OF_HOURS;
             }
+            _ => {}
+        }
 
-            Epidemiology::simulate(&mut counts_at_hr, simulation_hour, read_buffer_reference, write_buffer_reference,
-                                   &self.grid, &mut listeners, &mut rng, &self.disease);
-            listeners.counts_updated(counts_at_hr);
+        if run.is_city_locked_down && run.city_to_be_locked_till == simulation_hour {
+            Epidemiology::unlock_city(write_buffer_reference);
+        }
+        if run.is_city_locked_down && simulation_hour < run.city_to_be_locked_till {
+            run.summary.lockdown_hour();
+        }
 
-            match lock_down_details{
-                Some(x) if Epidemiology::should_lock_city(&counts_at_hr, is_city_locked_down, x) =>{
-                    Epidemiology::lock_city(&mut write_buffer_reference, &mut rng, &x);
-                    is_city_locked_down = true;
-                    city_to_be_locked_till = simulation_hour + x.lock_down_period * constants::NUMBER_OF_HOURS;
-                }
-                _ => {}
-            }
+        if let Some(vac_percent) = run.vaccinations.get(&simulation_hour) {
+            println!("Vaccination");
+            let vaccinated = Epidemiology::vaccinate(*vac_percent, write_buffer_reference, &mut run.rng);
+            run.summary.vaccinated(vaccinated);
+        }
 
-            if is_city_locked_down && city_to_be_locked_till == simulation_hour{
-                Epidemiology::unlock_city(&mut write_buffer_reference);
-            }
+        if start_of_day {
+            run.infection_count_for_yesterday = run.counts_at_hr.get_infected();
+        }
+        run.listeners.hour_ended(simulation_hour);
+        run.counts_at_hr
+    }
 
-            match vaccinations.get(&simulation_hour) {
-                Some(vac_percent) => {
-                    println!("Vaccination");
-                    Epidemiology::vaccinate(*vac_percent, &mut write_buffer_reference, &mut rng);
-                }
-                _ => {}
-            };
+    pub fn end_run(run: &mut SimulationRun) {
+        run.summary.finish(run.started_at.elapsed());
+        run.listeners.simulation_ended(&run.summary);
+    }
 
-            if Epidemiology::stop_simulation(counts_at_hr) {
-                break;
-            }
+    /// The location map holding citizens as of the end of the given simulation hour
+    pub fn current_location_map(&mut self, simulation_hour: i32) -> &mut AgentLocationMap {
+        if simulation_hour % 2 == 0 {
+            return &mut self.agent_location_map;
+        }
+        &mut self.write_agent_location_map
+    }
 
-            if simulation_hour % 100 == 0 {
-                println!("Throughput: {} iterations/sec; simulation hour {} of {}",
-                         simulation_hour as f32 / start_time.elapsed().as_secs_f32(),
-                         simulation_hour, config.get_hours());
-            }
+    /// Picks citizens who are free to travel out of the region, skipping cells that were already picked
+    pub fn pick_travellers(&mut self, simulation_hour: i32, fraction: f64, picked: &FxHashSet<Point>,
+                           rng: &mut RandomWrapper) -> Vec<Point> {
+        let map = self.current_location_map(simulation_hour);
+        let number_of_travellers = (map.len() as f64 * fraction).round() as usize;
+        map.iter()
+            .filter(|(cell, citizen)| citizen.can_move() && !picked.contains(cell))
+            .map(|(cell, _)| *cell)
+            .choose_multiple(rng.get(), number_of_travellers)
+    }
 
-            if start_of_day {
-                infection_count_for_yesterday = counts_at_hr.get_infected();
-            }
+    pub fn depart(&mut self, counts: &mut Counts, simulation_hour: i32, cell: &Point) -> Citizen {
+        let citizen = self.current_location_map(simulation_hour).remove(cell)
+            .expect("No citizen to depart from the cell");
+        counts.update_for_state(citizen.state_machine.state, -1);
+        citizen
+    }
+
+    pub fn find_vacant_home(&mut self, simulation_hour: i32, rng: &mut RandomWrapper) -> Option<Point> {
+        let housing_area = self.grid.housing_area;
+        let map = self.current_location_map(simulation_hour);
+        let random_vacant_cell = (0..constants::MAX_ATTEMPTS_TO_FIND_VACANT_CELL)
+            .map(|_| housing_area.get_random_point(rng))
+            .find(|cell| map.is_cell_vacant(cell));
+        random_vacant_cell.or_else(|| housing_area.into_iter().find(|cell| map.is_cell_vacant(cell)))
+    }
+
+    /// Gives a citizen arriving from another region a home and workplace here, and contacts if the infection spreads
+    /// over a contact network, and places them at the cell
+    pub fn settle(&mut self, counts: &mut Counts, simulation_hour: i32, mut citizen: Citizen, cell: Point,
+                  rng: &mut RandomWrapper) {
+        let home = *self.grid.houses.choose(rng.get()).unwrap();
+        let office = *self.grid.offices.choose(rng.get()).unwrap();
+        citizen.relocate(home, office);
+        if let Some(network) = self.contact_network.as_mut() {
+            network.join(&mut citizen, rng);
         }
-        let elapsed_time = start_time.elapsed().as_secs_f32();
-        println!("Number of iterations: {}, Total Time taken {} seconds", counts_at_hr.get_hour(), elapsed_time);
-        println!("Iterations/sec: {}", counts_at_hr.get_hour() as f32 / elapsed_time);
-        listeners.simulation_ended();
+        counts.update_for_state(citizen.state_machine.state, 1);
+        self.current_location_map(simulation_hour).insert(cell, citizen);
     }
 
     fn should_lock_city(counts_at_hr: &Counts, is_city_locked_down: bool, x: Lockdown) -> bool {
@@ -182,40 +307,45 @@ impl Epidemiology {
         vaccinations
     }
 
-    fn vaccinate(vaccination_percentage: f64, write_buffer_reference: &mut AgentLocationMap, rng: &mut RandomWrapper) {
-        for (_v, agent) in write_buffer_reference.agent_cell.iter_mut() {
+    /// Returns the number of citizens vaccinated
+    fn vaccinate(vaccination_percentage: f64, write_buffer_reference: &mut AgentLocationMap, rng: &mut RandomWrapper) -> i32 {
+        let mut vaccinated = 0;
+        for agent in write_buffer_reference.citizens_mut() {
             if agent.is_susceptible() && rng.get().gen_bool(vaccination_percentage) {
                 agent.set_vaccination(true);
+                vaccinated += 1;
             }
         }
+        vaccinated
     }
 
-    fn simulate(mut csv_record: &mut Counts, simulation_hour: i32, read_buffer: &AgentLocationMap,
-                write_buffer: &mut AgentLocationMap, grid: &Grid, listeners: &mut Listeners,
+    #[allow(clippy::too_many_arguments)]
+    fn simulate(csv_record: &mut Counts, simulation_hour: i32, read_buffer: &AgentLocationMap,
+                write_buffer: &mut AgentLocationMap, grid: &Grid, mixing: Mixing, listeners: &mut Listeners,
                 rng: &mut RandomWrapper, disease: &Disease) {
-        write_buffer.agent_cell.clear();
-        for (cell, agent) in read_buffer.agent_cell.iter() {
+        write_buffer.clear();
+        for (cell, agent) in read_buffer.iter() {
             let mut current_agent = *agent;
             let infection_status = current_agent.is_infected();
-            let point = current_agent.perform_operation(*cell, simulation_hour, &grid, read_buffer, &mut csv_record, rng, disease);
+            let point = current_agent.perform_operation(*cell, simulation_hour, grid, read_buffer, mixing, csv_record,
+                                                        rng, disease);
 
-            if infection_status == false && current_agent.is_infected() == true {
-                listeners.citizen_got_infected(&cell);
+            if !infection_status && current_agent.is_infected() {
+                listeners.citizen_got_infected(cell);
             }
 
-            let agent_option = write_buffer.agent_cell.get(&point);
-            let new_location = match agent_option {
-                Some(mut _agent) => cell, //occupied
-                _ => &point
+            let new_location = if write_buffer.is_cell_vacant(&point) { &point } else {
+                current_agent.stay_in_area_of(agent);
+                cell
             };
-            write_buffer.agent_cell.insert(*new_location, current_agent);
+            write_buffer.insert(*new_location, current_agent);
             listeners.citizen_state_updated(simulation_hour, &current_agent, new_location);
         }
     }
 
     fn lock_city(write_buffer_reference: &mut AgentLocationMap, rng: &mut RandomWrapper, lockdown_details: &Lockdown) {
         println!("Locking the city");
-        for (_v, agent) in write_buffer_reference.agent_cell.iter_mut() {
+        for agent in write_buffer_reference.citizens_mut() {
             if rng.get().gen_bool(1.0 - lockdown_details.essential_workers_population) {
                 agent.set_isolation(true);
             }
@@ -224,7 +354,7 @@ impl Epidemiology {
 
     fn unlock_city(write_buffer_reference: &mut AgentLocationMap) {
         println!("unlocking city");
-        for (_v, agent) in write_buffer_reference.agent_cell.iter_mut() {
+        for agent in write_buffer_reference.citizens_mut() {
             if agent.is_isolated() {
                 agent.set_isolation(false);
             }
@@ -234,7 +364,7 @@ impl Epidemiology {
 
 #[cfg(test)]
 mod tests {
-    use crate::config::AutoPopulation;
+    use crate::config::{AutoPopulation, CsvPopulation};
     use crate::geography::Area;
     use crate::geography::Point;
     use crate::interventions::Vaccinate;
@@ -247,6 +377,7 @@ mod tests {
             number_of_agents: 10,
             public_transport_percentage: 1.0,
             working_percentage: 1.0,
+            age_distribution: None,
         };
         let disease = Disease::new(0, 0, 0, 0.0, 0.0, 0.0);
         let vac = Vaccinate {
@@ -268,6 +399,64 @@ mod tests {
         let expected_hospital_area = Area::new(Point::new(70, 0), Point::new(80, 100));
         assert_eq!(epidemiology.grid.hospital_area, expected_hospital_area);
 
-        assert_eq!(epidemiology.agent_location_map.agent_cell.len(), 10);
+        assert_eq!(epidemiology.agent_location_map.len(), 10);
+    }
+
+    #[test]
+    fn should_run_a_csv_population_whose_offices_and_hospital_reach_past_the_grid() {
+        let file = std::env::temp_dir().join("epirust_csv_population_test.csv");
+        let mut rows = vec!["ind,age,sex,working,pub_transport".to_string()];
+        rows.extend((0..200).map(|i| format!("{},30-34,F,True,{}", i, if i % 2 == 0 { "True" } else { "False" })));
+        std::fs::write(&file, rows.join("\n")).unwrap();
+        let pop = CsvPopulation {
+            file: file.to_str().unwrap().to_string(),
+            cols: vec!["age".to_string(), "sex".to_string(), "working".to_string(), "pub_transport".to_string()],
+        };
+        let disease = Disease::new(0, 0, 10, 0.5, 0.5, 0.0);
+        let hospital = BuildNewHospital { spread_rate_threshold: 0 };
+        let config = Config::new(Population::Csv(pop), disease, vec![], 100, 72,
+                                 vec![Intervention::BuildNewHospital(hospital)], None);
+        let mut epidemiology = Epidemiology::new(&config, "id".to_string());
+        assert!(epidemiology.agent_location_map.citizens().any(|citizen| citizen.work_location.end_offset.x >= 100));
+
+        let mut run = epidemiology.start_run(&config, Listeners::from(vec![]));
+        let mut placed_past_the_grid = false;
+        for simulation_hour in 1..=72 {
+            epidemiology.run_hour(&config, &mut run, simulation_hour);
+            let map = epidemiology.current_location_map(simulation_hour);
+            placed_past_the_grid |= map.iter().any(|(cell, _)| !map.is_point_in_grid(cell));
+        }
+
+        assert_eq!(epidemiology.grid.hospital_area.end_offset, Point::new(100, 100));
+        assert!(placed_past_the_grid);
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn should_keep_area_of_citizen_whose_move_is_taken_by_another() {
+        let mut rng = RandomWrapper::new();
+        let office = Area::new(Point::new(5, 5), Point::new(6, 6));
+        let first_home = Area::new(Point::new(0, 0), Point::new(1, 1));
+        let second_home = Area::new(Point::new(0, 3), Point::new(1, 4));
+        let citizens = vec![Citizen::new_citizen(1, first_home, office, Point::new(0, 0), false, true, &mut rng),
+                            Citizen::new_citizen(2, second_home, office, Point::new(0, 3), false, true, &mut rng)];
+        let cells = vec![Point::new(0, 0), Point::new(0, 3)];
+        let read_buffer = AgentLocationMap::new(10, &citizens, &cells);
+        let mut write_buffer = AgentLocationMap::new(10, &citizens, &cells);
+        let grid = geography::define_geography(10);
+        let matrices = ContactMatrices::default();
+        let multipliers = Default::default();
+        let disease = Disease::new(0, 0, 0, 0.0, 0.0, 0.0);
+
+        Epidemiology::simulate(&mut Counts::new(2, 0), constants::ROUTINE_WORK_TIME, &read_buffer, &mut write_buffer,
+                               &grid, Mixing::new(None, &matrices, &multipliers), &mut Listeners::from(vec![]),
+                               &mut rng, &disease);
+
+        let at_work = write_buffer.get_agent_for(&Point::new(5, 5)).unwrap();
+        assert_eq!(at_work.get_current_area(), office);
+        let blocked = write_buffer.citizens().find(|citizen| citizen.id != at_work.id).unwrap();
+        assert_eq!(blocked.get_current_area(), blocked.home_location);
+        assert_eq!(write_buffer.iter().find(|(_, citizen)| citizen.id == blocked.id).unwrap().0,
+                   &blocked.home_location.start_offset);
     }
 }
@@ -18,12 +18,13 @@
  */
 
 use rand::Rng;
+use rand::seq::index;
 
 use crate::geography::Point;
 use crate::random_wrapper::RandomWrapper;
 use std::slice::IterMut;
 
-#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize)]
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Area {
     pub start_offset: Point,
     pub end_offset: Point,
@@ -41,25 +42,19 @@ impl Area {
         })
     }
 
-    //TODO improve randomness
+    /// Distinct points picked at random from the area. The points are drawn as indices into the area, so the time taken
+    /// grows with the number of points rather than its square.
     pub fn random_points(&self, number_of_points: i32, rng: &mut RandomWrapper) -> Vec<Point> {
-        let mut points: Vec<Point> = Vec::with_capacity(number_of_points as usize);
-        let rng = rng.get();
-        while points.len() != (number_of_points as usize) {
-            let rand_x = rng.gen_range(self.start_offset.x, self.end_offset.x);
-            let rand_y = rng.gen_range(self.start_offset.y, self.end_offset.y);
-            let mut is_duplicate = false;
-            for point in points.iter_mut() {
-                if *point == (Point::new(rand_x, rand_y)) {
-//                println!("Duplicate");
-                    is_duplicate = true;
-                }
-            }
-            if !is_duplicate {
-                points.push(Point { x: rand_x, y: rand_y });
-            }
-        }
-        points
+        let width = self.end_offset.x - self.start_offset.x;
+        index::sample(rng.get(), self.random_points_capacity() as usize, number_of_points as usize)
+            .into_iter()
+            .map(|i| Point::new(self.start_offset.x + i as i32 % width, self.start_offset.y + i as i32 / width))
+            .collect()
+    }
+
+    /// The number of distinct points `random_points` can pick from
+    pub fn random_points_capacity(&self) -> i64 {
+        (self.end_offset.x - self.start_offset.x) as i64 * (self.end_offset.y - self.start_offset.y) as i64
     }
 
     pub fn get_random_point(&self, rng: &mut RandomWrapper) -> Point {
@@ -75,6 +70,13 @@ impl Area {
     }
 }
 
+/// The number of areas `area_factory` fits between the two points
+pub fn area_count(start_point: Point, end_point: Point, size: i32) -> i64 {
+    let feasible_houses_in_x_dim = (end_point.x - start_point.x) / size;
+    let feasible_houses_in_y_dim = (end_point.y - start_point.y) / size;
+    feasible_houses_in_x_dim as i64 * feasible_houses_in_y_dim as i64
+}
+
 pub fn area_factory(start_point: Point, end_point: Point, size: i32) -> Vec<Area> {
     let feasible_houses_in_x_dim = (end_point.x - start_point.x) / size;
     let feasible_houses_in_y_dim = (end_point.y - start_point.y) / size;
@@ -152,6 +154,8 @@ impl Iterator for AreaPointIterator<'_> {
 
 #[cfg(test)]
 mod tests {
+    use fxhash::FxHashSet;
+
     use super::*;
 
     fn get_area() -> Area {
@@ -166,6 +170,16 @@ mod tests {
         assert_eq!(points.len(), 10);
     }
 
+    #[test]
+    fn should_generate_distinct_points_within_area() {
+        let area = Area::new(Point::new(3, 2), Point::new(7, 5));
+        let points = area.random_points(12, &mut RandomWrapper::new());
+
+        let distinct: FxHashSet<Point> = points.iter().cloned().collect();
+        assert_eq!(distinct.len(), 12);
+        assert!(points.iter().all(|p| p.x >= 3 && p.x < 7 && p.y >= 2 && p.y < 5));
+    }
+
     #[test]
     fn should_iterate_over_points_in_area() {
         let area = Area::new(Point { x: 0, y: 0 }, Point { x: 2, y: 2 });
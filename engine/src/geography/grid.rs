@@ -17,13 +17,16 @@
  *
  */
 
+use fxhash::FxHashMap;
 use plotters::prelude::*;
 
 use crate::{agent, constants};
+use crate::age::AgeDistribution;
 use crate::agent::{Citizen, PopulationRecord};
 use crate::config::{AutoPopulation, CsvPopulation};
-use crate::geography::{Area, area, Point};
+use crate::geography::{Area, area, Point, transport_locations_needed};
 use crate::random_wrapper::RandomWrapper;
+use crate::setting::Setting;
 use std::fs::File;
 use crate::geography::area::AreaPointIterator;
 
@@ -44,29 +47,41 @@ impl Grid {
         let working_percentage = auto_pop.working_percentage;
         let public_transport_percentage = auto_pop.public_transport_percentage;
 
-        //        TODO: fix the hack
-        let number_of_agents_using_public_transport = number_of_agents as f64 * (public_transport_percentage + 0.1) * (working_percentage + 0.1);
+        let number_of_agents_using_public_transport = transport_locations_needed(
+            number_of_agents, public_transport_percentage, working_percentage);
 
         let home_locations = self.housing_area.random_points(number_of_agents as i32, rng);
 
-        let transport_locations = self.transport_area.random_points(number_of_agents_using_public_transport.ceil() as i32, rng);
+        let transport_locations = self.transport_area.random_points(number_of_agents_using_public_transport as i32, rng);
 
-        let agent_list = agent::citizen_factory(number_of_agents, &self.houses, &self.offices, &transport_locations, public_transport_percentage, working_percentage, rng);
+        let mut agent_list = agent::citizen_factory(number_of_agents, &self.houses, &self.offices, &transport_locations, public_transport_percentage, working_percentage, rng);
 
-        self.draw(&home_locations, &self.houses, &self.offices);
+        if let Some(shares) = &auto_pop.age_distribution {
+            let age_distribution = AgeDistribution::new(shares).expect("Invalid age distribution");
+            for citizen in agent_list.iter_mut() {
+                citizen.age = age_distribution.random_age(rng);
+            }
+        }
         (home_locations, agent_list)
     }
 
-    fn draw(&self, home_locations: &Vec<Point>, homes: &Vec<Area>, offices: &Vec<Area>) {
-        let mut svg = SVGBackend::new("grid.svg", (self.grid_size as u32, self.grid_size as u32));
+    /// Draws the areas of the grid, every house and office in it, and a pixel at each of the home locations of the
+    /// citizens. The offices of a population read from a CSV file lie beyond the hospital, so those are drawn as well.
+    pub fn draw(&self, file: &str, citizens: &[Citizen], home_locations: &[Point]) {
+        let offices_beyond_hospital: FxHashMap<Point, Area> = citizens.iter()
+            .filter(|c| c.work_location.start_offset.x > self.hospital_area.end_offset.x)
+            .map(|c| (c.work_location.start_offset, c.work_location))
+            .collect();
+
+        let mut svg = SVGBackend::new(file, (self.grid_size as u32, self.grid_size as u32));
         Grid::draw_rect(&mut svg, &self.housing_area, &plotters::style::YELLOW);
         Grid::draw_rect(&mut svg, &self.transport_area, &plotters::style::RGBColor(121, 121, 121));
         Grid::draw_rect(&mut svg, &self.work_area, &plotters::style::BLUE);
         Grid::draw_rect(&mut svg, &self.hospital_area, &plotters::style::RED);
-        for home in homes {
+        for home in &self.houses {
             Grid::draw_rect(&mut svg, home, &plotters::style::RGBColor(204, 153, 0));
         }
-        for office in offices {
+        for office in self.offices.iter().chain(offices_beyond_hospital.values()) {
             Grid::draw_rect(&mut svg, office, &plotters::style::RGBColor(51, 153, 255));
         }
         for home in home_locations {
@@ -105,10 +120,23 @@ impl Grid {
             citizens.push(citizen);
             home_loc.push(home_point);
         }
-        self.draw(&home_loc, &homes, &offices);
         (home_loc, citizens)
     }
 
+    /// The setting of an area of the grid, going by where it starts, so that a house or an office counts as home or
+    /// work, and a hospital that has grown since a citizen was taken to it still counts as the hospital
+    pub fn setting_of(&self, area: &Area) -> Setting {
+        if self.hospital_area.contains(&area.start_offset) {
+            Setting::Hospital
+        } else if self.work_area.contains(&area.start_offset) {
+            Setting::Work
+        } else if self.transport_area.contains(&area.start_offset) {
+            Setting::Transport
+        } else {
+            Setting::Home
+        }
+    }
+
     pub fn increase_hospital_size(&mut self, grid_size: i32) {
         let start_offset = self.hospital_area.start_offset;
         let end_offset = Point::new(grid_size, grid_size);
@@ -135,6 +163,7 @@ mod tests {
             number_of_agents: 10,
             public_transport_percentage: 0.2,
             working_percentage: 0.2,
+            age_distribution: None,
         };
         let (home_locations, agent_list) = grid.generate_population(&pop, &mut rng);
 
@@ -150,6 +179,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn should_give_citizens_ages_from_distribution() {
+        let grid = define_geography(100);
+        let shares = vec![("5-9".to_string(), 1.0), ("70+".to_string(), 1.0)].into_iter().collect();
+        let pop = AutoPopulation {
+            number_of_agents: 50,
+            public_transport_percentage: 0.2,
+            working_percentage: 0.2,
+            age_distribution: Some(shares),
+        };
+
+        let (_, agent_list) = grid.generate_population(&pop, &mut RandomWrapper::new());
+
+        assert!(agent_list.iter().all(|c| (5..=9).contains(&c.age) || (70..=74).contains(&c.age)));
+        assert!(agent_list.iter().any(|c| c.age >= 70));
+    }
+
+    #[test]
+    fn should_classify_areas_by_setting() {
+        let mut grid = define_geography(100);
+        let hospital = grid.hospital_area;
+        grid.increase_hospital_size(100);
+
+        assert!(grid.houses.iter().all(|house| grid.setting_of(house) == Setting::Home));
+        assert!(grid.offices.iter().all(|office| grid.setting_of(office) == Setting::Work));
+        assert_eq!(grid.setting_of(&grid.transport_area), Setting::Transport);
+        assert_eq!(grid.setting_of(&hospital), Setting::Hospital);
+        assert_eq!(grid.setting_of(&grid.hospital_area), Setting::Hospital);
+    }
+
+    #[test]
+    fn should_draw_grid_to_given_file() {
+        let mut rng = RandomWrapper::new();
+        let grid = define_geography(100);
+        let pop = AutoPopulation {
+            number_of_agents: 10,
+            public_transport_percentage: 0.2,
+            working_percentage: 0.2,
+            age_distribution: None,
+        };
+        let (home_locations, agent_list) = grid.generate_population(&pop, &mut rng);
+        let file_name = std::env::temp_dir().join("epirust_grid_test.svg");
+
+        grid.draw(file_name.to_str().unwrap(), &agent_list, &home_locations);
+
+        let svg = std::fs::read_to_string(&file_name).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("width=\"100\""));
+        assert_eq!(svg.matches("<rect").count(), 4 + grid.houses.len() + grid.offices.len() + home_locations.len());
+        std::fs::remove_file(file_name).unwrap();
+    }
+
     #[test]
     fn should_increase_hospital_size() {
         let mut grid = define_geography(100);
@@ -28,6 +28,40 @@ pub use self::grid::Grid;
 pub use self::point::Point;
 
 pub fn define_geography(grid_size: i32) -> Grid {
+    let (housing_area, transport_area, work_area, hospital_area) = layout(grid_size);
+
+    let houses = area::area_factory(housing_area.start_offset, housing_area.end_offset, constants::HOME_SIZE);
+    let offices = area::area_factory(work_area.start_offset, work_area.end_offset, constants::OFFICE_SIZE);
+
+    Grid { grid_size, housing_area, transport_area, hospital_area, work_area, houses, offices }
+}
+
+/// What a grid of the given size can hold, worked out without building it
+pub struct Capacity {
+    pub home_locations: i64,
+    pub transport_locations: i64,
+    pub houses: i64,
+    pub offices: i64,
+}
+
+pub fn capacity(grid_size: i32) -> Capacity {
+    let (housing_area, transport_area, work_area, _hospital_area) = layout(grid_size);
+    Capacity {
+        home_locations: housing_area.random_points_capacity(),
+        transport_locations: transport_area.random_points_capacity(),
+        houses: area::area_count(housing_area.start_offset, housing_area.end_offset, constants::HOME_SIZE),
+        offices: area::area_count(work_area.start_offset, work_area.end_offset, constants::OFFICE_SIZE),
+    }
+}
+
+/// The transport locations a population gets, which is the same number whether the population is checked or built
+pub fn transport_locations_needed(number_of_agents: i32, public_transport_percentage: f64, working_percentage: f64) -> i64 {
+    //        TODO: fix the hack
+    (number_of_agents as f64 * (public_transport_percentage + 0.1) * (working_percentage + 0.1)).ceil() as i64
+}
+
+/// The housing, transport, work and hospital areas, from left to right
+fn layout(grid_size: i32) -> (Area, Area, Area, Area) {
     let home_width = (grid_size as f32 * constants::HOUSE_AREA_RELATIVE_SIZE).ceil() as i32;
     let transport_start = home_width;
     let transport_end = home_width + (grid_size as f32 * constants::TRANSPORT_AREA_RELATIVE_SIZE).ceil() as i32;
@@ -40,11 +74,7 @@ pub fn define_geography(grid_size: i32) -> Grid {
     let transport_area = Area::new(Point::new(transport_start, 0), Point::new(transport_end, grid_size));
     let work_area = Area::new(Point::new(work_area_start, 0), Point::new(work_area_end, grid_size));
     let hospital_area = Area::new(Point::new(hospital_start, 0), Point::new(hospital_end, grid_size));
-
-    let houses = area::area_factory(housing_area.start_offset, housing_area.end_offset, constants::HOME_SIZE);
-    let offices = area::area_factory(work_area.start_offset, work_area.end_offset, constants::OFFICE_SIZE);
-
-    Grid { grid_size, housing_area, transport_area, hospital_area, work_area, houses, offices }
+    (housing_area, transport_area, work_area, hospital_area)
 }
 
 #[cfg(test)]
@@ -66,4 +96,21 @@ mod tests {
         assert_eq!(grid.hospital_area.start_offset, Point::new(7,0));
         assert_eq!(grid.hospital_area.end_offset, Point::new(8,10));
     }
+
+    #[test]
+    fn should_match_capacity_with_geography() {
+        let grid = define_geography(100);
+        let capacity = capacity(100);
+
+        assert_eq!(capacity.houses, grid.houses.len() as i64);
+        assert_eq!(capacity.offices, grid.offices.len() as i64);
+        assert_eq!(capacity.home_locations, 40 * 100);
+        assert_eq!(capacity.transport_locations, 10 * 100);
+    }
+
+    #[test]
+    fn should_round_up_transport_locations_needed() {
+        assert_eq!(transport_locations_needed(100, 0.4, 0.4), 25);
+        assert_eq!(transport_locations_needed(10, 0.0, 0.0), 1);
+    }
 }
\ No newline at end of file
@@ -19,7 +19,7 @@
 
 use std::ops::Add;
 
-#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize)]
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
@@ -0,0 +1,607 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::stream::{self, BoxStream};
+use futures::{future, StreamExt};
+use hyper::header::{ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE, HeaderValue, InvalidHeaderValue};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use tokio::sync::{broadcast, Semaphore};
+use tokio::task;
+
+use crate::agent::Citizen;
+use crate::config::Config;
+use crate::constants;
+use crate::geography::Point;
+use crate::listeners::events::citizen_state::CitizenStatesAtHr;
+use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary::Summary;
+use crate::listeners::listener::Listener;
+use crate::run_control::{panic_message, RunControl, Status};
+use crate::simulation::Simulation;
+
+/// The events a client can fall behind on before it starts missing some
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Runs simulations submitted over HTTP, and serves their status and results:
+///
+/// - `POST /simulations` starts a simulation of the config in the body, with an optional `sim_id` next to the config
+/// - `GET /simulations` lists the simulations and their status
+/// - `GET /simulations/{sim_id}` returns the status of a simulation, its counts so far and its summary once it ended
+/// - `DELETE /simulations/{sim_id}` cancels a simulation
+/// - `GET /simulations/{sim_id}/events` streams the counts of every hour as server-sent events, along with the
+///   citizen states of every hour when asked for with `?citizen_states=true`
+///
+/// The results are only served over HTTP: the outputs, output file and grid SVG of a submitted config are dropped, so
+/// that clients can't write files on the machine of the server. Browsers only let pages of the allowed origin, if any,
+/// call the server.
+#[derive(Clone)]
+pub struct HttpServer {
+    jobs: Arc<Mutex<BTreeMap<String, Job>>>,
+    workers: Arc<Semaphore>,
+    last_sim_id: Arc<AtomicUsize>,
+    allowed_origin: Option<HeaderValue>,
+}
+
+/// A simulation submitted to the server, and what it has produced so far
+struct Job {
+    status: Status,
+    counts: Vec<Counts>,
+    summary: Option<Summary>,
+    cancelled: Arc<AtomicBool>,
+    events: broadcast::Sender<Event>,
+    citizen_states: broadcast::Sender<Event>,
+}
+
+impl Job {
+    fn new() -> Job {
+        Job {
+            status: Status::Queued,
+            counts: Vec::new(),
+            summary: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            citizen_states: broadcast::channel(EVENTS_CAPACITY).0,
+        }
+    }
+
+    /// Clients that are not following the simulation any more are dropped, so sending to nobody is not an error
+    fn send(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+
+    fn set_status(&mut self, status: Status) {
+        self.send(Event::status(&status));
+        self.status = status;
+    }
+}
+
+fn is_over(status: &Status) -> bool {
+    matches!(status,
+             Status::Cancelled { .. } | Status::Finished { .. } | Status::Failed { .. } | Status::Invalid { .. })
+}
+
+/// A server-sent event. The stream of events of a simulation ends with its last status.
+#[derive(Clone)]
+struct Event {
+    name: &'static str,
+    data: String,
+    last: bool,
+}
+
+impl Event {
+    fn new(name: &'static str, data: &impl Serialize) -> Event {
+        Event { name, data: serde_json::to_string(data).expect("Failed to serialize event"), last: false }
+    }
+
+    fn status(status: &Status) -> Event {
+        Event { last: is_over(status), ..Event::new("status", status) }
+    }
+
+    fn to_sse(&self) -> String {
+        format!("event: {}\ndata: {}\n\n", self.name, self.data)
+    }
+}
+
+#[derive(Deserialize)]
+struct SimulationRequest {
+    sim_id: Option<String>,
+    #[serde(flatten)]
+    config: Config,
+}
+
+#[derive(Serialize)]
+struct SimulationStatus<'a> {
+    sim_id: &'a str,
+    #[serde(flatten)]
+    status: &'a Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counts: Option<&'a [Counts]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<&'a Summary>,
+}
+
+#[derive(Serialize)]
+struct ErrorMessage {
+    error: String,
+}
+
+impl HttpServer {
+    pub fn new(max_concurrent_simulations: usize) -> HttpServer {
+        HttpServer {
+            jobs: Arc::new(Mutex::new(BTreeMap::new())),
+            workers: Arc::new(Semaphore::new(max_concurrent_simulations)),
+            last_sim_id: Arc::new(AtomicUsize::new(0)),
+            allowed_origin: None,
+        }
+    }
+
+    /// Lets pages of another origin, such as `http://localhost:3000`, call the server from a browser
+    pub fn allow_origin(self, origin: &str) -> Result<HttpServer, InvalidHeaderValue> {
+        Ok(HttpServer { allowed_origin: Some(origin.parse()?), ..self })
+    }
+
+    pub async fn serve(self, address: SocketAddr) -> Result<(), hyper::Error> {
+        let make_service = make_service_fn(move |_connection| {
+            let server = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(request).await) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&address)?.serve(make_service);
+        println!("Listening on http://{}", server.local_addr());
+        server.await
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let path = request.uri().path().trim_matches('/').to_string();
+        let citizen_states = request.uri().query()
+            .is_some_and(|query| query.split('&').any(|parameter| parameter == "citizen_states=true"));
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut response = match (request.method(), segments.as_slice()) {
+            (&Method::OPTIONS, _) => empty_response(StatusCode::NO_CONTENT),
+            (&Method::POST, ["simulations"]) => self.submit(request.into_body()).await,
+            (&Method::GET, ["simulations"]) => self.list(),
+            (&Method::GET, ["simulations", sim_id]) => self.status(sim_id),
+            (&Method::DELETE, ["simulations", sim_id]) => self.cancel(sim_id),
+            (&Method::GET, ["simulations", sim_id, "events"]) => self.events(sim_id, citizen_states),
+            _ => error_response(StatusCode::NOT_FOUND, format!("No route for {} /{}", request.method(), path)),
+        };
+        if let Some(origin) = &self.allowed_origin {
+            let headers = response.headers_mut();
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, DELETE".parse().unwrap());
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type".parse().unwrap());
+        }
+        response
+    }
+
+    async fn submit(&self, body: Body) -> Response<Body> {
+        let request = match hyper::body::to_bytes(body).await {
+            Ok(body) => serde_json::from_slice::<SimulationRequest>(&body).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let SimulationRequest { sim_id, config } = match request {
+            Ok(request) => request,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, format!("Could not parse the config: {}", e)),
+        };
+        let config = config.without_file_outputs();
+        if let Err(e) = config.validate() {
+            return json_response(StatusCode::BAD_REQUEST, &Status::Invalid { errors: e.get_errors().clone() });
+        }
+
+        let sim_id = sim_id
+            .unwrap_or_else(|| (self.last_sim_id.fetch_add(1, Ordering::SeqCst) + 1).to_string());
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(&sim_id) {
+            return error_response(StatusCode::CONFLICT, format!("There is already a simulation {}", sim_id));
+        }
+        let mut job = Job::new();
+        match self.workers.try_acquire() {
+            Ok(permit) => {
+                permit.forget();
+                job.status = Status::Accepted;
+                let server = self.clone();
+                let sim_id = sim_id.clone();
+                task::spawn_blocking(move || server.run(&sim_id, config));
+            }
+            Err(_) => {
+                let server = self.clone();
+                let sim_id = sim_id.clone();
+                tokio::spawn(async move {
+                    server.workers.acquire().await.forget();
+                    server.update(&sim_id, |job| if !is_over(&job.status) { job.set_status(Status::Accepted) });
+                    task::spawn_blocking(move || server.run(&sim_id, config));
+                });
+            }
+        }
+        let response = json_response(StatusCode::CREATED, &SimulationStatus {
+            sim_id: &sim_id,
+            status: &job.status,
+            counts: None,
+            summary: None,
+        });
+        jobs.insert(sim_id, job);
+        response
+    }
+
+    fn list(&self) -> Response<Body> {
+        let jobs = self.jobs.lock().unwrap();
+        let statuses: Vec<SimulationStatus> = jobs.iter()
+            .map(|(sim_id, job)| SimulationStatus { sim_id, status: &job.status, counts: None, summary: None })
+            .collect();
+        json_response(StatusCode::OK, &statuses)
+    }
+
+    fn status(&self, sim_id: &str) -> Response<Body> {
+        let jobs = self.jobs.lock().unwrap();
+        match jobs.get(sim_id) {
+            None => not_found(sim_id),
+            Some(job) => json_response(StatusCode::OK, &SimulationStatus {
+                sim_id,
+                status: &job.status,
+                counts: Some(&job.counts),
+                summary: job.summary.as_ref(),
+            }),
+        }
+    }
+
+    /// A simulation still waiting for a worker is cancelled right away; a running one stops before its next hour.
+    /// Cancelling a simulation that is already over is a conflict.
+    fn cancel(&self, sim_id: &str) -> Response<Body> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = match jobs.get_mut(sim_id) {
+            None => return not_found(sim_id),
+            Some(job) => job,
+        };
+        let status = if is_over(&job.status) {
+            StatusCode::CONFLICT
+        } else {
+            job.cancelled.store(true, Ordering::SeqCst);
+            if job.status == Status::Queued {
+                job.set_status(Status::Cancelled { hour: 0 });
+            }
+            StatusCode::ACCEPTED
+        };
+        json_response(status, &SimulationStatus { sim_id, status: &job.status, counts: None, summary: None })
+    }
+
+    /// Replays the counts so far, then follows the simulation until it is over. The events are replayed and followed
+    /// under the same lock that the simulation sends them under, so that none are missed or repeated.
+    fn events(&self, sim_id: &str, citizen_states: bool) -> Response<Body> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = match jobs.get(sim_id) {
+            None => return not_found(sim_id),
+            Some(job) => job,
+        };
+        let mut replay: Vec<Event> = job.counts.iter().map(|counts| Event::new("counts", counts)).collect();
+        if let Some(summary) = &job.summary {
+            replay.push(Event::new("summary", summary));
+        }
+        replay.push(Event::status(&job.status));
+        let live: BoxStream<Result<Event, broadcast::RecvError>> = if citizen_states {
+            stream::select(job.events.subscribe(), job.citizen_states.subscribe()).boxed()
+        } else {
+            job.events.subscribe().boxed()
+        };
+
+        let events = stream::iter(replay).chain(live.filter_map(|event| future::ready(event.ok()))).boxed();
+        let events = stream::unfold((events, false), |(mut events, over)| async move {
+            if over {
+                return None;
+            }
+            let event = events.next().await?;
+            let over = event.last;
+            Some((Ok::<_, Infallible>(event.to_sse()), (events, over)))
+        });
+        Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(Body::wrap_stream(events))
+            .unwrap()
+    }
+
+    fn update<F: FnOnce(&mut Job)>(&self, sim_id: &str, update: F) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(sim_id) {
+            update(job);
+        }
+    }
+
+    /// Runs a simulation on a worker thread, and frees the worker afterwards. A panic fails only this simulation.
+    fn run(&self, sim_id: &str, config: Config) {
+        let cancelled = self.jobs.lock().unwrap()[sim_id].cancelled.clone();
+        if !cancelled.load(Ordering::SeqCst) {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let listener = HttpListener::new(sim_id, self.clone());
+                let mut control = HttpRunControl { sim_id, server: self.clone(), cancelled, total_hours: 0 };
+                let simulation = Simulation::builder(config).sim_id(sim_id).listener(Box::new(listener)).build()
+                    .expect("The config was validated when the simulation was submitted");
+                simulation.run_with_control(&mut control);
+            }));
+            if let Err(e) = result {
+                self.update(sim_id, |job| job.set_status(Status::Failed { error: panic_message(e) }));
+            }
+        }
+        self.workers.add_permits(1);
+    }
+}
+
+/// Keeps the results of a simulation for its clients. The citizen states of an hour are only collected when a client
+/// is following them.
+struct HttpListener {
+    sim_id: String,
+    server: HttpServer,
+    citizen_states: Option<CitizenStatesAtHr>,
+}
+
+impl HttpListener {
+    fn new(sim_id: &str, server: HttpServer) -> HttpListener {
+        HttpListener { sim_id: sim_id.to_string(), server, citizen_states: None }
+    }
+}
+
+impl Listener for HttpListener {
+    fn hour_started(&mut self, hr: i32) {
+        let mut following = false;
+        self.server.update(&self.sim_id, |job| following = job.citizen_states.receiver_count() > 0);
+        self.citizen_states = if following {
+            let mut citizen_states = self.citizen_states.take().unwrap_or_else(|| CitizenStatesAtHr::init(0));
+            citizen_states.start_hour(hr);
+            Some(citizen_states)
+        } else {
+            None
+        };
+    }
+
+    fn counts_updated(&mut self, counts: Counts) {
+        self.server.update(&self.sim_id, |job| {
+            job.counts.push(counts);
+            job.send(Event::new("counts", &counts));
+        });
+    }
+
+    fn simulation_ended(&mut self, summary: &Summary) {
+        self.server.update(&self.sim_id, |job| {
+            job.summary = Some(summary.clone());
+            job.send(Event::new("summary", summary));
+        });
+    }
+
+    fn citizen_state_updated(&mut self, _hr: i32, citizen: &Citizen, location: &Point) {
+        if let Some(citizen_states) = self.citizen_states.as_mut() {
+            citizen_states.update(citizen, location);
+        }
+    }
+
+    fn hour_ended(&mut self, _hr: i32) {
+        if let Some(citizen_states) = &self.citizen_states {
+            let event = Event::new("citizen_states", citizen_states);
+            self.server.update(&self.sim_id, |job| { let _ = job.citizen_states.send(event); });
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Stops a simulation once it is cancelled, and keeps its status up to date
+struct HttpRunControl<'a> {
+    sim_id: &'a str,
+    server: HttpServer,
+    cancelled: Arc<AtomicBool>,
+    total_hours: i32,
+}
+
+impl RunControl for HttpRunControl<'_> {
+    fn run_started(&mut self, total_hours: i32) {
+        self.total_hours = total_hours;
+        self.server.update(self.sim_id, |job| job.set_status(Status::Running { total_hours }));
+    }
+
+    fn should_continue(&mut self, simulation_hour: i32) -> bool {
+        if simulation_hour % constants::NUMBER_OF_HOURS == 0 {
+            let status = Status::Progress { hour: simulation_hour, total_hours: self.total_hours };
+            self.server.update(self.sim_id, |job| job.set_status(status));
+        }
+        !self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn run_ended(&mut self, last_hour: i32, cancelled: bool) {
+        let status = if cancelled {
+            Status::Cancelled { hour: last_hour }
+        } else {
+            Status::Finished { hour: last_hour }
+        };
+        self.server.update(self.sim_id, |job| job.set_status(status));
+    }
+}
+
+fn json_response(status: StatusCode, body: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(body).expect("Failed to serialize response")))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, error: String) -> Response<Body> {
+    json_response(status, &ErrorMessage { error })
+}
+
+fn not_found(sim_id: &str) -> Response<Body> {
+    error_response(StatusCode::NOT_FOUND, format!("There is no simulation {}", sim_id))
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder().status(status).body(Body::empty()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::Value;
+
+    use crate::config::{AutoPopulation, Population};
+    use crate::disease::Disease;
+
+    use super::*;
+
+    fn config(hours: i32) -> Value {
+        let population = Population::Auto(AutoPopulation {
+            number_of_agents: 10,
+            public_transport_percentage: 1.0,
+            working_percentage: 1.0,
+            age_distribution: None,
+        });
+        let disease = Disease::new(5, 20, 40, 0.025, 0.25, 0.035);
+        serde_json::to_value(Config::new(population, disease, vec![], 100, hours, vec![], None)).unwrap()
+    }
+
+    async fn send(server: &HttpServer, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, String) {
+        let body = body.map(|body| Body::from(body.to_string())).unwrap_or_else(Body::empty);
+        let request = Request::builder().method(method).uri(uri).body(body).unwrap();
+        let response = server.handle(request).await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    async fn status_of(server: &HttpServer, sim_id: &str) -> Value {
+        let (_, body) = send(server, Method::GET, &format!("/simulations/{}", sim_id), None).await;
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_run_a_simulation_and_serve_its_results() {
+        let server = HttpServer::new(1);
+
+        let (status, body) = send(&server, Method::POST, "/simulations", Some(config(5))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body, r#"{"sim_id":"1","status":"accepted"}"#);
+
+        let mut simulation = status_of(&server, "1").await;
+        for _ in 0..500 {
+            if simulation["status"] == "finished" {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+            simulation = status_of(&server, "1").await;
+        }
+        assert_eq!(simulation["status"], "finished");
+        assert_eq!(simulation["counts"].as_array().unwrap().len(), 4);
+        assert_eq!(simulation["summary"]["population"], 10);
+
+        let (status, events) = send(&server, Method::GET, "/simulations/1/events", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(events.matches("event: counts\n").count(), 4);
+        assert!(events.contains("event: summary\n"));
+        assert!(events.ends_with("event: status\ndata: {\"status\":\"finished\",\"hour\":4}\n\n"));
+    }
+
+    #[tokio::test]
+    async fn should_not_write_the_file_outputs_of_submitted_configs() {
+        let server = HttpServer::new(1);
+        let grid_svg = std::env::temp_dir().join("epirust_http_server_test.svg");
+        let output_dir = std::env::temp_dir().join("epirust_http_server_test");
+        let _ = std::fs::remove_dir_all(&output_dir);
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let mut request = config(3);
+        request["grid_svg"] = Value::from(grid_svg.to_str().unwrap());
+        request["outputs"] = serde_json::json!([{"Csv": {"output_file": output_dir.join("counts").to_str().unwrap()}}]);
+
+        send(&server, Method::POST, "/simulations", Some(request)).await;
+        let mut simulation = status_of(&server, "1").await;
+        for _ in 0..500 {
+            if simulation["status"] == "finished" {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+            simulation = status_of(&server, "1").await;
+        }
+
+        assert_eq!(simulation["status"], "finished");
+        assert!(!grid_svg.exists());
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_only_allow_the_configured_origin() {
+        let request = || Request::builder().method(Method::GET).uri("/simulations").body(Body::empty()).unwrap();
+
+        let response = HttpServer::new(1).handle(request()).await;
+        assert!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        let server = HttpServer::new(1).allow_origin("http://localhost:3000").unwrap();
+        let response = server.handle(request()).await;
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "http://localhost:3000");
+    }
+
+    #[tokio::test]
+    async fn should_cancel_a_simulation_waiting_for_a_worker() {
+        let server = HttpServer::new(0);
+        let mut request = config(5);
+        request["sim_id"] = Value::from("flu");
+
+        let (_, body) = send(&server, Method::POST, "/simulations", Some(request.clone())).await;
+        assert_eq!(body, r#"{"sim_id":"flu","status":"queued"}"#);
+        let (status, _) = send(&server, Method::POST, "/simulations", Some(request)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, body) = send(&server, Method::DELETE, "/simulations/flu", None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body, r#"{"sim_id":"flu","status":"cancelled","hour":0}"#);
+        let (_, body) = send(&server, Method::GET, "/simulations", None).await;
+        assert_eq!(body, r#"[{"sim_id":"flu","status":"cancelled","hour":0}]"#);
+        let (status, _) = send(&server, Method::DELETE, "/simulations/flu", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_requests() {
+        let server = HttpServer::new(1);
+
+        let (status, body) = send(&server, Method::POST, "/simulations", Some(config(0))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body,
+                   r#"{"status":"invalid","errors":[{"field":"hours","message":"should be positive, but is 0"}]}"#);
+
+        let (status, _) = send(&server, Method::POST, "/simulations", Some(Value::from("config"))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&server, Method::GET, "/simulations/42", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send(&server, Method::PUT, "/simulations", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
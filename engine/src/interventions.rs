@@ -18,12 +18,14 @@
  */
 
 use crate::config::Config;
+use crate::validation::{field, ValidationErrors};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
 pub enum Intervention {
     Vaccinate(Vaccinate),
     Lockdown(Lockdown),
-    BuildNewHospital(BuildNewHospital)
+    BuildNewHospital(BuildNewHospital),
+    CloseBorders(CloseBorders),
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
@@ -56,6 +58,19 @@ pub struct BuildNewHospital {
     pub spread_rate_threshold: i32
 }
 
+/// Stops travel into and out of a region. Only applies to multi-region simulations
+#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub struct CloseBorders {
+    pub at_number_of_infections: i32,
+    pub close_borders_period: i32,
+}
+
+impl CloseBorders {
+    pub fn new(at_number_of_infections: i32, close_borders_period: i32) -> CloseBorders {
+        CloseBorders { at_number_of_infections, close_borders_period }
+    }
+}
+
 impl Intervention {
     pub fn get_hospital_intervention(config: &Config) -> Option<BuildNewHospital> {
         return config.get_interventions().iter().filter_map(|i| {
@@ -74,4 +89,38 @@ impl Intervention {
             }
         }).next().copied();
     }
+
+    pub fn get_close_borders_intervention(config: &Config) -> Option<CloseBorders> {
+        config.get_interventions().iter().filter_map(|i| {
+            match i {
+                Intervention::CloseBorders(x) => Some(x),
+                _ => None
+            }
+        }).next().copied()
+    }
+
+    pub fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        match self {
+            Intervention::Vaccinate(vaccinate) => {
+                let path = field(path, "Vaccinate");
+                errors.check_non_negative(vaccinate.at_hour, field(&path, "at_hour"));
+                errors.check_fraction(vaccinate.percent, field(&path, "percent"));
+            }
+            Intervention::Lockdown(lockdown) => {
+                let path = field(path, "Lockdown");
+                errors.check_non_negative(lockdown.at_number_of_infections, field(&path, "at_number_of_infections"));
+                errors.check_fraction(lockdown.essential_workers_population, field(&path, "essential_workers_population"));
+                errors.check_non_negative(lockdown.lock_down_period, field(&path, "lock_down_period"));
+            }
+            Intervention::BuildNewHospital(hospital) => {
+                let path = field(path, "BuildNewHospital");
+                errors.check_non_negative(hospital.spread_rate_threshold, field(&path, "spread_rate_threshold"));
+            }
+            Intervention::CloseBorders(close_borders) => {
+                let path = field(path, "CloseBorders");
+                errors.check_non_negative(close_borders.at_number_of_infections, field(&path, "at_number_of_infections"));
+                errors.check_non_negative(close_borders.close_borders_period, field(&path, "close_borders_period"));
+            }
+        }
+    }
 }
@@ -1,79 +1,218 @@
 use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
 
+use futures::executor;
 use futures::StreamExt;
-use rdkafka::ClientConfig;
+use kafka_config::KafkaConfig;
 use rdkafka::consumer::{DefaultConsumerContext, MessageStream, StreamConsumer};
-use rdkafka::consumer::Consumer;
+use rdkafka::consumer::{CommitMode, Consumer};
 use rdkafka::error::KafkaError;
 use rdkafka::message::BorrowedMessage;
 use rdkafka::message::Message;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::task;
 
 use crate::config::Config;
-use crate::epidemiology_simulation::Epidemiology;
-use std::collections::HashMap;
-use rand::Rng;
+use crate::distributed_simulation::DistributedSimulation;
+use crate::listeners::outputs::Output;
+use crate::simulation::Simulation;
+use crate::run_control::{panic_message, Status};
+use crate::simulation_control::{KafkaRunControl, StatusPublisher};
+use crate::travel_plan::TravelPlan;
+use crate::validation::ValidationErrors;
 
 pub struct KafkaConsumer<'a> {
     engine_id: &'a str,
     consumer: StreamConsumer,
+    kafka_config: KafkaConfig,
+    status: StatusPublisher,
+    workers: Arc<Semaphore>,
 }
 
 impl KafkaConsumer<'_> {
-    pub fn new(engine_id: &str) -> KafkaConsumer {
-        let consumer: StreamConsumer = ClientConfig::new()
-            .set("bootstrap.servers", "localhost:9092")
+    pub fn new(engine_id: &str, kafka_config: KafkaConfig, max_concurrent_simulations: usize) -> KafkaConsumer<'_> {
+        let consumer: StreamConsumer = kafka_config.client_config()
             .set("group.id", engine_id)
+            .set("enable.auto.commit", "false")
             .create()
             .expect("Consumer creation failed");
 
-        let topics = ["simulation_requests"];
+        let topics = [kafka_config.topics.simulation_requests.as_str()];
         consumer.subscribe(&topics)
             .expect("Can't subscribe to specified topics");
 
-        return KafkaConsumer { engine_id, consumer };
+        let status = StatusPublisher::new(&kafka_config);
+        let workers = Arc::new(Semaphore::new(max_concurrent_simulations));
+        KafkaConsumer { engine_id, consumer, kafka_config, status, workers }
     }
 
+    /// Hands each request over to a worker, waiting for one to be free when all of them are busy. The consumer is
+    /// paused while it waits, so no more requests are read. The offset of a request is committed once it has been
+    /// accepted, so a request still waiting for a worker is read again after a restart.
     pub async fn listen_loop(&self) {
         let mut message_stream: MessageStream<DefaultConsumerContext> = self.consumer.start();
         while let Some(message) = message_stream.next().await {
-            let simulation_config = self.parse_message(message);
+            let simulation_config = self.parse_message(&message);
             match simulation_config {
                 Err(e) => {
                     println!("Received a message, but could not parse it.\n\
-                        Error Details: {}", e)
+                        Error Details: {}", e);
+                    if let Some(sim_id) = KafkaConsumer::sim_id_of(&message) {
+                        self.status.publish(&sim_id, Status::Failed { error: e.to_string() });
+                    }
                 }
                 Ok(request) => {
-                    self.run_sim(request)
+                    match self.job_for_engine(request) {
+                        None => { println!("Couldn't find any work for engine_id: {}", self.engine_id) }
+                        Some(job) => {
+                            match job.validate() {
+                                Ok(()) => self.start(job).await,
+                                Err(e) => {
+                                    let errors = e.get_errors().clone();
+                                    self.status.publish(job.sim_id(), Status::Invalid { errors })
+                                }
+                            }
+                        }
+                    }
                 }
             };
+            if let Ok(message) = &message {
+                if let Err(e) = self.consumer.commit_message(message, CommitMode::Async) {
+                    println!("Failed to commit the offset of a simulation request: {}", e);
+                }
+            }
         }
     }
 
-    fn run_sim(&self, request: Request) {
-        match request {
-            Request::SimulationRequest(req) => {
-                let mut epidemiology = Epidemiology::new(&req.config, req.sim_id);
-                epidemiology.run(&req.config);
+    fn job_for_engine(&self, request: Request) -> Option<Job> {
+        let job = match request {
+            Request::Simulation(req) => {
+                Some(Job::Simulation(*req))
             }
-            Request::MultiSimRequest(req) => {
-                let sim_req = req.iter().find(|c| c.engine_id == self.engine_id);
-                match sim_req {
-                    None => { println!("Couldn't find any work for engine_id: {}", self.engine_id) }
-                    Some(req) => {
-                        let sim_id = req.config.sim_id.clone();
-                        let mut epidemiology = Epidemiology::new(&req.config.config, sim_id);
-                        epidemiology.run(&req.config.config);
-                    }
-                }
+            Request::MultiSim(req) => {
+                req.into_iter()
+                    .find(|c| c.engine_id == self.engine_id)
+                    .map(|req| Job::Simulation(req.config))
+            }
+            Request::DistributedSim(req) => {
+                let travel_plan = req.travel_plan;
+                req.engine_configs.into_iter()
+                    .find(|c| c.engine_id == self.engine_id)
+                    .map(|req| Job::DistributedSimulation(req.config, travel_plan))
+            }
+        };
+        job.map(Job::with_kafka_outputs)
+    }
+
+    async fn start(&self, job: Job) {
+        let permit = match self.workers.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                self.status.publish(job.sim_id(), Status::Queued);
+                self.wait_for_worker().await
+            }
+        };
+        permit.forget();
+        self.status.publish(job.sim_id(), Status::Accepted);
+
+        let workers = self.workers.clone();
+        let engine_id = self.engine_id.to_string();
+        let kafka_config = self.kafka_config.clone();
+        let status = self.status.clone();
+        task::spawn_blocking(move || {
+            job.run(&engine_id, &kafka_config, &status);
+            workers.add_permits(1);
+        });
+    }
+
+    /// Waits for a worker with the partitions of the consumer paused, so that it keeps polling the brokers without
+    /// fetching requests that no worker could take
+    async fn wait_for_worker(&self) -> SemaphorePermit<'_> {
+        let partitions = self.consumer.assignment()
+            .and_then(|partitions| self.consumer.pause(&partitions).map(|_| partitions));
+        if let Err(e) = &partitions {
+            println!("Failed to pause the consumer while the workers are busy: {}", e);
+        }
+        let permit = self.workers.acquire().await;
+        if let Ok(partitions) = &partitions {
+            if let Err(e) = self.consumer.resume(partitions) {
+                println!("Failed to resume the consumer: {}", e);
             }
         }
+        permit
     }
 
-    fn parse_message(&self, message: Result<BorrowedMessage, KafkaError>) -> Result<Request, Box<dyn Error>> {
-        let borrowed_message = message?;
+    fn parse_message(&self, message: &Result<BorrowedMessage, KafkaError>) -> Result<Request, Box<dyn Error>> {
+        let borrowed_message = message.as_ref().map_err(|e| e.clone())?;
         let parsed_message = borrowed_message.payload_view::<str>().unwrap()?;
         serde_json::from_str(parsed_message).map_err(|e| e.into())
     }
+
+    /// The id of a request that could not be parsed, so that its failure can still be reported
+    fn sim_id_of(message: &Result<BorrowedMessage, KafkaError>) -> Option<String> {
+        let payload = message.as_ref().ok()?.payload_view::<str>()?.ok()?;
+        let request: serde_json::Value = serde_json::from_str(payload).ok()?;
+        request.get("sim_id")?.as_str().map(|sim_id| sim_id.to_string())
+    }
+}
+
+/// The part of a request that runs on this engine
+enum Job {
+    Simulation(SimulationRequest),
+    DistributedSimulation(SimulationRequest, TravelPlan),
+}
+
+impl Job {
+    /// Sends the output of a request that doesn't name its outputs back over Kafka, rather than to files on the engine
+    fn with_kafka_outputs(mut self) -> Job {
+        match &mut self {
+            Job::Simulation(req) | Job::DistributedSimulation(req, _) =>
+                req.config.set_default_outputs(Output::kafka_defaults()),
+        }
+        self
+    }
+
+    fn sim_id(&self) -> &str {
+        match self {
+            Job::Simulation(req) => &req.sim_id,
+            Job::DistributedSimulation(req, _) => &req.sim_id,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            Job::Simulation(req) => req.config.validate(),
+            Job::DistributedSimulation(req, _) => req.config.validate(),
+        }
+    }
+
+    /// Runs the simulation on a worker thread. A panic fails only this simulation, so that the engine goes on with
+    /// the other ones.
+    fn run(self, engine_id: &str, kafka_config: &KafkaConfig, status: &StatusPublisher) {
+        let sim_id = self.sim_id().to_string();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            match self {
+                Job::Simulation(req) => {
+                    let mut control = KafkaRunControl::new(engine_id, req.sim_id.clone(), kafka_config, status);
+                    let simulation = Simulation::builder(req.config).sim_id(&req.sim_id).config_outputs(kafka_config)
+                        .build().expect("The config was validated when the request was accepted");
+                    simulation.run_with_control(&mut control);
+                }
+                Job::DistributedSimulation(req, travel_plan) => {
+                    let total_hours = req.config.get_hours();
+                    let mut simulation = DistributedSimulation::new(engine_id.to_string(), req.sim_id.clone(),
+                                                                    req.config, travel_plan, kafka_config.clone());
+                    status.publish(&req.sim_id, Status::Running { total_hours });
+                    executor::block_on(simulation.run());
+                    status.publish(&req.sim_id, Status::Finished { hour: total_hours - 1 });
+                }
+            }
+        }));
+        if let Err(e) = result {
+            status.publish(&sim_id, Status::Failed { error: panic_message(e) });
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -89,9 +228,17 @@ struct SimRequestByEngine {
     config: SimulationRequest,
 }
 
+/// A simulation of the regions of a travel plan, with each engine running one region
+#[derive(Debug, Deserialize)]
+struct DistributedSimRequest {
+    engine_configs: Vec<SimRequestByEngine>,
+    travel_plan: TravelPlan,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 enum Request {
-    SimulationRequest(SimulationRequest),
-    MultiSimRequest(Vec<SimRequestByEngine>),
+    Simulation(Box<SimulationRequest>),
+    MultiSim(Vec<SimRequestByEngine>),
+    DistributedSim(DistributedSimRequest),
 }
@@ -0,0 +1,91 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+//! EpiRust simulates the spread of an epidemic through a population of agents moving around a grid. The `epirust`
+//! binary is a thin command line and Kafka daemon on top of this library, which other programs can use to embed
+//! simulations:
+//!
+//! ```
+//! use epirust::{config, Simulation};
+//!
+//! let config = config::read("config/test/calibration_base.json".to_string()).unwrap();
+//! let mut simulation = Simulation::builder(config).seed(7).build().unwrap();
+//!
+//! let counts = simulation.step();
+//! assert_eq!(counts.get_hour(), 1);
+//!
+//! let summary = simulation.run();
+//! println!("{} citizens died", summary.get_total_deceased());
+//! ```
+//!
+//! The progress of a simulation can be followed with a `Listener`, added with `SimulationBuilder::listener`.
+
+#[macro_use]
+extern crate serde_derive;
+extern crate kafka_config;
+
+mod constants;
+
+pub mod age;
+pub mod agent;
+pub mod contact_matrix;
+pub mod contact_network;
+pub mod epidemiology_simulation;
+pub mod allocation_map;
+pub mod geography;
+pub mod disease;
+pub mod progression;
+pub mod random_wrapper;
+pub mod listeners;
+mod population;
+mod geojson_service;
+mod models;
+pub mod config;
+pub mod interventions;
+#[cfg(feature = "kafka")]
+pub mod kafka_consumer;
+#[cfg(feature = "http")]
+pub mod http_server;
+pub mod travel_plan;
+pub mod multi_region_simulation;
+pub mod run_control;
+pub mod validation;
+pub mod ensemble;
+pub mod calibration;
+mod parameter_space;
+pub mod sensitivity;
+pub mod setting;
+pub mod simulation;
+#[cfg(feature = "kafka")]
+pub mod distributed_simulation;
+#[cfg(feature = "kafka")]
+pub mod simulation_control;
+
+pub use crate::config::Config;
+pub use crate::disease::Disease;
+pub use crate::epidemiology_simulation::Epidemiology;
+pub use crate::listeners::events::counts::Counts;
+pub use crate::listeners::events::summary::Summary;
+pub use crate::listeners::listener::{Listener, Listeners};
+pub use crate::run_control::RunControl;
+pub use crate::simulation::{Simulation, SimulationBuilder};
+pub use crate::validation::{ValidationError, ValidationErrors};
+
+/// The sim_id of simulations that are not run from a Kafka request
+pub const STANDALONE_SIM_ID: &str = "0";
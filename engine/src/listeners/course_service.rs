@@ -0,0 +1,138 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+use std::error::Error;
+use std::fs::File;
+
+use csv::Writer;
+use fxhash::FxHashSet;
+
+use crate::agent::Citizen;
+use crate::geography::Point;
+use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary::Summary;
+use crate::listeners::listener::Listener;
+
+#[derive(Serialize)]
+struct CourseRow {
+    citizen_id: i32,
+    latent_period: i32,
+    infectious_period: i32,
+    time_to_quarantine: i32,
+    time_to_recovery: i32,
+}
+
+/// Writes the course of the disease drawn for every citizen as a row of CSV, when the citizen is first seen, so that
+/// the days can be checked against the distributions of the disease
+pub struct CourseListener {
+    output_file_name: String,
+    writer: Option<Writer<File>>,
+    written: FxHashSet<i32>,
+}
+
+impl CourseListener {
+    pub fn new(output_file_name: String) -> CourseListener {
+        CourseListener { output_file_name, writer: None, written: FxHashSet::default() }
+    }
+
+    fn write(&mut self, citizen: &Citizen) -> Result<(), Box<dyn Error>> {
+        if self.writer.is_none() {
+            self.writer = Some(Writer::from_path(&self.output_file_name)?);
+        }
+        let course = citizen.state_machine.get_course();
+        self.writer.as_mut().unwrap().serialize(CourseRow {
+            citizen_id: citizen.id,
+            latent_period: course.latent_period,
+            infectious_period: course.infectious_period,
+            time_to_quarantine: course.time_to_quarantine,
+            time_to_recovery: course.time_to_recovery,
+        })?;
+        Ok(())
+    }
+}
+
+impl Listener for CourseListener {
+    fn counts_updated(&mut self, _counts: Counts) {}
+
+    fn simulation_ended(&mut self, _summary: &Summary) {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().expect("Failed to write to file");
+        }
+    }
+
+    fn citizen_state_updated(&mut self, _hr: i32, citizen: &Citizen, _location: &Point) {
+        if self.written.insert(citizen.id) {
+            if let Err(e) = self.write(citizen) {
+                println!("Error occurred while writing to {}! {:?}", self.output_file_name, e);
+            }
+        }
+    }
+
+    fn hour_ended(&mut self, _hr: i32) {
+        if let Some(Err(e)) = self.writer.as_mut().map(|writer| writer.flush()) {
+            println!("Error occurred while writing to {}! {:?}", self.output_file_name, e);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::disease::Disease;
+    use crate::geography::Area;
+    use crate::random_wrapper::RandomWrapper;
+
+    use super::*;
+
+    #[test]
+    fn should_write_the_course_of_every_citizen_once() {
+        let file_name = std::env::temp_dir().join("epirust_course_listener_test.csv");
+        let mut listener = CourseListener::new(file_name.to_str().unwrap().to_string());
+        let area = Area::new(Point::new(0, 0), Point::new(1, 1));
+        let mut rng = RandomWrapper::new();
+        let disease = Disease::new(5, 10, 20, 0.1, 0.5, 0.1);
+        let citizens: Vec<Citizen> = (1..=2).map(|id| {
+            let mut citizen = Citizen::new_citizen(id, area, area, Point::new(2, 2), true, true, &mut rng);
+            citizen.draw_course(&disease.course_sampler(), &mut rng);
+            citizen
+        }).collect();
+
+        for hr in 1..=2 {
+            listener.hour_started(hr);
+            citizens.iter().for_each(|citizen| listener.citizen_state_updated(hr, citizen, &Point::new(1, 1)));
+            listener.hour_ended(hr);
+        }
+        listener.simulation_ended(&Summary::new());
+
+        let contents = fs::read_to_string(&file_name).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "citizen_id,latent_period,infectious_period,time_to_quarantine,time_to_recovery");
+        let course = citizens[1].state_machine.get_course();
+        assert_eq!(lines[2], format!("2,{},{},{},20", course.latent_period, course.infectious_period,
+                                     course.time_to_quarantine));
+        assert_eq!(lines.len(), 3);
+        fs::remove_file(file_name).unwrap();
+    }
+}
@@ -20,50 +20,89 @@
 extern crate csv;
 
 use std::error::Error;
+use std::fs::File;
 
 use csv::Writer;
 
 use crate::geography::Point;
 use std::any::Any;
 use crate::listeners::events::counts::Counts;
+use crate::listeners::events::simulation_started::SimulationStarted;
+use crate::listeners::events::summary::Summary;
 use crate::listeners::listener::Listener;
 
+/// Writes the counts for every hour as a row of CSV, with the description of the run in a JSON file beside it.
+/// Rows are flushed to the file every `flush_interval` hours, so a run that stops early still leaves a valid file.
 pub struct CsvListener {
     output_file_name: String,
-    counts: Vec<Counts>,
+    flush_interval: usize,
+    writer: Option<Writer<File>>,
+    rows_since_flush: usize,
 }
 
 impl CsvListener {
-    pub fn new(output_file_name: String) -> CsvListener {
-        CsvListener { output_file_name, counts: Vec::new() }
+    pub fn new(output_file_name: String, flush_interval: usize) -> CsvListener {
+        CsvListener { output_file_name, flush_interval, writer: None, rows_since_flush: 0 }
     }
 
-    pub fn write(file_path: &String, data: &Vec<Counts>) -> Result<(), Box<dyn Error>> {
-        let mut wtr = Writer::from_path(file_path)?;
-
-        for row in data {
-            let result = wtr.serialize(row);
-            match result {
-                Ok(_) => {}
-                Err(e) => {
-                    println!("Error occurred while serializing! {:?}", e);
-                }
-            }
+    fn writer(&mut self) -> Result<&mut Writer<File>, Box<dyn Error>> {
+        if self.writer.is_none() {
+            let file = File::create(&self.output_file_name)?;
+            self.writer = Some(Writer::from_writer(file));
         }
+        Ok(self.writer.as_mut().unwrap())
+    }
 
-        wtr.flush()?;
+    fn write(&mut self, counts: &Counts) -> Result<(), Box<dyn Error>> {
+        self.writer()?.serialize(counts)?;
+        self.rows_since_flush += 1;
+        if self.rows_since_flush >= self.flush_interval {
+            self.writer()?.flush()?;
+            self.rows_since_flush = 0;
+        }
         Ok(())
     }
+
+    /// Writes the summary beside the counts, e.g. `counts.csv` gets `counts_summary.json`
+    pub fn write_summary(file_path: &str, summary: &Summary) -> Result<(), Box<dyn Error>> {
+        let file = File::create(format!("{}_summary.json", CsvListener::stem(file_path)))?;
+        serde_json::to_writer_pretty(file, summary)?;
+        Ok(())
+    }
+
+    /// Writes the description of the run beside the counts, e.g. `counts.csv` gets `counts.meta.json`
+    fn write_metadata(file_path: &str, started: &SimulationStarted) -> Result<(), Box<dyn Error>> {
+        let metadata: serde_json::Map<String, serde_json::Value> = started.describe().into_iter()
+            .map(|(key, value)| (key, serde_json::Value::String(value)))
+            .collect();
+        let file = File::create(format!("{}.meta.json", CsvListener::stem(file_path)))?;
+        serde_json::to_writer_pretty(file, &metadata)?;
+        Ok(())
+    }
+
+    fn stem(file_path: &str) -> &str {
+        file_path.strip_suffix(".csv").unwrap_or(file_path)
+    }
 }
 
 impl Listener for CsvListener {
+    fn simulation_started(&mut self, started: &SimulationStarted) {
+        if let Err(e) = CsvListener::write_metadata(&self.output_file_name, started) {
+            println!("Error occurred while describing the run beside {}! {:?}", self.output_file_name, e);
+        }
+    }
+
     fn counts_updated(&mut self, counts: Counts) {
-        self.counts.push(counts);
+        if let Err(e) = self.write(&counts) {
+            println!("Error occurred while writing to {}! {:?}", self.output_file_name, e);
+        }
     }
 
-    fn simulation_ended(&mut self) {
-        CsvListener::write(&self.output_file_name, &self.counts)
+    fn simulation_ended(&mut self, summary: &Summary) {
+        self.writer().and_then(|writer| Ok(writer.flush()?))
             .expect("Failed to write to file");
+        CsvListener::write_summary(&self.output_file_name, summary)
+            .expect("Failed to write the summary");
     }
 
     fn citizen_got_infected(&mut self, _cell: &Point) {
@@ -73,3 +112,54 @@ impl Listener for CsvListener {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Local;
+
+    use super::*;
+
+    #[test]
+    fn should_write_rows_before_the_run_ends() {
+        let file_name = std::env::temp_dir().join("epirust_csv_listener_streaming_test.csv");
+        let metadata_file_name = std::env::temp_dir().join("epirust_csv_listener_streaming_test.meta.json");
+        let mut listener = CsvListener::new(file_name.to_str().unwrap().to_string(), 2);
+        let config = crate::config::read(String::from("config/test/auto_pop.json")).unwrap();
+        let mut counts = Counts::new(99, 1);
+
+        listener.simulation_started(&SimulationStarted { sim_id: "1", config: &config, population: 100, started_at: Local::now() });
+        listener.counts_updated(counts);
+        counts.increment_hour();
+        listener.counts_updated(counts);
+        counts.increment_hour();
+        listener.counts_updated(counts);
+
+        let contents = fs::read_to_string(&file_name).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines, ["hour,susceptible,infected,quarantined,recovered,deceased", "0,99,1,0,0,0", "1,99,1,0,0,0"]);
+        let metadata: serde_json::Value = serde_json::from_str(&fs::read_to_string(&metadata_file_name).unwrap()).unwrap();
+        assert_eq!(metadata["sim_id"], "1");
+        assert_eq!(metadata["hours"], "10000");
+        fs::remove_file(file_name).unwrap();
+        fs::remove_file(metadata_file_name).unwrap();
+    }
+
+    #[test]
+    fn should_write_summary_beside_counts() {
+        let file_name = std::env::temp_dir().join("epirust_csv_listener_test.csv");
+        let summary_file_name = std::env::temp_dir().join("epirust_csv_listener_test_summary.json");
+        let mut listener = CsvListener::new(file_name.to_str().unwrap().to_string(), 1);
+
+        listener.counts_updated(Counts::new(99, 1));
+        listener.simulation_ended(&Summary::new());
+
+        assert_eq!(fs::read_to_string(&file_name).unwrap().lines().count(), 2);
+        let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary_file_name).unwrap()).unwrap();
+        assert_eq!(summary["peak_infected"], 0);
+        assert_eq!(summary["runtime"]["hours_simulated"], 0);
+        fs::remove_file(file_name).unwrap();
+        fs::remove_file(summary_file_name).unwrap();
+    }
+}
@@ -22,7 +22,9 @@ use std::any::Any;
 use crate::listeners::listener::Listener;
 use crate::geography::Point;
 use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary::Summary;
 
+#[derive(Default)]
 pub struct Hotspot {
     disease_hotspot_tracker: FxHashMap<Point, i32>
 }
@@ -38,7 +40,7 @@ impl Listener for Hotspot {
     fn counts_updated(&mut self, _counts: Counts) {
     }
 
-    fn simulation_ended(&mut self) {
+    fn simulation_ended(&mut self, _summary: &Summary) {
     }
 
     fn citizen_got_infected(&mut self, cell: &Point) {
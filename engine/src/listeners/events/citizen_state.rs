@@ -17,18 +17,25 @@
  *
  */
 
+use serde::Serializer;
+
 use crate::agent::{Citizen, State};
 use crate::geography::Point;
 
 #[derive(Serialize)]
 pub struct CitizenState {
-    citizen_id: i32,
-    state: String,
-    location: Point,
+    pub citizen_id: i32,
+    #[serde(serialize_with = "serialize_state")]
+    pub state: State,
+    pub location: Point,
+}
+
+fn serialize_state<S: Serializer>(state: &State, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&CitizenState::state_str(*state))
 }
 
 impl CitizenState {
-    fn state_str(state: State) -> String {
+    pub fn state_str(state: State) -> String {
         match state {
             State::Susceptible { .. } => "s".to_string(),
             State::Infected { .. } => "i".to_string(),
@@ -55,13 +62,13 @@ impl CitizenStatesAtHr {
     pub fn update(&mut self, citizen: &Citizen, location: &Point) {
         self.citizen_states.push(CitizenState {
             citizen_id: citizen.id,
-            state: CitizenState::state_str(citizen.state_machine.state),
+            state: citizen.state_machine.state,
             location: *location,
         })
     }
 
-    pub fn next_hour(&mut self) {
-        self.hr += 1;
+    pub fn start_hour(&mut self, hr: i32) {
+        self.hr = hr;
         self.citizen_states.clear();
     }
 }
@@ -84,7 +91,7 @@ mod tests {
         assert_eq!(citizen_states_at_hr.hr, 1);
         assert_eq!(citizen_states_at_hr.citizen_states.len(), 1);
 
-        citizen_states_at_hr.next_hour();
+        citizen_states_at_hr.start_hour(2);
         assert_eq!(citizen_states_at_hr.hr, 2);
         assert_eq!(citizen_states_at_hr.citizen_states.len(), 0);
     }
@@ -0,0 +1,278 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+//! A compact binary encoding of the citizen states published every hour. Each message is a frame laid out as, with
+//! all numbers little endian:
+//!
+//! | bytes | content                                                   |
+//! |-------|-----------------------------------------------------------|
+//! | 4     | `EPCS`                                                    |
+//! | 1     | version, currently 1                                      |
+//! | 1     | kind: 0 for a keyframe, 1 for a delta                     |
+//! | 4     | hour, u32                                                 |
+//! | 4     | number of records, u32                                    |
+//! | 9 × n | records: citizen id u32, state u8, x u16, y u16           |
+//!
+//! The state is 0 for susceptible, 1 for infected, 2 for recovered, 3 for deceased, and 255 for a citizen who left
+//! the region (with x and y of 0). A keyframe has every citizen, and a delta only has the citizens whose state or
+//! location changed since the previous frame.
+
+use std::error::Error;
+
+use fxhash::FxHashMap;
+
+use crate::agent::State;
+use crate::listeners::events::citizen_state::CitizenStatesAtHr;
+
+const MAGIC: &[u8; 4] = b"EPCS";
+const VERSION: u8 = 1;
+const KEYFRAME: u8 = 0;
+const DELTA: u8 = 1;
+const HEADER_SIZE: usize = 14;
+const RECORD_SIZE: usize = 9;
+pub const LEFT_REGION: u8 = 255;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct EncodedState {
+    pub state: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+fn state_code(state: State) -> u8 {
+    match state {
+        State::Susceptible {} => 0,
+        State::Infected {} => 1,
+        State::Recovered {} => 2,
+        State::Deceased {} => 3,
+    }
+}
+
+/// Encodes the citizen states of each hour as a delta from the previous hour, with a keyframe every
+/// `keyframe_interval` hours so that consumers can start from the middle of a run
+pub struct Encoder {
+    keyframe_interval: i32,
+    previous: FxHashMap<u32, EncodedState>,
+}
+
+impl Encoder {
+    pub fn new(keyframe_interval: i32) -> Encoder {
+        Encoder { keyframe_interval, previous: FxHashMap::default() }
+    }
+
+    pub fn encode(&mut self, states: &CitizenStatesAtHr) -> Vec<u8> {
+        let current: Vec<(u32, EncodedState)> = states.citizen_states.iter()
+            .map(|c| (c.citizen_id as u32, EncodedState {
+                state: state_code(c.state),
+                x: c.location.x as u16,
+                y: c.location.y as u16,
+            }))
+            .collect();
+        let is_keyframe = self.previous.is_empty() || states.hr % self.keyframe_interval == 0;
+
+        let mut records: Vec<(u32, EncodedState)> = if is_keyframe {
+            current.clone()
+        } else {
+            current.iter().filter(|(id, state)| self.previous.get(id) != Some(state)).copied().collect()
+        };
+        let next: FxHashMap<u32, EncodedState> = current.into_iter().collect();
+        if !is_keyframe {
+            let mut left: Vec<u32> = self.previous.keys().filter(|id| !next.contains_key(id)).copied().collect();
+            left.sort_unstable();
+            records.extend(left.into_iter().map(|id| (id, EncodedState { state: LEFT_REGION, x: 0, y: 0 })));
+        }
+        self.previous = next;
+
+        let mut frame = Vec::with_capacity(HEADER_SIZE + RECORD_SIZE * records.len());
+        frame.extend_from_slice(MAGIC);
+        frame.push(VERSION);
+        frame.push(if is_keyframe { KEYFRAME } else { DELTA });
+        frame.extend_from_slice(&(states.hr as u32).to_le_bytes());
+        frame.extend_from_slice(&(records.len() as u32).to_le_bytes());
+        for (id, state) in records {
+            frame.extend_from_slice(&id.to_le_bytes());
+            frame.push(state.state);
+            frame.extend_from_slice(&state.x.to_le_bytes());
+            frame.extend_from_slice(&state.y.to_le_bytes());
+        }
+        frame
+    }
+}
+
+/// Rebuilds the state of every citizen from the frames of a run, for consumers of the messages
+#[derive(Default)]
+pub struct Decoder {
+    hour: u32,
+    citizens: FxHashMap<u32, EncodedState>,
+    has_keyframe: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Applies a frame, returning its hour
+    pub fn apply(&mut self, frame: &[u8]) -> Result<u32, Box<dyn Error>> {
+        if frame.len() < HEADER_SIZE || &frame[0..4] != MAGIC {
+            return Err("Not a citizen states frame".into());
+        }
+        if frame[4] != VERSION {
+            return Err(format!("Unsupported citizen states version {}", frame[4]).into());
+        }
+        let is_keyframe = match frame[5] {
+            KEYFRAME => true,
+            DELTA => false,
+            kind => return Err(format!("Unknown kind of frame {}", kind).into()),
+        };
+        if !is_keyframe && !self.has_keyframe {
+            return Err("A delta frame needs a keyframe before it".into());
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([frame[i], frame[i + 1], frame[i + 2], frame[i + 3]]);
+        let u16_at = |i: usize| u16::from_le_bytes([frame[i], frame[i + 1]]);
+        let hour = u32_at(6);
+        let records = u32_at(10) as usize;
+        if frame.len() != HEADER_SIZE + RECORD_SIZE * records {
+            return Err(format!("Expected {} records in the frame for hour {}", records, hour).into());
+        }
+
+        if is_keyframe {
+            self.citizens.clear();
+            self.has_keyframe = true;
+        }
+        for record in 0..records {
+            let start = HEADER_SIZE + RECORD_SIZE * record;
+            let id = u32_at(start);
+            let state = EncodedState { state: frame[start + 4], x: u16_at(start + 5), y: u16_at(start + 7) };
+            if state.state == LEFT_REGION {
+                self.citizens.remove(&id);
+            } else {
+                self.citizens.insert(id, state);
+            }
+        }
+        self.hour = hour;
+        Ok(hour)
+    }
+
+    pub fn get_hour(&self) -> u32 {
+        self.hour
+    }
+
+    pub fn get_citizens(&self) -> &FxHashMap<u32, EncodedState> {
+        &self.citizens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::agent::Citizen;
+    use crate::geography::{Area, Point};
+    use crate::random_wrapper::RandomWrapper;
+
+    use super::*;
+
+    fn citizen(id: i32) -> Citizen {
+        let area = Area::new(Point::new(0, 0), Point::new(1, 1));
+        Citizen::new_citizen(id, area, area, Point::new(2, 2), true, true, &mut RandomWrapper::new())
+    }
+
+    fn states_at(hr: i32, citizens: &[(Citizen, Point)]) -> CitizenStatesAtHr {
+        let mut states = CitizenStatesAtHr::init(citizens.len());
+        states.hr = hr;
+        citizens.iter().for_each(|(citizen, location)| states.update(citizen, location));
+        states
+    }
+
+    #[test]
+    fn should_only_encode_citizens_that_changed_between_keyframes() {
+        let mut encoder = Encoder::new(24);
+        let mut decoder = Decoder::new();
+        let mut infected = citizen(2);
+        infected.infect();
+        let hour_1 = vec![(citizen(1), Point::new(1, 1)), (citizen(2), Point::new(5, 6)), (citizen(3), Point::new(0, 0))];
+        let hour_2 = vec![(citizen(1), Point::new(1, 1)), (infected, Point::new(5, 7))];
+
+        let keyframe = encoder.encode(&states_at(1, &hour_1));
+        let delta = encoder.encode(&states_at(2, &hour_2));
+
+        assert_eq!(keyframe.len(), HEADER_SIZE + 3 * RECORD_SIZE);
+        assert_eq!(delta.len(), HEADER_SIZE + 2 * RECORD_SIZE);
+        assert_eq!(decoder.apply(&keyframe).unwrap(), 1);
+        assert_eq!(decoder.get_citizens().len(), 3);
+        assert_eq!(decoder.apply(&delta).unwrap(), 2);
+        let citizens = decoder.get_citizens();
+        assert_eq!(citizens.len(), 2);
+        assert_eq!(citizens[&1], EncodedState { state: 0, x: 1, y: 1 });
+        assert_eq!(citizens[&2], EncodedState { state: 1, x: 5, y: 7 });
+    }
+
+    #[test]
+    fn should_send_keyframes_at_the_interval() {
+        let mut encoder = Encoder::new(2);
+        let citizens = vec![(citizen(1), Point::new(1, 1))];
+
+        let kinds: Vec<u8> = (1..=4).map(|hr| encoder.encode(&states_at(hr, &citizens))[5]).collect();
+
+        assert_eq!(kinds, vec![KEYFRAME, KEYFRAME, DELTA, KEYFRAME]);
+    }
+
+    #[test]
+    fn should_reject_delta_without_keyframe() {
+        let mut encoder = Encoder::new(24);
+        let citizens = vec![(citizen(1), Point::new(1, 1))];
+        encoder.encode(&states_at(1, &citizens));
+        let delta = encoder.encode(&states_at(2, &citizens));
+
+        let mut decoder = Decoder::new();
+
+        assert!(decoder.apply(&delta).is_err());
+        assert!(decoder.apply(b"{\"hr\": 2}").is_err());
+    }
+
+    /// The frames in `config/test/citizen_states` are shared with the decoders of consumers, so a change to the layout
+    /// shows up here first
+    #[test]
+    fn should_match_the_checked_in_frames() {
+        let keyframe = fs::read("config/test/citizen_states/keyframe.bin").unwrap();
+        let delta = fs::read("config/test/citizen_states/delta.bin").unwrap();
+        let decoded: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("config/test/citizen_states/decoded.json").unwrap()).unwrap();
+        let mut infected = citizen(2);
+        infected.infect();
+        let hour_1 = vec![(citizen(1), Point::new(1, 1)), (citizen(2), Point::new(5, 6)), (citizen(3), Point::new(0, 0))];
+        let hour_2 = vec![(citizen(1), Point::new(1, 1)), (infected, Point::new(5, 7))];
+
+        let mut encoder = Encoder::new(24);
+        assert_eq!(encoder.encode(&states_at(1, &hour_1)), keyframe);
+        assert_eq!(encoder.encode(&states_at(2, &hour_2)), delta);
+
+        let mut decoder = Decoder::new();
+        for (frame, expected) in [keyframe, delta].iter().zip(decoded.as_array().unwrap()) {
+            let hour = decoder.apply(frame).unwrap();
+            let mut citizens: Vec<(&u32, &EncodedState)> = decoder.get_citizens().iter().collect();
+            citizens.sort_by_key(|(id, _)| **id);
+            let citizens: Vec<serde_json::Value> = citizens.into_iter()
+                .map(|(id, c)| serde_json::json!({"citizen_id": id, "state": c.state, "x": c.x, "y": c.y}))
+                .collect();
+            assert_eq!(serde_json::json!({"hour": hour, "citizens": citizens}), *expected);
+        }
+    }
+}
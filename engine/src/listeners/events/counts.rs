@@ -17,6 +17,9 @@
  *
  */
 
+use crate::agent::State;
+use crate::setting::Setting;
+
 #[derive(Serialize, Copy, Clone)]
 pub struct Counts {
     hour: i32,
@@ -25,11 +28,20 @@ pub struct Counts {
     quarantined: i32,
     recovered: i32,
     deceased: i32,
+    /// Citizens infected so far in each setting, in the order of `Setting::ALL`. They are only reported in the summary,
+    /// so the hourly counts keep the columns their readers expect.
+    #[serde(skip)]
+    infections_by_setting: [i32; Setting::ALL.len()],
 }
 
 impl Counts {
     pub fn new(population: i32, infected: i32) -> Counts {
-        Counts { hour: 0, susceptible: population, infected, quarantined: 0, recovered: 0, deceased: 0 }
+        Counts { hour: 0, susceptible: population, infected, quarantined: 0, recovered: 0, deceased: 0,
+            infections_by_setting: [0; Setting::ALL.len()] }
+    }
+
+    pub fn get_susceptible(&self) -> i32 {
+        self.susceptible
     }
 
     pub fn get_infected(&self) -> i32 {
@@ -40,10 +52,22 @@ impl Counts {
         self.quarantined
     }
 
+    pub fn get_recovered(&self) -> i32 {
+        self.recovered
+    }
+
+    pub fn get_deceased(&self) -> i32 {
+        self.deceased
+    }
+
     pub fn get_hour(&self) -> i32 {
         self.hour
     }
 
+    pub fn get_infections_in(&self, setting: Setting) -> i32 {
+        self.infections_by_setting[setting as usize]
+    }
+
     pub fn update_susceptible(&mut self, count: i32) {
         self.susceptible += count;
     }
@@ -64,6 +88,19 @@ impl Counts {
         self.quarantined += count;
     }
 
+    pub fn update_infections_in(&mut self, setting: Setting, count: i32) {
+        self.infections_by_setting[setting as usize] += count;
+    }
+
+    pub fn update_for_state(&mut self, state: State, count: i32) {
+        match state {
+            State::Susceptible {} => self.update_susceptible(count),
+            State::Infected {} => self.update_infected(count),
+            State::Recovered {} => self.update_recovered(count),
+            State::Deceased {} => self.update_deceased(count),
+        }
+    }
+
     pub fn increment_hour(&mut self) {
         self.hour += 1;
     }
@@ -72,7 +109,9 @@ impl Counts {
 
 #[cfg(test)]
 mod tests {
+    use crate::agent::State;
     use crate::listeners::events::counts::Counts;
+    use crate::setting::Setting;
 
     #[test]
     fn should_create_counts() {
@@ -145,6 +184,26 @@ mod tests {
         assert_eq!(counts.hour, 0);
     }
 
+    #[test]
+    fn should_update_for_state() {
+        let mut counts = Counts::new(100, 1);
+        counts.update_for_state(State::Infected {}, 2);
+        counts.update_for_state(State::Susceptible {}, -3);
+        assert_eq!(counts.susceptible, 97);
+        assert_eq!(counts.infected, 3);
+        assert_eq!(counts.recovered, 0);
+        assert_eq!(counts.deceased, 0);
+    }
+
+    #[test]
+    fn should_update_infections_in_setting() {
+        let mut counts = Counts::new(100, 1);
+        counts.update_infections_in(Setting::Work, 2);
+        counts.update_infections_in(Setting::Work, 1);
+        assert_eq!(counts.get_infections_in(Setting::Work), 3);
+        assert_eq!(counts.get_infections_in(Setting::Home), 0);
+    }
+
     #[test]
     fn should_increment_hour() {
         let mut counts = Counts::new(100, 1);
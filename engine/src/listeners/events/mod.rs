@@ -18,4 +18,8 @@
  */
 
 pub mod counts;
-pub mod citizen_state;
\ No newline at end of file
+pub mod citizen_state;
+pub mod summary;
+pub mod simulation_started;
+#[cfg(feature = "kafka")]
+pub mod citizen_state_encoding;
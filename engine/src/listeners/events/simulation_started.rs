@@ -0,0 +1,46 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use chrono::{DateTime, Local};
+
+use crate::config::Config;
+
+/// Sent to the listeners before the first hour of a run
+pub struct SimulationStarted<'a> {
+    pub sim_id: &'a str,
+    pub config: &'a Config,
+    pub population: usize,
+    pub started_at: DateTime<Local>,
+}
+
+impl SimulationStarted<'_> {
+    /// The run as key and value pairs, e.g. for the header of an output file
+    pub fn describe(&self) -> Vec<(String, String)> {
+        let seed = self.config.get_seed().map(|s| s.to_string()).unwrap_or_else(|| "random".to_string());
+        vec![
+            ("engine".to_string(), format!("epirust {}", env!("CARGO_PKG_VERSION"))),
+            ("sim_id".to_string(), self.sim_id.to_string()),
+            ("started_at".to_string(), self.started_at.to_rfc3339()),
+            ("population".to_string(), self.population.to_string()),
+            ("hours".to_string(), self.config.get_hours().to_string()),
+            ("seed".to_string(), seed),
+            ("config".to_string(), serde_json::to_string(self.config).unwrap_or_default()),
+        ]
+    }
+}
@@ -0,0 +1,206 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::listeners::events::counts::Counts;
+use crate::setting::Setting;
+
+/// Statistics about a whole run of the simulation, sent to the listeners when it ends
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct Summary {
+    population: i32,
+    peak_infected: i32,
+    peak_infected_hour: i32,
+    peak_quarantined: i32,
+    peak_quarantined_hour: i32,
+    /// Fraction of the population that got infected
+    attack_rate: f64,
+    total_deceased: i32,
+    /// Fraction of the infected citizens who died
+    case_fatality_rate: f64,
+    /// Hours until no citizen was infected or quarantined, or until the end of the run if the epidemic was still going
+    epidemic_duration: i32,
+    lockdown_hours: i32,
+    vaccinations: i32,
+    /// Citizens infected in each setting, such as `home` or `transport`
+    infections_by_setting: BTreeMap<&'static str, i32>,
+    runtime: Runtime,
+    #[serde(skip)]
+    epidemic_active: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct Runtime {
+    hours_simulated: i32,
+    seconds: f64,
+    hours_per_second: f64,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary::default()
+    }
+
+    pub fn counts_updated(&mut self, counts: &Counts) {
+        if counts.get_infected() > self.peak_infected {
+            self.peak_infected = counts.get_infected();
+            self.peak_infected_hour = counts.get_hour();
+        }
+        if counts.get_quarantined() > self.peak_quarantined {
+            self.peak_quarantined = counts.get_quarantined();
+            self.peak_quarantined_hour = counts.get_hour();
+        }
+        let epidemic_active = counts.get_infected() > 0 || counts.get_quarantined() > 0;
+        if epidemic_active || self.epidemic_active {
+            self.epidemic_duration = counts.get_hour();
+        }
+        self.epidemic_active = epidemic_active;
+        self.runtime.hours_simulated = counts.get_hour();
+
+        self.population = counts.get_susceptible() + counts.get_infected() + counts.get_quarantined()
+            + counts.get_recovered() + counts.get_deceased();
+        let ever_infected = self.population - counts.get_susceptible();
+        self.attack_rate = ratio(ever_infected, self.population);
+        self.total_deceased = counts.get_deceased();
+        self.case_fatality_rate = ratio(counts.get_deceased(), ever_infected);
+        self.infections_by_setting = Setting::ALL.iter()
+            .map(|setting| (setting.name(), counts.get_infections_in(*setting)))
+            .collect();
+    }
+
+    pub fn lockdown_hour(&mut self) {
+        self.lockdown_hours += 1;
+    }
+
+    pub fn vaccinated(&mut self, citizens: i32) {
+        self.vaccinations += citizens;
+    }
+
+    pub fn finish(&mut self, elapsed: Duration) {
+        self.runtime.seconds = elapsed.as_secs_f64();
+        self.runtime.hours_per_second = if self.runtime.seconds > 0.0 {
+            self.runtime.hours_simulated as f64 / self.runtime.seconds
+        } else {
+            0.0
+        };
+    }
+
+    pub fn get_population(&self) -> i32 {
+        self.population
+    }
+
+    pub fn get_peak_infected(&self) -> i32 {
+        self.peak_infected
+    }
+
+    pub fn get_peak_infected_hour(&self) -> i32 {
+        self.peak_infected_hour
+    }
+
+    pub fn get_attack_rate(&self) -> f64 {
+        self.attack_rate
+    }
+
+    pub fn get_total_deceased(&self) -> i32 {
+        self.total_deceased
+    }
+
+    pub fn get_epidemic_duration(&self) -> i32 {
+        self.epidemic_duration
+    }
+
+    pub fn get_hours_simulated(&self) -> i32 {
+        self.runtime.hours_simulated
+    }
+
+    pub fn get_infections_in(&self, setting: Setting) -> i32 {
+        self.infections_by_setting.get(setting.name()).cloned().unwrap_or(0)
+    }
+}
+
+fn ratio(numerator: i32, denominator: i32) -> f64 {
+    if denominator == 0 {
+        return 0.0;
+    }
+    numerator as f64 / denominator as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::agent::State;
+
+    use super::*;
+
+    fn counts_at(hour: i32, infected: i32, recovered: i32, deceased: i32) -> Counts {
+        let mut counts = Counts::new(100 - infected - recovered - deceased, 0);
+        counts.update_for_state(State::Infected {}, infected);
+        counts.update_for_state(State::Recovered {}, recovered);
+        counts.update_for_state(State::Deceased {}, deceased);
+        (0..hour).for_each(|_| counts.increment_hour());
+        counts
+    }
+
+    #[test]
+    fn should_summarise_counts() {
+        let mut summary = Summary::new();
+
+        summary.counts_updated(&counts_at(1, 1, 0, 0));
+        summary.counts_updated(&counts_at(2, 10, 0, 0));
+        summary.counts_updated(&counts_at(3, 5, 5, 0));
+        summary.counts_updated(&counts_at(4, 0, 8, 2));
+        summary.counts_updated(&counts_at(5, 0, 8, 2));
+
+        assert_eq!(summary.peak_infected, 10);
+        assert_eq!(summary.peak_infected_hour, 2);
+        assert_eq!(summary.population, 100);
+        assert_eq!(summary.attack_rate, 0.1);
+        assert_eq!(summary.total_deceased, 2);
+        assert_eq!(summary.case_fatality_rate, 0.2);
+        assert_eq!(summary.epidemic_duration, 4);
+        assert_eq!(summary.runtime.hours_simulated, 5);
+    }
+
+    #[test]
+    fn should_report_infections_by_setting() {
+        let mut summary = Summary::new();
+        let mut counts = counts_at(1, 3, 0, 0);
+        counts.update_infections_in(Setting::Home, 1);
+        counts.update_infections_in(Setting::Transport, 2);
+
+        summary.counts_updated(&counts);
+
+        assert_eq!(summary.get_infections_in(Setting::Home), 1);
+        assert_eq!(summary.get_infections_in(Setting::Transport), 2);
+        assert_eq!(summary.get_infections_in(Setting::Work), 0);
+    }
+
+    #[test]
+    fn should_run_until_the_end_if_the_epidemic_is_not_over() {
+        let mut summary = Summary::new();
+
+        summary.counts_updated(&counts_at(1, 1, 0, 0));
+        summary.counts_updated(&counts_at(2, 3, 0, 0));
+        summary.finish(Duration::from_secs(4));
+
+        assert_eq!(summary.epidemic_duration, 2);
+        assert_eq!(summary.runtime.hours_per_second, 0.5);
+    }
+}
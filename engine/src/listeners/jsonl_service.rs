@@ -0,0 +1,102 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::geography::Point;
+use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary::Summary;
+use crate::listeners::listener::Listener;
+
+/// Writes the counts for every hour as a line of JSON, flushing the file every `flush_interval` hours
+pub struct JsonlListener {
+    output_file_name: String,
+    flush_interval: usize,
+    writer: Option<BufWriter<File>>,
+    lines_since_flush: usize,
+}
+
+impl JsonlListener {
+    pub fn new(output_file_name: String, flush_interval: usize) -> JsonlListener {
+        JsonlListener { output_file_name, flush_interval, writer: None, lines_since_flush: 0 }
+    }
+
+    fn write(&mut self, counts: &Counts) -> Result<(), Box<dyn Error>> {
+        if self.writer.is_none() {
+            self.writer = Some(BufWriter::new(File::create(&self.output_file_name)?));
+        }
+        let writer = self.writer.as_mut().unwrap();
+        serde_json::to_writer(&mut *writer, counts)?;
+        writer.write_all(b"\n")?;
+        self.lines_since_flush += 1;
+        if self.lines_since_flush >= self.flush_interval {
+            writer.flush()?;
+            self.lines_since_flush = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Listener for JsonlListener {
+    fn counts_updated(&mut self, counts: Counts) {
+        if let Err(e) = self.write(&counts) {
+            println!("Error occurred while writing to {}! {:?}", self.output_file_name, e);
+        }
+    }
+
+    fn simulation_ended(&mut self, _summary: &Summary) {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush().expect("Failed to write to file");
+        }
+    }
+
+    fn citizen_got_infected(&mut self, _cell: &Point) {}
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn should_write_a_line_per_hour() {
+        let file_name = std::env::temp_dir().join("epirust_jsonl_listener_test.jsonl");
+        let mut listener = JsonlListener::new(file_name.to_str().unwrap().to_string(), 1);
+        let mut counts = Counts::new(99, 1);
+
+        listener.counts_updated(counts);
+        counts.increment_hour();
+        listener.counts_updated(counts);
+        listener.simulation_ended(&Summary::new());
+
+        let contents = fs::read_to_string(&file_name).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], r#"{"hour":1,"susceptible":99,"infected":1,"quarantined":0,"recovered":0,"deceased":0}"#);
+        fs::remove_file(file_name).unwrap();
+    }
+}
@@ -20,36 +20,52 @@
 
 use std::any::Any;
 
-use rdkafka::ClientConfig;
+use kafka_config::KafkaConfig;
 use rdkafka::producer::{FutureProducer, FutureRecord};
 
 use crate::agent::Citizen;
+use crate::constants;
 use crate::geography::{Grid, Point};
 use crate::listeners::events::citizen_state::CitizenStatesAtHr;
+use crate::listeners::events::citizen_state_encoding::Encoder;
 use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary::Summary;
 use crate::listeners::listener::Listener;
+use crate::listeners::outputs::CitizenStatesEncoding;
 
 pub struct KafkaProducer {
     sim_id: String,
     producer: FutureProducer,
     citizen_states_buffer: CitizenStatesAtHr,
     enable_citizen_state_messages: bool,
+    encoder: Option<Encoder>,
     count_updated_topic: String,
     citizen_states_topic: String,
 }
 
+/// The last message on the counts topic
+#[derive(Serialize)]
+struct SimulationEnded<'a> {
+    simulation_ended: bool,
+    summary: &'a Summary,
+}
+
 impl KafkaProducer {
-    pub fn new(sim_id: String, population_size: usize, enable_citizen_state_messages: bool) -> KafkaProducer {
-        let count_updated_topic = "counts_updated".to_string();
-        let citizen_states_topic = "citizen_states_updated".to_string();
+    pub fn new(sim_id: String, population_size: usize, enable_citizen_state_messages: bool,
+               encoding: CitizenStatesEncoding, kafka_config: &KafkaConfig) -> KafkaProducer {
+        let count_updated_topic = kafka_config.topics.counts_updated.clone();
+        let citizen_states_topic = kafka_config.topics.citizen_states_updated.clone();
         KafkaProducer {
             sim_id,
-            producer: ClientConfig::new()
-                .set("bootstrap.servers", "localhost:9092")
+            producer: kafka_config.client_config()
                 .create()
                 .expect("Could not create Kafka Producer"),
             citizen_states_buffer: CitizenStatesAtHr::init(population_size),
             enable_citizen_state_messages,
+            encoder: match encoding {
+                CitizenStatesEncoding::Json => None,
+                CitizenStatesEncoding::Binary => Some(Encoder::new(constants::NUMBER_OF_HOURS)),
+            },
             count_updated_topic,
             citizen_states_topic,
         }
@@ -65,32 +81,41 @@ impl Listener for KafkaProducer {
         self.producer.send(record, 0);
     }
 
-    fn simulation_ended(&mut self) {
-        let message = r#"{"simulation_ended": true}"#.to_string();
+    fn simulation_ended(&mut self, summary: &Summary) {
+        let message = serde_json::to_string(&SimulationEnded { simulation_ended: true, summary })
+            .expect("Failed to serialize the summary");
         let record: FutureRecord<String, String> = FutureRecord::to(&self.count_updated_topic)
             .key(&self.sim_id)
             .payload(&message);
         self.producer.send(record, 0);
     }
 
-    fn citizen_state_updated(&mut self, hr: i32, citizen: &Citizen, location: &Point) {
-        if !self.enable_citizen_state_messages {
-            return;
+    fn hour_started(&mut self, hr: i32) {
+        if self.enable_citizen_state_messages {
+            self.citizen_states_buffer.start_hour(hr);
         }
-        if self.citizen_states_buffer.hr < hr {
-            //hour incremented, push out all states to kafka
-            let message = serde_json::to_string(&self.citizen_states_buffer)
-                .expect("Failed to serialize citizen states");
-            let record: FutureRecord<String, String> = FutureRecord::to(&self.citizen_states_topic)
-                .key(&self.sim_id)
-                .payload(&message);
-            self.producer.send(record, 0);
-            self.citizen_states_buffer.next_hour();
-        } else {
+    }
+
+    fn citizen_state_updated(&mut self, _hr: i32, citizen: &Citizen, location: &Point) {
+        if self.enable_citizen_state_messages {
             self.citizen_states_buffer.update(citizen, location);
         }
     }
 
+    fn hour_ended(&mut self, _hr: i32) {
+        if !self.enable_citizen_state_messages {
+            return;
+        }
+        let message = match self.encoder.as_mut() {
+            Some(encoder) => encoder.encode(&self.citizen_states_buffer),
+            None => serde_json::to_vec(&self.citizen_states_buffer).expect("Failed to serialize citizen states"),
+        };
+        let record: FutureRecord<String, Vec<u8>> = FutureRecord::to(&self.citizen_states_topic)
+            .key(&self.sim_id)
+            .payload(&message);
+        self.producer.send(record, 0);
+    }
+
     fn grid_updated(&self, grid: &Grid) {
         if self.enable_citizen_state_messages {
             let message = serde_json::to_string(grid);
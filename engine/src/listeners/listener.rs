@@ -22,13 +22,20 @@ use std::any::Any;
 use crate::agent::Citizen;
 use crate::geography::{Point, Grid};
 use crate::listeners::events::counts::Counts;
+use crate::listeners::events::simulation_started::SimulationStarted;
+use crate::listeners::events::summary::Summary;
 
 pub trait Listener {
+    fn simulation_started(&mut self, _started: &SimulationStarted) {}
+    /// Sent before any other event of the hour
+    fn hour_started(&mut self, _hr: i32) {}
     fn counts_updated(&mut self, counts: Counts);
-    fn simulation_ended(&mut self);
+    fn simulation_ended(&mut self, summary: &Summary);
     fn citizen_got_infected(&mut self, _cell: &Point) {}
     fn citizen_state_updated(&mut self, _hr: i32, _citizen: &Citizen, _location: &Point) {}
     fn grid_updated(&self, grid: &Grid) {}
+    /// Sent after every other event of the hour, so that listeners can publish what they buffered for it
+    fn hour_ended(&mut self, _hr: i32) {}
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -43,12 +50,24 @@ impl Listeners {
 }
 
 impl Listeners {
+    pub fn simulation_started(&mut self, started: &SimulationStarted) {
+        self.listeners.iter_mut().for_each(|listener| { listener.simulation_started(started) });
+    }
+
+    pub fn hour_started(&mut self, hr: i32) {
+        self.listeners.iter_mut().for_each(|listener| { listener.hour_started(hr) });
+    }
+
+    pub fn hour_ended(&mut self, hr: i32) {
+        self.listeners.iter_mut().for_each(|listener| { listener.hour_ended(hr) });
+    }
+
     pub fn counts_updated(&mut self, counts: Counts) {
         self.listeners.iter_mut().for_each(|listener| { listener.counts_updated(counts) });
     }
 
-    pub fn simulation_ended(&mut self) {
-        self.listeners.iter_mut().for_each(|listener| { listener.simulation_ended() });
+    pub fn simulation_ended(&mut self, summary: &Summary) {
+        self.listeners.iter_mut().for_each(|listener| { listener.simulation_ended(summary) });
     }
 
     pub fn citizen_got_infected(&mut self, cell: &Point) {
@@ -72,9 +91,12 @@ mod tests {
 
     use crate::geography::Point;
     use crate::listeners::events::counts::Counts;
+    use crate::listeners::events::summary::Summary;
     use crate::listeners::listener::{Listener, Listeners};
 
     struct MockListener {
+        calls_hour_started: u32,
+        calls_hour_ended: u32,
         calls_counts_updated: u32,
         calls_simulation_ended: u32,
         calls_citizen_got_infected: u32,
@@ -83,6 +105,8 @@ mod tests {
     impl MockListener {
         fn new() -> MockListener {
             MockListener {
+                calls_hour_started: 0,
+                calls_hour_ended: 0,
                 calls_counts_updated: 0,
                 calls_simulation_ended: 0,
                 calls_citizen_got_infected: 0,
@@ -91,11 +115,19 @@ mod tests {
     }
 
     impl Listener for MockListener {
+        fn hour_started(&mut self, _hr: i32) {
+            self.calls_hour_started += 1;
+        }
+
+        fn hour_ended(&mut self, _hr: i32) {
+            self.calls_hour_ended += 1;
+        }
+
         fn counts_updated(&mut self, _counts: Counts) {
             self.calls_counts_updated += 1;
         }
 
-        fn simulation_ended(&mut self) {
+        fn simulation_ended(&mut self, _summary: &Summary) {
             self.calls_simulation_ended += 1;
         }
 
@@ -117,13 +149,17 @@ mod tests {
         let mut listeners = Listeners::from(mocks);
 
 
+        listeners.hour_started(1);
         listeners.counts_updated(Counts::new(10, 1));
         listeners.citizen_got_infected(&Point::new(1, 1));
-        listeners.simulation_ended();
+        listeners.hour_ended(1);
+        listeners.simulation_ended(&Summary::new());
 
         for i in 0..=1 {
             //ownership has moved. We need to read the value from the struct, and downcast to MockListener to assert
             let mock = listeners.listeners.get(i).unwrap().as_any().downcast_ref::<MockListener>().unwrap();
+            assert_eq!(mock.calls_hour_started, 1);
+            assert_eq!(mock.calls_hour_ended, 1);
             assert_eq!(mock.calls_counts_updated, 1);
             assert_eq!(mock.calls_citizen_got_infected, 1);
             assert_eq!(mock.calls_simulation_ended, 1);
@@ -17,8 +17,14 @@
  *
  */
 
+#[cfg(feature = "kafka")]
 pub mod kafka_producer;
+#[cfg(feature = "parquet")]
+pub mod parquet_service;
 pub mod csv_service;
+pub mod course_service;
+pub mod jsonl_service;
 pub mod disease_tracker;
 pub mod listener;
-pub mod events;
\ No newline at end of file
+pub mod events;
+pub mod outputs;
\ No newline at end of file
@@ -0,0 +1,316 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use chrono::{DateTime, Local};
+use kafka_config::KafkaConfig;
+
+use crate::config::Config;
+use crate::constants;
+use crate::listeners::course_service::CourseListener;
+use crate::listeners::csv_service::CsvListener;
+use crate::listeners::disease_tracker::Hotspot;
+use crate::listeners::jsonl_service::JsonlListener;
+#[cfg(feature = "kafka")]
+use crate::listeners::kafka_producer::KafkaProducer;
+use crate::listeners::listener::Listener;
+#[cfg(feature = "parquet")]
+use crate::listeners::parquet_service::ParquetListener;
+use crate::validation::{field, ValidationErrors};
+
+/// A sink for the simulation output, selected in the `outputs` section of the config
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub enum Output {
+    Csv(FileOutput),
+    Jsonl(FileOutput),
+    Kafka(KafkaOutput),
+    Hotspot,
+    Parquet(ParquetOutput),
+    /// The course of the disease drawn for every citizen, written once per citizen
+    Courses(CoursesOutput),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct FileOutput {
+    /// Prefix of the output file name, defaults to the `output_file` in the config
+    #[serde(default)]
+    pub output_file: Option<String>,
+    /// Hours of output written between flushes of the file, defaults to every hour
+    #[serde(default)]
+    pub flush_interval: Option<usize>,
+}
+
+impl FileOutput {
+    fn get_flush_interval(&self) -> usize {
+        self.flush_interval.unwrap_or(constants::DEFAULT_FLUSH_INTERVAL).max(1)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct KafkaOutput {
+    /// Defaults to `enable_citizen_state_messages` in the config
+    #[serde(default)]
+    pub enable_citizen_state_messages: Option<bool>,
+    #[serde(default)]
+    pub citizen_states_encoding: CitizenStatesEncoding,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum CitizenStatesEncoding {
+    /// Every citizen as JSON, every hour
+    #[default]
+    Json,
+    /// Only the citizens that changed since the previous hour, in the binary format of `citizen_state_encoding`
+    Binary,
+}
+
+/// The state of every citizen, written to a Parquet file with a row per citizen for each sampled hour
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct ParquetOutput {
+    /// Prefix of the output file name, defaults to the `output_file` in the config
+    #[serde(default)]
+    pub output_file: Option<String>,
+    /// Hours between samples, e.g. 24 writes the citizens once a day
+    #[serde(default = "default_sample_interval")]
+    pub sample_interval: i32,
+    #[serde(default = "Column::all")]
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct CoursesOutput {
+    /// Prefix of the output file name, defaults to the `output_file` in the config
+    #[serde(default)]
+    pub output_file: Option<String>,
+}
+
+fn default_sample_interval() -> i32 {
+    1
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Hour,
+    CitizenId,
+    State,
+    X,
+    Y,
+    Quarantined,
+    Isolated,
+    Vaccinated,
+    /// The days of each stage of the course of the disease drawn for the citizen
+    LatentPeriod,
+    InfectiousPeriod,
+    TimeToQuarantine,
+    TimeToRecovery,
+}
+
+impl Column {
+    pub fn all() -> Vec<Column> {
+        vec![Column::Hour, Column::CitizenId, Column::State, Column::X, Column::Y, Column::Quarantined,
+             Column::Isolated, Column::Vaccinated, Column::LatentPeriod, Column::InfectiousPeriod,
+             Column::TimeToQuarantine, Column::TimeToRecovery]
+    }
+}
+
+impl Output {
+    /// Used when the config doesn't specify any outputs
+    pub fn defaults() -> Vec<Output> {
+        vec![Output::Csv(FileOutput::default()), Output::Hotspot]
+    }
+
+    /// Used in daemon mode when the request doesn't specify any outputs, to send the output back over Kafka
+    pub fn kafka_defaults() -> Vec<Output> {
+        vec![Output::Kafka(KafkaOutput::default()), Output::Hotspot]
+    }
+
+    pub fn create_listener(&self, config: &Config, sim_id: &str, population_size: usize, now: DateTime<Local>,
+                           kafka_config: &KafkaConfig) -> Box<dyn Listener> {
+        match self {
+            Output::Csv(options) => {
+                let file_name = Output::file_name(config, &options.output_file, "csv", now);
+                Box::new(CsvListener::new(file_name, options.get_flush_interval()))
+            }
+            Output::Jsonl(options) => {
+                let file_name = Output::file_name(config, &options.output_file, "jsonl", now);
+                Box::new(JsonlListener::new(file_name, options.get_flush_interval()))
+            }
+            Output::Kafka(options) => {
+                let enable_citizen_state_messages = options.enable_citizen_state_messages
+                    .unwrap_or(config.enable_citizen_state_messages());
+                Output::kafka_listener(sim_id, population_size, enable_citizen_state_messages,
+                                       options.citizen_states_encoding, kafka_config)
+            }
+            Output::Hotspot => Box::new(Hotspot::new()),
+            Output::Parquet(options) => {
+                let file_name = Output::file_name(config, &options.output_file, "parquet", now);
+                Output::parquet_listener(file_name, options)
+            }
+            Output::Courses(options) => {
+                let file_name = Output::file_name(config, &options.output_file, "courses.csv", now);
+                Box::new(CourseListener::new(file_name))
+            }
+        }
+    }
+
+    pub fn validate(&self, path: &str, grid_size: i32, errors: &mut ValidationErrors) {
+        if let Output::Kafka(options) = self {
+            errors.check(cfg!(feature = "kafka"), path.to_string(), "needs an engine built with the `kafka` feature");
+            errors.check(options.citizen_states_encoding != CitizenStatesEncoding::Binary || grid_size <= u16::MAX as i32,
+                         field(&field(path, "Kafka"), "citizen_states_encoding"),
+                         &format!("Binary needs a grid_size of at most {}, but it is {}", u16::MAX, grid_size));
+        }
+        if let Output::Parquet(options) = self {
+            errors.check(cfg!(feature = "parquet"), path.to_string(),
+                         "needs an engine built with the `parquet` feature");
+            let path = &field(path, "Parquet");
+            errors.check(options.sample_interval > 0, field(path, "sample_interval"),
+                         &format!("should be positive, but is {}", options.sample_interval));
+            errors.check(!options.columns.is_empty(), field(path, "columns"), "should have at least one column");
+            let repeated = options.columns.iter().enumerate().any(|(i, column)| options.columns[..i].contains(column));
+            errors.check(!repeated, field(path, "columns"), "should not repeat a column");
+        }
+    }
+
+    #[cfg(feature = "parquet")]
+    fn parquet_listener(file_name: String, options: &ParquetOutput) -> Box<dyn Listener> {
+        Box::new(ParquetListener::new(file_name, options.sample_interval, options.columns.clone()))
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    fn parquet_listener(_file_name: String, _options: &ParquetOutput) -> Box<dyn Listener> {
+        panic!("Parquet output is not available, as the engine was built without the `parquet` feature")
+    }
+
+    #[cfg(feature = "kafka")]
+    fn kafka_listener(sim_id: &str, population_size: usize, enable_citizen_state_messages: bool,
+                      encoding: CitizenStatesEncoding, kafka_config: &KafkaConfig) -> Box<dyn Listener> {
+        Box::new(KafkaProducer::new(sim_id.to_string(), population_size, enable_citizen_state_messages, encoding,
+                                    kafka_config))
+    }
+
+    #[cfg(not(feature = "kafka"))]
+    fn kafka_listener(_sim_id: &str, _population_size: usize, _enable_citizen_state_messages: bool,
+                      _encoding: CitizenStatesEncoding, _kafka_config: &KafkaConfig) -> Box<dyn Listener> {
+        panic!("Kafka output is not available, as the engine was built without the `kafka` feature")
+    }
+
+    fn file_name(config: &Config, output_file: &Option<String>, extension: &str, now: DateTime<Local>) -> String {
+        let output_file_prefix = output_file.clone()
+            .or_else(|| config.get_output_file())
+            .unwrap_or_else(|| "simulation".to_string());
+        format!("{}_{}.{}", output_file_prefix, now.format("%Y-%m-%dT%H:%M:%S"), extension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_outputs() {
+        let outputs: Vec<Output> = serde_json::from_str(r#"[
+            {"Csv": {"flush_interval": 24}},
+            {"Jsonl": {"output_file": "counts"}},
+            {"Kafka": {"enable_citizen_state_messages": true, "citizen_states_encoding": "Binary"}},
+            "Hotspot",
+            {"Parquet": {"sample_interval": 24, "columns": ["hour", "citizen_id", "state"]}},
+            {"Courses": {}}
+        ]"#).unwrap();
+
+        assert_eq!(outputs, vec![
+            Output::Csv(FileOutput { output_file: None, flush_interval: Some(24) }),
+            Output::Jsonl(FileOutput { output_file: Some("counts".to_string()), flush_interval: None }),
+            Output::Kafka(KafkaOutput {
+                enable_citizen_state_messages: Some(true),
+                citizen_states_encoding: CitizenStatesEncoding::Binary,
+            }),
+            Output::Hotspot,
+            Output::Parquet(ParquetOutput {
+                output_file: None,
+                sample_interval: 24,
+                columns: vec![Column::Hour, Column::CitizenId, Column::State],
+            }),
+            Output::Courses(CoursesOutput { output_file: None })
+        ]);
+    }
+
+    #[test]
+    fn should_validate_parquet_output() {
+        let output = Output::Parquet(ParquetOutput {
+            output_file: None,
+            sample_interval: 0,
+            columns: vec![Column::Hour, Column::State, Column::Hour],
+        });
+        let mut errors = ValidationErrors::new();
+
+        output.validate("outputs[0]", 100, &mut errors);
+
+        let fields: Vec<&str> = errors.get_errors().iter().map(|e| e.field.as_str()).collect();
+        let mut expected = vec!["outputs[0].Parquet.sample_interval", "outputs[0].Parquet.columns"];
+        if !cfg!(feature = "parquet") {
+            expected.insert(0, "outputs[0]");
+        }
+        assert_eq!(fields, expected);
+    }
+
+    #[test]
+    fn should_validate_kafka_output() {
+        let output = Output::Kafka(KafkaOutput {
+            enable_citizen_state_messages: None,
+            citizen_states_encoding: CitizenStatesEncoding::Binary,
+        });
+        let mut errors = ValidationErrors::new();
+
+        output.validate("outputs[0]", 70000, &mut errors);
+
+        let fields: Vec<&str> = errors.get_errors().iter().map(|e| e.field.as_str()).collect();
+        let mut expected = vec!["outputs[0].Kafka.citizen_states_encoding"];
+        if !cfg!(feature = "kafka") {
+            expected.insert(0, "outputs[0]");
+        }
+        assert_eq!(fields, expected);
+    }
+
+    #[test]
+    fn should_only_publish_to_kafka_when_asked_or_in_daemon_mode() {
+        let mut config = crate::config::read(String::from("config/test/auto_pop.json")).unwrap();
+        assert!(!config.get_outputs().iter().any(|output| matches!(output, Output::Kafka(_))));
+
+        config.set_default_outputs(Output::kafka_defaults());
+        assert_eq!(config.get_outputs(), Output::kafka_defaults());
+
+        let mut config = config.without_file_outputs();
+        config.set_default_outputs(Output::kafka_defaults());
+        assert_eq!(config.get_outputs(), vec![]);
+    }
+
+    #[test]
+    fn should_name_output_file_after_config_unless_overridden() {
+        let config = crate::config::read(String::from("config/test/auto_pop.json")).unwrap();
+        let now = Local::now();
+        let timestamp = now.format("%Y-%m-%dT%H:%M:%S");
+
+        let default_name = Output::file_name(&config, &None, "csv", now);
+        let overridden = Output::file_name(&config, &Some("counts".to_string()), "jsonl", now);
+
+        assert_eq!(default_name, format!("simulation_default_config_{}.csv", timestamp));
+        assert_eq!(overridden, format!("counts_{}.jsonl", timestamp));
+    }
+}
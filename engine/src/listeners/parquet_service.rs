@@ -0,0 +1,238 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+use std::error::Error;
+use std::fs::File;
+use std::mem;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, BooleanArray, Int32Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::agent::Citizen;
+use crate::geography::Point;
+use crate::listeners::events::citizen_state::CitizenState;
+use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary::Summary;
+use crate::listeners::listener::Listener;
+use crate::listeners::outputs::Column;
+
+/// Writes the state and location of every citizen to a Parquet file, once every `sample_interval` hours. The rows of
+/// each sampled hour are written as one record batch, so only a single hour is held in memory.
+pub struct ParquetListener {
+    output_file_name: String,
+    sample_interval: i32,
+    columns: Vec<Column>,
+    schema: SchemaRef,
+    writer: Option<ArrowWriter<File>>,
+    rows: Rows,
+}
+
+#[derive(Default)]
+struct Rows {
+    hour: Vec<i32>,
+    citizen_id: Vec<i32>,
+    state: Vec<String>,
+    x: Vec<i32>,
+    y: Vec<i32>,
+    quarantined: Vec<bool>,
+    isolated: Vec<bool>,
+    vaccinated: Vec<bool>,
+    latent_period: Vec<i32>,
+    infectious_period: Vec<i32>,
+    time_to_quarantine: Vec<i32>,
+    time_to_recovery: Vec<i32>,
+}
+
+impl Rows {
+    fn take_array(&mut self, column: Column) -> ArrayRef {
+        match column {
+            Column::Hour => Arc::new(Int32Array::from(mem::take(&mut self.hour))),
+            Column::CitizenId => Arc::new(Int32Array::from(mem::take(&mut self.citizen_id))),
+            Column::State => Arc::new(StringArray::from(mem::take(&mut self.state))),
+            Column::X => Arc::new(Int32Array::from(mem::take(&mut self.x))),
+            Column::Y => Arc::new(Int32Array::from(mem::take(&mut self.y))),
+            Column::Quarantined => Arc::new(BooleanArray::from(mem::take(&mut self.quarantined))),
+            Column::Isolated => Arc::new(BooleanArray::from(mem::take(&mut self.isolated))),
+            Column::Vaccinated => Arc::new(BooleanArray::from(mem::take(&mut self.vaccinated))),
+            Column::LatentPeriod => Arc::new(Int32Array::from(mem::take(&mut self.latent_period))),
+            Column::InfectiousPeriod => Arc::new(Int32Array::from(mem::take(&mut self.infectious_period))),
+            Column::TimeToQuarantine => Arc::new(Int32Array::from(mem::take(&mut self.time_to_quarantine))),
+            Column::TimeToRecovery => Arc::new(Int32Array::from(mem::take(&mut self.time_to_recovery))),
+        }
+    }
+}
+
+impl ParquetListener {
+    pub fn new(output_file_name: String, sample_interval: i32, columns: Vec<Column>) -> ParquetListener {
+        let schema = Arc::new(Schema::new(columns.iter().map(|column| {
+            let (name, data_type) = ParquetListener::column_type(*column);
+            Field::new(name, data_type, false)
+        }).collect::<Vec<Field>>()));
+        ParquetListener { output_file_name, sample_interval, columns, schema, writer: None, rows: Rows::default() }
+    }
+
+    fn column_type(column: Column) -> (&'static str, DataType) {
+        match column {
+            Column::Hour => ("hour", DataType::Int32),
+            Column::CitizenId => ("citizen_id", DataType::Int32),
+            Column::State => ("state", DataType::Utf8),
+            Column::X => ("x", DataType::Int32),
+            Column::Y => ("y", DataType::Int32),
+            Column::Quarantined => ("quarantined", DataType::Boolean),
+            Column::Isolated => ("isolated", DataType::Boolean),
+            Column::Vaccinated => ("vaccinated", DataType::Boolean),
+            Column::LatentPeriod => ("latent_period", DataType::Int32),
+            Column::InfectiousPeriod => ("infectious_period", DataType::Int32),
+            Column::TimeToQuarantine => ("time_to_quarantine", DataType::Int32),
+            Column::TimeToRecovery => ("time_to_recovery", DataType::Int32),
+        }
+    }
+
+    fn write_rows(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.rows.hour.is_empty() {
+            return Ok(());
+        }
+        if self.writer.is_none() {
+            let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let file = File::create(&self.output_file_name)?;
+            self.writer = Some(ArrowWriter::try_new(file, self.schema.clone(), Some(properties))?);
+        }
+        let mut rows = mem::take(&mut self.rows);
+        let arrays = self.columns.iter().map(|column| rows.take_array(*column)).collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        self.writer.as_mut().unwrap().write(&batch)?;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.write_rows()?;
+        if let Some(writer) = self.writer.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl Listener for ParquetListener {
+    fn counts_updated(&mut self, _counts: Counts) {}
+
+    fn simulation_ended(&mut self, _summary: &Summary) {
+        self.close().expect("Failed to write to file");
+    }
+
+    fn citizen_state_updated(&mut self, hr: i32, citizen: &Citizen, location: &Point) {
+        if hr % self.sample_interval != 0 {
+            return;
+        }
+        let rows = &mut self.rows;
+        rows.hour.push(hr);
+        rows.citizen_id.push(citizen.id);
+        rows.state.push(CitizenState::state_str(citizen.state_machine.state));
+        rows.x.push(location.x);
+        rows.y.push(location.y);
+        rows.quarantined.push(citizen.is_quarantined());
+        rows.isolated.push(citizen.is_isolated());
+        rows.vaccinated.push(citizen.is_vaccinated());
+        let course = citizen.state_machine.get_course();
+        rows.latent_period.push(course.latent_period);
+        rows.infectious_period.push(course.infectious_period);
+        rows.time_to_quarantine.push(course.time_to_quarantine);
+        rows.time_to_recovery.push(course.time_to_recovery);
+    }
+
+    fn hour_ended(&mut self, _hr: i32) {
+        if let Err(e) = self.write_rows() {
+            println!("Error occurred while writing to {}! {:?}", self.output_file_name, e);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use crate::disease::Disease;
+    use crate::geography::Area;
+    use crate::random_wrapper::RandomWrapper;
+
+    use super::*;
+
+    #[test]
+    fn should_write_sampled_hours_with_selected_columns() {
+        let file_name = std::env::temp_dir().join("epirust_parquet_listener_test.parquet");
+        let mut listener = ParquetListener::new(file_name.to_str().unwrap().to_string(), 2,
+                                                vec![Column::Hour, Column::CitizenId, Column::State]);
+        let area = Area::new(Point::new(0, 0), Point::new(1, 1));
+        let citizen = Citizen::new_citizen(7, area, area, Point::new(2, 2), true, true, &mut RandomWrapper::new());
+
+        for hr in 1..=4 {
+            listener.hour_started(hr);
+            listener.citizen_state_updated(hr, &citizen, &Point::new(1, 1));
+            listener.citizen_state_updated(hr, &citizen, &Point::new(0, 1));
+            listener.hour_ended(hr);
+        }
+        listener.simulation_ended(&Summary::new());
+
+        let reader = SerializedFileReader::new(File::open(&file_name).unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 4);
+        let columns: Vec<&str> = metadata.schema_descr().columns().iter().map(|c| c.name()).collect();
+        assert_eq!(columns, vec!["hour", "citizen_id", "state"]);
+        let hours: Vec<String> = reader.get_row_iter(None).unwrap()
+            .map(|row| row.unwrap().get_column_iter().next().unwrap().1.to_string())
+            .collect();
+        assert_eq!(hours, vec!["2", "2", "4", "4"]);
+        std::fs::remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn should_write_course_of_disease_drawn_for_citizens() {
+        let file_name = std::env::temp_dir().join("epirust_parquet_listener_course_test.parquet");
+        let mut listener = ParquetListener::new(file_name.to_str().unwrap().to_string(), 1,
+                                                vec![Column::LatentPeriod, Column::TimeToRecovery]);
+        let area = Area::new(Point::new(0, 0), Point::new(1, 1));
+        let mut rng = RandomWrapper::new();
+        let mut citizen = Citizen::new_citizen(7, area, area, Point::new(2, 2), true, true, &mut rng);
+        let disease = Disease::new(5, 10, 20, 0.1, 0.5, 0.1);
+        citizen.draw_course(&disease.course_sampler(), &mut rng);
+
+        listener.hour_started(1);
+        listener.citizen_state_updated(1, &citizen, &Point::new(1, 1));
+        listener.hour_ended(1);
+        listener.simulation_ended(&Summary::new());
+
+        let reader = SerializedFileReader::new(File::open(&file_name).unwrap()).unwrap();
+        let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+        let values: Vec<String> = row.get_column_iter().map(|(_, value)| value.to_string()).collect();
+        let course = citizen.state_machine.get_course();
+        assert_eq!(values, vec![course.latent_period.to_string(), course.time_to_recovery.to_string()]);
+        assert_eq!(course.time_to_recovery, 20);
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
@@ -17,31 +17,15 @@
  *
  */
 
-extern crate clap;
 #[macro_use]
-extern crate serde_derive;
-
-use clap::{App, Arg};
-
-use crate::kafka_consumer::KafkaConsumer;
-
-mod constants;
+extern crate clap;
+extern crate kafka_config;
 
-mod agent;
-mod epidemiology_simulation;
-mod allocation_map;
-mod geography;
-mod disease;
-mod random_wrapper;
-mod listeners;
-mod population;
-mod geojson_service;
-mod models;
-mod config;
-mod interventions;
-mod kafka_consumer;
+use clap::{App, Arg, ArgMatches};
+use kafka_config::KafkaConfig;
 
-const STANDALONE_SIM_ID: &str = "0";
+use epirust::{calibration, config, ensemble, multi_region_simulation, sensitivity, STANDALONE_SIM_ID};
+use epirust::{Disease, Simulation, ValidationErrors};
 
 #[tokio::main]
 async fn main() {
@@ -59,21 +43,78 @@ async fn main() {
             .help("Start the engine in daemon mode. It will wait for messages from Kafka. \
             Specifying this flag will cause other arguments to be ignored")
             .takes_value(false))
+        .arg(Arg::with_name("http")
+            .long("http")
+            .value_name("ADDRESS")
+            .help("Start the engine as an HTTP server listening on ADDRESS, e.g. 127.0.0.1:8080, to submit and \
+            follow simulations without Kafka. Specifying this flag will cause other arguments to be ignored")
+            .takes_value(true))
+        .arg(Arg::with_name("http_allow_origin")
+            .long("http-allow-origin")
+            .value_name("ORIGIN")
+            .help("Let pages of ORIGIN, e.g. http://localhost:3000, call the HTTP server from a browser. Other origins \
+            are not allowed by default")
+            .takes_value(true))
         .arg(Arg::with_name("id")
             .long("id")
             .short("i")
             .help("An identifier for the engine. Needed in daemon mode when running a larger simulation \
             distributed across multiple engines.")
             .takes_value(true))
+        .arg(Arg::with_name("ensemble")
+            .long("ensemble")
+            .value_name("FILE")
+            .help("Run an ensemble: several replicates of a config, optionally sweeping over some of its parameters, \
+            with their counts aggregated")
+            .takes_value(true))
+        .arg(Arg::with_name("calibrate")
+            .long("calibrate")
+            .value_name("FILE")
+            .help("Search for the parameter values of a config that best reproduce observed daily cases and deaths")
+            .takes_value(true))
+        .arg(Arg::with_name("sensitivity")
+            .long("sensitivity")
+            .value_name("FILE")
+            .help("Run a global sensitivity analysis of the simulation outputs to some parameters of a config")
+            .takes_value(true))
+        .arg(Arg::with_name("disease")
+            .long("disease")
+            .value_name("NAME")
+            .help("Simulate a disease from the disease library instead of the one in the config file")
+            .takes_value(true))
+        .arg(Arg::with_name("disease_library")
+            .long("disease-library")
+            .value_name("FILE")
+            .default_value("config/diseases.yaml")
+            .help("The disease library to read --disease from")
+            .takes_value(true))
+        .arg(Arg::with_name("max_concurrent_simulations")
+            .long("max-concurrent-simulations")
+            .value_name("N")
+            .default_value("4")
+            .help("The number of simulations run at the same time in daemon or HTTP server mode. Further requests \
+            wait until one of the running simulations is over")
+            .takes_value(true))
+        .arg(Arg::with_name("multi_region")
+            .long("multi-region")
+            .short("m")
+            .help("Treat the config file as a multi-region config, with several regions and travel between them")
+            .takes_value(false))
+        .args(&kafka_config::args())
         .get_matches();
 
     let daemon = matches.is_present("daemon");
+    let kafka_config = KafkaConfig::load(&matches).expect("Failed to read Kafka settings");
 
-    if daemon {
-        println!("Started in daemon mode");
+    if let Some(address) = matches.value_of("http") {
+        let max_concurrent_simulations = value_t!(matches, "max_concurrent_simulations", usize)
+            .unwrap_or_else(|e| e.exit());
+        start_http_server(address, max_concurrent_simulations, matches.value_of("http_allow_origin")).await;
+    } else if daemon {
         let engine_id = matches.value_of("id").unwrap_or("default_engine");
-        let consumer = KafkaConsumer::new(engine_id);
-        consumer.listen_loop().await;
+        let max_concurrent_simulations = value_t!(matches, "max_concurrent_simulations", usize)
+            .unwrap_or_else(|e| e.exit());
+        start_daemon(engine_id, kafka_config, max_concurrent_simulations).await;
     } else {
         let config_file = matches.value_of("config").unwrap_or("config/default.json");
 
@@ -88,10 +129,120 @@ async fn main() {
         //     _ => panic!("Cannot run for {} agents", input_count)
         // };
 
-        let config = config::read(config_file.to_string()).expect("Failed to read config file");
+        if let Some(ensemble_file) = matches.value_of("ensemble") {
+            let result = ensemble::read(ensemble_file).and_then(|ensemble| ensemble.run());
+            if let Err(e) = result {
+                eprintln!("Failed to run ensemble {}\n{}", ensemble_file, e);
+                std::process::exit(1);
+            }
+            println!("Done");
+            return;
+        }
 
-        let mut epidemiology = epidemiology_simulation::Epidemiology::new(&config, STANDALONE_SIM_ID.to_string());
-        epidemiology.run(&config);
+        if let Some(calibration_file) = matches.value_of("calibrate") {
+            let result = calibration::read(calibration_file).and_then(|calibration| calibration.run());
+            if let Err(e) = result {
+                eprintln!("Failed to run calibration {}\n{}", calibration_file, e);
+                std::process::exit(1);
+            }
+            println!("Done");
+            return;
+        }
+
+        if let Some(sensitivity_file) = matches.value_of("sensitivity") {
+            let result = sensitivity::read(sensitivity_file).and_then(|sensitivity| sensitivity.run());
+            if let Err(e) = result {
+                eprintln!("Failed to run sensitivity analysis {}\n{}", sensitivity_file, e);
+                std::process::exit(1);
+            }
+            println!("Done");
+            return;
+        }
+
+        if matches.is_present("multi_region") {
+            let mut config = config::read_multi_region(config_file.to_string()).unwrap_or_else(|e| {
+                eprintln!("Failed to read multi-region config file {}\n{}", config_file, e);
+                std::process::exit(1);
+            });
+            if let Some(disease) = disease_override(&matches) {
+                for region in config.regions.iter_mut() {
+                    region.config.set_disease(disease.clone());
+                }
+            }
+            let mut simulation = multi_region_simulation::MultiRegionSimulation::new(&config, STANDALONE_SIM_ID.to_string());
+            simulation.run(&kafka_config);
+            println!("Done");
+            return;
+        }
+
+        let mut config = config::read(config_file.to_string()).unwrap_or_else(|e| {
+            eprintln!("Failed to read config file {}\n{}", config_file, e);
+            std::process::exit(1);
+        });
+        if let Some(disease) = disease_override(&matches) {
+            config.set_disease(disease);
+        }
+
+        let simulation = Simulation::builder(config).config_outputs(&kafka_config).build().unwrap_or_else(|e| {
+            eprintln!("Failed to start the simulation of {}\n{}", config_file, e);
+            std::process::exit(1);
+        });
+        simulation.run();
         println!("Done");
     }
 }
+
+/// The disease picked with `--disease`, which replaces the one in the config file
+fn disease_override(matches: &ArgMatches) -> Option<Disease> {
+    let name = matches.value_of("disease")?;
+    let library = matches.value_of("disease_library").unwrap();
+    let disease = Disease::from_library(library, name).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let mut errors = ValidationErrors::new();
+    disease.validate("disease", &mut errors);
+    if let Err(e) = errors.into_result() {
+        eprintln!("Disease {} in {} can't be simulated\n{}", name, library, e);
+        std::process::exit(1);
+    }
+    Some(disease)
+}
+
+#[cfg(feature = "kafka")]
+async fn start_daemon(engine_id: &str, kafka_config: KafkaConfig, max_concurrent_simulations: usize) {
+    println!("Started in daemon mode");
+    let consumer = epirust::kafka_consumer::KafkaConsumer::new(engine_id, kafka_config, max_concurrent_simulations);
+    consumer.listen_loop().await;
+}
+
+#[cfg(not(feature = "kafka"))]
+async fn start_daemon(_engine_id: &str, _kafka_config: KafkaConfig, _max_concurrent_simulations: usize) {
+    eprintln!("Daemon mode needs Kafka, but the engine was built without the `kafka` feature");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "http")]
+async fn start_http_server(address: &str, max_concurrent_simulations: usize, allowed_origin: Option<&str>) {
+    let address = address.parse().unwrap_or_else(|e| {
+        eprintln!("Invalid address {} to listen on: {}", address, e);
+        std::process::exit(1);
+    });
+    let mut server = epirust::http_server::HttpServer::new(max_concurrent_simulations);
+    if let Some(origin) = allowed_origin {
+        server = server.allow_origin(origin).unwrap_or_else(|e| {
+            eprintln!("Invalid origin {} to allow: {}", origin, e);
+            std::process::exit(1);
+        });
+    }
+    if let Err(e) = server.serve(address).await {
+        eprintln!("HTTP server failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "http"))]
+async fn start_http_server(_address: &str, _max_concurrent_simulations: usize, _allowed_origin: Option<&str>) {
+    eprintln!("The HTTP server needs the `http` feature, which the engine was built without");
+    std::process::exit(1);
+}
@@ -0,0 +1,249 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::time::Instant;
+
+use fxhash::FxHashSet;
+use kafka_config::KafkaConfig;
+
+use crate::config::{Config, MultiRegionConfig};
+use crate::constants;
+use crate::epidemiology_simulation::{Epidemiology, SimulationRun};
+use crate::geography::Point;
+use crate::interventions::{CloseBorders, Intervention};
+use crate::listeners::events::counts::Counts;
+use crate::listeners::listener::Listeners;
+use crate::random_wrapper::RandomWrapper;
+use crate::travel_plan::TravelPlan;
+
+struct Region {
+    name: String,
+    config: Config,
+    epidemiology: Epidemiology,
+    close_borders: Option<CloseBorders>,
+    are_borders_closed: bool,
+    borders_closed_till: i32,
+}
+
+impl Region {
+    fn update_borders(&mut self, counts: &Counts, simulation_hour: i32) {
+        match self.close_borders {
+            Some(x) if !self.are_borders_closed && counts.get_infected() > x.at_number_of_infections => {
+                println!("Closing the borders of {}", self.name);
+                self.are_borders_closed = true;
+                self.borders_closed_till = simulation_hour + x.close_borders_period * constants::NUMBER_OF_HOURS;
+            }
+            _ => {}
+        }
+        if self.are_borders_closed && self.borders_closed_till == simulation_hour {
+            println!("Opening the borders of {}", self.name);
+            self.are_borders_closed = false;
+        }
+    }
+}
+
+/// Simulates several regions in lockstep, moving citizens between them once a day as per the travel plan
+pub struct MultiRegionSimulation {
+    regions: Vec<Region>,
+    travel_plan: TravelPlan,
+    hours: i32,
+    rng: RandomWrapper,
+}
+
+impl MultiRegionSimulation {
+    pub fn new(config: &MultiRegionConfig, sim_id: String) -> MultiRegionSimulation {
+        let mut regions: Vec<Region> = config.regions.iter().map(|region| {
+            let mut region_config = region.config.clone();
+            if region_config.get_output_file().is_none() {
+                region_config.set_output_file(format!("simulation_{}", region.name));
+            }
+            let epidemiology = Epidemiology::new(&region_config, format!("{}_{}", sim_id, region.name));
+            Region {
+                name: region.name.clone(),
+                close_borders: Intervention::get_close_borders_intervention(&region_config),
+                config: region_config,
+                epidemiology,
+                are_borders_closed: false,
+                borders_closed_till: 0,
+            }
+        }).collect();
+
+        let rng = regions.first_mut().map_or_else(RandomWrapper::new, |region| region.epidemiology.split_rng());
+        MultiRegionSimulation { regions, travel_plan: config.travel_plan.clone(), hours: config.get_hours(), rng }
+    }
+
+    pub fn run(&mut self, kafka_config: &KafkaConfig) {
+        let mut runs: Vec<SimulationRun> = self.regions.iter_mut().map(|region| {
+            let listeners = Listeners::from(region.epidemiology.create_listeners(&region.config, kafka_config));
+            region.epidemiology.start_run(&region.config, listeners)
+        }).collect();
+        let start_time = Instant::now();
+
+        for simulation_hour in 1..self.hours {
+            for (region, run) in self.regions.iter_mut().zip(runs.iter_mut()) {
+                let counts_at_hr = region.epidemiology.run_hour(&region.config, run, simulation_hour);
+                region.update_borders(&counts_at_hr, simulation_hour);
+            }
+
+            if simulation_hour % constants::NUMBER_OF_HOURS == 0 {
+                self.travel(&mut runs, simulation_hour);
+            }
+
+            if runs.iter().all(|run| Epidemiology::stop_simulation(run.counts_at_hr)) {
+                break;
+            }
+
+            if simulation_hour % 100 == 0 {
+                println!("Throughput: {} iterations/sec; simulation hour {} of {}",
+                         simulation_hour as f32 / start_time.elapsed().as_secs_f32(), simulation_hour, self.hours);
+            }
+        }
+        println!("Total Time taken {} seconds", start_time.elapsed().as_secs_f32());
+        runs.iter_mut().for_each(Epidemiology::end_run);
+    }
+
+    fn travel(&mut self, runs: &mut [SimulationRun], simulation_hour: i32) {
+        let closed: Vec<bool> = self.regions.iter().map(|r| r.are_borders_closed).collect();
+        let mut journeys: Vec<(usize, usize, Point)> = Vec::new();
+
+        for from in 0..self.regions.len() {
+            let mut picked: FxHashSet<Point> = FxHashSet::default();
+            for to in 0..self.regions.len() {
+                if from == to || closed[from] || closed[to] {
+                    continue;
+                }
+                let fraction = self.travel_plan.get_travel_fraction(&self.regions[from].name, &self.regions[to].name);
+                let travellers = self.regions[from].epidemiology.pick_travellers(simulation_hour, fraction, &picked, &mut self.rng);
+                for cell in travellers {
+                    picked.insert(cell);
+                    journeys.push((from, to, cell));
+                }
+            }
+        }
+
+        for (from, to, cell) in journeys {
+            match self.regions[to].epidemiology.find_vacant_home(simulation_hour, &mut self.rng) {
+                Some(destination) => {
+                    let citizen = self.regions[from].epidemiology.depart(&mut runs[from].counts_at_hr, simulation_hour, &cell);
+                    self.regions[to].epidemiology.settle(&mut runs[to].counts_at_hr, simulation_hour, citizen,
+                                                         destination, &mut self.rng);
+                }
+                None => println!("Could not find a vacant home in {}, skipping travel", self.regions[to].name)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{AutoPopulation, Population, RegionConfig};
+    use crate::disease::Disease;
+
+    use super::*;
+
+    fn region(name: &str, number_of_agents: i32, interventions: Vec<Intervention>) -> RegionConfig {
+        let pop = AutoPopulation {
+            number_of_agents,
+            public_transport_percentage: 0.2,
+            working_percentage: 0.7,
+            age_distribution: None,
+        };
+        let disease = Disease::new(5, 20, 40, 0.025, 0.25, 0.035);
+        let config = Config::new(Population::Auto(pop), disease, vec![], 50, 100, interventions, None);
+        RegionConfig { name: name.to_string(), config }
+    }
+
+    fn simulation(interventions: Vec<Intervention>) -> (MultiRegionSimulation, Vec<SimulationRun>) {
+        seeded_simulation(interventions, None)
+    }
+
+    fn seeded_simulation(interventions: Vec<Intervention>, seed: Option<u64>) -> (MultiRegionSimulation, Vec<SimulationRun>) {
+        let travel_plan = TravelPlan::new(vec!["a".to_string(), "b".to_string()],
+                                          vec![vec![0.0, 0.5], vec![0.0, 0.0]]);
+        let mut regions = vec![region("a", 100, interventions), region("b", 100, vec![])];
+        if let Some(seed) = seed {
+            regions.iter_mut().for_each(|region| region.config.set_seed(seed));
+        }
+        let config = MultiRegionConfig { regions, travel_plan };
+        let mut simulation = MultiRegionSimulation::new(&config, "id".to_string());
+        let runs = simulation.regions.iter_mut()
+            .map(|r| r.epidemiology.start_run(&r.config, Listeners::from(vec![])))
+            .collect();
+        (simulation, runs)
+    }
+
+    fn population(simulation: &mut MultiRegionSimulation, region: usize, hour: i32) -> usize {
+        simulation.regions[region].epidemiology.current_location_map(hour).len()
+    }
+
+    #[test]
+    fn should_move_citizens_between_regions() {
+        let (mut simulation, mut runs) = simulation(vec![]);
+        let hour = 24;
+
+        simulation.travel(&mut runs, hour);
+
+        assert_eq!(population(&mut simulation, 0, hour), 50);
+        assert_eq!(population(&mut simulation, 1, hour), 150);
+    }
+
+    #[test]
+    fn should_repeat_travel_with_the_same_seed() {
+        let cells_after_a_day = |seed| {
+            let (mut simulation, mut runs) = seeded_simulation(vec![], Some(seed));
+            let hour = constants::NUMBER_OF_HOURS;
+            for simulation_hour in 1..=hour {
+                for (region, run) in simulation.regions.iter_mut().zip(runs.iter_mut()) {
+                    region.epidemiology.run_hour(&region.config, run, simulation_hour);
+                }
+            }
+            simulation.travel(&mut runs, hour);
+            (0..2).map(|region| {
+                let mut cells: Vec<(i32, Point)> = simulation.regions[region].epidemiology.current_location_map(hour)
+                    .iter()
+                    .map(|(cell, citizen)| (citizen.id, *cell))
+                    .collect();
+                cells.sort_by_key(|(id, cell)| (*id, cell.x, cell.y));
+                cells
+            }).collect::<Vec<_>>()
+        };
+
+        let cells = cells_after_a_day(9);
+
+        assert_eq!(cells[1].len(), 150);
+        assert_eq!(cells, cells_after_a_day(9));
+    }
+
+    #[test]
+    fn should_not_travel_when_borders_are_closed() {
+        let close_borders = Intervention::CloseBorders(CloseBorders::new(0, 1));
+        let (mut simulation, mut runs) = simulation(vec![close_borders]);
+        let hour = 24;
+
+        let counts = Counts::new(99, 1);
+        simulation.regions[0].update_borders(&counts, hour);
+        simulation.travel(&mut runs, hour);
+
+        assert_eq!(population(&mut simulation, 0, hour), 100);
+        assert_eq!(population(&mut simulation, 1, hour), 100);
+
+        simulation.regions[0].update_borders(&counts, hour + constants::NUMBER_OF_HOURS);
+        assert!(!simulation.regions[0].are_borders_closed);
+    }
+}
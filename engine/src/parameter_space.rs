@@ -0,0 +1,110 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// Numeric parameters of a config, named by their path, each with a range of values to explore
+pub struct ParameterSpace {
+    names: Vec<String>,
+    bounds: Vec<(f64, f64)>,
+    is_integer: Vec<bool>,
+}
+
+impl ParameterSpace {
+    /// Checks that every parameter is a number in the config, and notes which of them are whole numbers
+    pub fn new(ranges: &BTreeMap<String, (f64, f64)>, config: &Config) -> Result<ParameterSpace, Box<dyn Error>> {
+        let mut is_integer = Vec::new();
+        for (path, (lower, upper)) in ranges {
+            if lower > upper {
+                return Err(format!("The lower bound of {} is above its upper bound", path).into());
+            }
+            match config.get_parameter(path) {
+                Some(Value::Number(n)) => is_integer.push(!n.is_f64()),
+                _ => return Err(format!("{} is not a number in the config", path).into()),
+            }
+        }
+        Ok(ParameterSpace {
+            names: ranges.keys().cloned().collect(),
+            bounds: ranges.values().cloned().collect(),
+            is_integer,
+        })
+    }
+
+    pub fn get_names(&self) -> &Vec<String> {
+        &self.names
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The parameter values at a point of the unit hypercube, where 0 is the lower bound and 1 the upper one
+    pub fn scale(&self, point: &[f64]) -> Vec<f64> {
+        point.iter().zip(self.bounds.iter())
+            .map(|(x, (lower, upper))| lower + x.clamp(0.0, 1.0) * (upper - lower))
+            .collect()
+    }
+
+    /// The values as config parameters, rounding those that are whole numbers in the config
+    pub fn to_parameters(&self, values: &[f64]) -> Vec<(String, Value)> {
+        self.names.iter().zip(values.iter()).zip(self.is_integer.iter())
+            .map(|((path, value), is_integer)| {
+                let value = if *is_integer { Value::from(value.round() as i64) } else { Value::from(*value) };
+                (path.clone(), value)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn should_scale_points_and_round_whole_numbers() {
+        let config = config::read(String::from("config/default.json")).unwrap();
+        let mut ranges = BTreeMap::new();
+        ranges.insert("disease.death_rate".to_string(), (0.0, 0.2));
+        ranges.insert("grid_size".to_string(), (200.0, 300.0));
+        let space = ParameterSpace::new(&ranges, &config).unwrap();
+
+        let values = space.scale(&[0.5, 0.333]);
+
+        assert_eq!(values, vec![0.1, 233.3]);
+        assert_eq!(space.to_parameters(&values), vec![("disease.death_rate".to_string(), Value::from(0.1)),
+                                                       ("grid_size".to_string(), Value::from(233))]);
+    }
+
+    #[test]
+    fn should_only_accept_numeric_parameters() {
+        let config = config::read(String::from("config/default.json")).unwrap();
+        let mut ranges = BTreeMap::new();
+        ranges.insert("output_file".to_string(), (0.0, 1.0));
+
+        let error = ParameterSpace::new(&ranges, &config).err().unwrap();
+
+        assert_eq!(error.to_string(), "output_file is not a number in the config");
+    }
+}
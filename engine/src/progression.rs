@@ -0,0 +1,176 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use rand_distr::{Gamma, LogNormal};
+
+use crate::random_wrapper::RandomWrapper;
+use crate::validation::{field, ValidationErrors};
+
+/// A number of days, drawn for each citizen. Draws from the continuous distributions are rounded to whole days.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub enum DaysDistribution {
+    Fixed(u32),
+    /// Any whole number of days from `min` to `max`, all equally likely
+    Uniform { min: u32, max: u32 },
+    /// With a mean of `shape * scale` days
+    Gamma { shape: f64, scale: f64 },
+    /// The days are `e^x`, where `x` is normally distributed with mean `meanlog` and standard deviation `sdlog`
+    LogNormal { meanlog: f64, sdlog: f64 },
+    /// `weights[k]` is how likely it is to take `k` days, such as the share of the cases in a line list
+    Empirical { weights: Vec<f64> },
+}
+
+impl DaysDistribution {
+    pub fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        match self {
+            DaysDistribution::Fixed(_) => {}
+            DaysDistribution::Uniform { min, max } => {
+                if min > max {
+                    errors.add(field(&field(path, "Uniform"), "max"),
+                               format!("should not be below min ({}), but is {}", min, max));
+                }
+            }
+            DaysDistribution::Gamma { shape, scale } => {
+                let gamma_field = field(path, "Gamma");
+                check_positive(*shape, field(&gamma_field, "shape"), errors);
+                check_positive(*scale, field(&gamma_field, "scale"), errors);
+            }
+            DaysDistribution::LogNormal { meanlog, sdlog } => {
+                let log_normal_field = field(path, "LogNormal");
+                if !meanlog.is_finite() {
+                    errors.add(field(&log_normal_field, "meanlog"), format!("should be a number, but is {}", meanlog));
+                }
+                if !(sdlog.is_finite() && *sdlog >= 0.0) {
+                    errors.add(field(&log_normal_field, "sdlog"),
+                               format!("should be a number that is not negative, but is {}", sdlog));
+                }
+            }
+            DaysDistribution::Empirical { weights } => {
+                let weights_field = field(&field(path, "Empirical"), "weights");
+                if weights.iter().any(|w| !(w.is_finite() && *w >= 0.0)) {
+                    errors.add(weights_field, "should not be negative".to_string());
+                } else if !weights.iter().any(|w| *w > 0.0) {
+                    errors.add(weights_field, "should have at least one weight above 0".to_string());
+                }
+            }
+        }
+    }
+
+    fn sampler(&self) -> DaysSampler {
+        match self {
+            DaysDistribution::Fixed(days) => DaysSampler::Fixed(*days as i32),
+            DaysDistribution::Uniform { min, max } => DaysSampler::Uniform(*min as i32, *max as i32),
+            DaysDistribution::Gamma { shape, scale } => {
+                DaysSampler::Gamma(Gamma::new(*shape, *scale).expect("Invalid gamma distribution of days"))
+            }
+            DaysDistribution::LogNormal { meanlog, sdlog } => {
+                DaysSampler::LogNormal(LogNormal::new(*meanlog, *sdlog).expect("Invalid log-normal distribution of days"))
+            }
+            DaysDistribution::Empirical { weights } => {
+                DaysSampler::Empirical(WeightedIndex::new(weights).expect("Invalid weights of empirical days"))
+            }
+        }
+    }
+}
+
+fn check_positive(value: f64, field: String, errors: &mut ValidationErrors) {
+    if !(value.is_finite() && value > 0.0) {
+        errors.add(field, format!("should be a positive number, but is {}", value));
+    }
+}
+
+pub enum DaysSampler {
+    Fixed(i32),
+    Uniform(i32, i32),
+    Gamma(Gamma<f64>),
+    LogNormal(LogNormal<f64>),
+    Empirical(WeightedIndex<f64>),
+}
+
+impl DaysSampler {
+    fn sample(&self, rng: &mut RandomWrapper) -> i32 {
+        match self {
+            DaysSampler::Fixed(days) => *days,
+            DaysSampler::Uniform(min, max) => rng.get().gen_range(*min, *max + 1),
+            DaysSampler::Gamma(gamma) => gamma.sample(rng.get()).round() as i32,
+            DaysSampler::LogNormal(log_normal) => log_normal.sample(rng.get()).round() as i32,
+            DaysSampler::Empirical(weights) => weights.sample(rng.get()) as i32,
+        }
+    }
+}
+
+/// The course of the disease in a citizen, in days since they got infected
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Course {
+    /// Days before the citizen starts passing the infection on
+    pub latent_period: i32,
+    /// Days the citizen passes the infection on for, once they have started
+    pub infectious_period: i32,
+    /// Days until the citizen reaches the high rate of transmission and gets quarantined
+    pub time_to_quarantine: i32,
+    /// Days until the citizen recovers or dies. It is always after they are quarantined, as only quarantined citizens
+    /// recover or die.
+    pub time_to_recovery: i32,
+}
+
+/// How many days a stage of the disease takes in a citizen
+pub enum Stage {
+    Drawn(DaysSampler),
+    Fixed(i32),
+    /// A fixed number of days, made shorter or longer by the immunity of the citizen
+    ShiftedByImmunity(i32),
+}
+
+impl Stage {
+    /// Draws the days from the distribution if there is one, and falls back on the fixed days otherwise
+    pub fn new(distribution: &Option<DaysDistribution>, fallback: Stage) -> Stage {
+        match distribution {
+            Some(distribution) => Stage::Drawn(distribution.sampler()),
+            None => fallback,
+        }
+    }
+
+    fn days(&self, immunity: i32, rng: &mut RandomWrapper) -> i32 {
+        match self {
+            Stage::Drawn(sampler) => sampler.sample(rng),
+            Stage::Fixed(days) => *days,
+            Stage::ShiftedByImmunity(days) => days - immunity,
+        }
+    }
+}
+
+/// Draws the course of the disease for citizens
+pub struct CourseSampler {
+    pub latent_period: Stage,
+    pub infectious_period: Stage,
+    pub time_to_quarantine: Stage,
+    pub time_to_recovery: Stage,
+}
+
+impl CourseSampler {
+    pub fn draw(&self, immunity: i32, rng: &mut RandomWrapper) -> Course {
+        let latent_period = self.latent_period.days(immunity, rng);
+        let infectious_period = self.infectious_period.days(immunity, rng);
+        let time_to_quarantine = self.time_to_quarantine.days(immunity, rng);
+        let time_to_recovery = self.time_to_recovery.days(immunity, rng).max(time_to_quarantine.saturating_add(1));
+        Course { latent_period, infectious_period, time_to_quarantine, time_to_recovery }
+    }
+}
@@ -17,19 +17,55 @@
  *
  */
 
-use rand::thread_rng;
-use rand::rngs::ThreadRng;
+use rand::{SeedableRng, thread_rng};
+use rand::rngs::StdRng;
 
 pub struct RandomWrapper {
-    rng: ThreadRng,
+    rng: StdRng,
+}
+
+impl Default for RandomWrapper {
+    fn default() -> Self {
+        RandomWrapper::new()
+    }
 }
 
 impl RandomWrapper {
     pub fn new() -> RandomWrapper {
-        RandomWrapper { rng: thread_rng() }
+        RandomWrapper { rng: StdRng::from_rng(thread_rng()).expect("Failed to seed random number generator") }
+    }
+
+    /// A generator that gives the same numbers every time for the same seed, or a random one without a seed
+    pub fn with_seed(seed: Option<u64>) -> RandomWrapper {
+        match seed {
+            Some(seed) => RandomWrapper { rng: StdRng::seed_from_u64(seed) },
+            None => RandomWrapper::new(),
+        }
+    }
+
+    /// A new generator seeded from this one, so that seeded runs stay reproducible
+    pub fn split(&mut self) -> RandomWrapper {
+        RandomWrapper { rng: StdRng::from_rng(&mut self.rng).expect("Failed to seed random number generator") }
     }
 
-    pub fn get(&mut self) -> &mut ThreadRng {
+    pub fn get(&mut self) -> &mut StdRng {
         &mut self.rng
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn should_repeat_numbers_for_the_same_seed() {
+        let numbers = |rng: &mut RandomWrapper| -> Vec<u32> { (0..10).map(|_| rng.get().gen()).collect() };
+
+        assert_eq!(numbers(&mut RandomWrapper::with_seed(Some(7))), numbers(&mut RandomWrapper::with_seed(Some(7))));
+        assert_ne!(numbers(&mut RandomWrapper::with_seed(Some(7))), numbers(&mut RandomWrapper::with_seed(Some(8))));
+        assert_eq!(numbers(&mut RandomWrapper::with_seed(Some(7)).split()),
+                   numbers(&mut RandomWrapper::with_seed(Some(7)).split()));
+    }
+}
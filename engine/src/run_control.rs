@@ -0,0 +1,86 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+
+use crate::validation::ValidationError;
+
+/// Lets the caller of a simulation run observe its progress and stop it between hours
+pub trait RunControl {
+    fn run_started(&mut self, _total_hours: i32) {}
+
+    /// Called before every simulation hour; the run stops when this returns false. Implementations may block here to
+    /// pause the simulation.
+    fn should_continue(&mut self, simulation_hour: i32) -> bool;
+
+    /// Called once the run is over, with the last simulated hour and whether the run was stopped by `should_continue`
+    fn run_ended(&mut self, _last_hour: i32, _cancelled: bool) {}
+}
+
+/// Runs the simulation to completion
+pub struct Uncontrolled;
+
+impl RunControl for Uncontrolled {
+    fn should_continue(&mut self, _simulation_hour: i32) -> bool {
+        true
+    }
+}
+
+/// The lifecycle of a simulation run by a daemon, as reported to whoever asked for it
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Status {
+    Queued,
+    Accepted,
+    Running { total_hours: i32 },
+    Progress { hour: i32, total_hours: i32 },
+    Paused { hour: i32 },
+    Resumed { hour: i32 },
+    Cancelled { hour: i32 },
+    Finished { hour: i32 },
+    Failed { error: String },
+    Invalid { errors: Vec<ValidationError> },
+}
+
+/// The message of a panic caught while running a simulation, for reporting it as a failure
+pub fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Simulation panicked".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+
+    #[test]
+    fn should_read_panic_message() {
+        let result = panic::catch_unwind(|| panic!("population is too large"));
+        assert_eq!(panic_message(result.unwrap_err()), "population is too large");
+
+        let result = panic::catch_unwind(|| panic!("hour {} is out of range", 5));
+        assert_eq!(panic_message(result.unwrap_err()), "hour 5 is out of range");
+    }
+}
@@ -0,0 +1,456 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+
+use csv::Writer;
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::config;
+use crate::config::Config;
+use crate::ensemble::{check_parallelism, run_all, Run};
+use crate::listeners::events::counts::Counts;
+use crate::parameter_space::ParameterSpace;
+use crate::random_wrapper::RandomWrapper;
+use crate::validation::ValidationErrors;
+
+/// Finds out which parameters of a config matter most for the course of the epidemic, by running the simulation
+/// for many samples of the parameters within their ranges
+#[derive(Debug, Deserialize)]
+pub struct SensitivityConfig {
+    config: String,
+    parameters: BTreeMap<String, (f64, f64)>,
+    method: Method,
+    #[serde(default)]
+    seed: u64,
+    output_dir: String,
+    parallelism: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub enum Method {
+    /// Latin hypercube samples, with partial rank correlation coefficients (PRCC) between each parameter and output
+    LatinHypercube { samples: usize },
+    /// First order and total Sobol indices, estimated with Saltelli's scheme from `samples * (parameters + 2)` runs
+    Sobol { samples: usize },
+}
+
+const OUTPUTS: [&str; 3] = ["peak_infected", "peak_hour", "total_deceased"];
+
+fn outputs(counts: &[Counts]) -> [f64; 3] {
+    let peak = counts.iter().max_by_key(|c| c.get_infected());
+    let total_deceased = counts.last().map(|c| c.get_deceased()).unwrap_or(0);
+    [
+        peak.map(|c| c.get_infected()).unwrap_or(0) as f64,
+        peak.map(|c| c.get_hour()).unwrap_or(0) as f64,
+        total_deceased as f64,
+    ]
+}
+
+pub fn read(filename: &str) -> Result<SensitivityConfig, Box<dyn Error>> {
+    let reader = File::open(filename)?;
+    let sensitivity: SensitivityConfig = serde_json::from_reader(reader)?;
+    Ok(sensitivity)
+}
+
+impl SensitivityConfig {
+    /// Writes the parameters and outputs of every run to `samples.csv`, and the sensitivity indices to `indices.csv`
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let mut errors = ValidationErrors::new();
+        check_parallelism(self.parallelism, &mut errors);
+        errors.into_result()?;
+        let base_config = config::read(self.config.clone())?;
+        let space = ParameterSpace::new(&self.parameters, &base_config)?;
+        let dimensions = space.dimensions();
+        let mut rng = RandomWrapper::with_seed(Some(self.seed));
+
+        let points = match self.method {
+            Method::LatinHypercube { samples } => {
+                check_prcc_samples(samples, dimensions)?;
+                latin_hypercube(samples, dimensions, &mut rng)
+            }
+            Method::Sobol { samples } => saltelli_points(samples, dimensions, &mut rng),
+        };
+        let samples: Vec<Vec<f64>> = points.iter().map(|point| space.scale(point)).collect();
+        let configs: Vec<Option<Config>> = samples.iter()
+            .map(|values| {
+                base_config.with_parameters(&space.to_parameters(values))
+                    .map_err(|e| println!("Dropping the sample {:?}: {}", values, e))
+                    .ok()
+            })
+            .collect();
+        let kept = self.kept_samples(&configs, dimensions);
+        let runs: Vec<Run> = configs.into_iter().enumerate()
+            .filter(|(i, _)| kept[*i])
+            .map(|(i, config)| {
+                let mut config = config.unwrap();
+                config.set_seed(self.seed + i as u64);
+                Run { config, output_file: None }
+            })
+            .collect();
+        let samples: Vec<Vec<f64>> = samples.into_iter().zip(kept.iter()).filter(|(_, kept)| **kept)
+            .map(|(values, _)| values)
+            .collect();
+        if runs.is_empty() {
+            return Err("Every sample makes an invalid config".into());
+        }
+        let results: Vec<[f64; 3]> = run_all(&runs, self.parallelism).iter().map(|counts| outputs(counts)).collect();
+
+        fs::create_dir_all(&self.output_dir)?;
+        self.write_samples(&space, &samples, &results)?;
+        let mut writer = Writer::from_path(Path::new(&self.output_dir).join("indices.csv"))?;
+        match self.method {
+            Method::LatinHypercube { .. } => {
+                check_prcc_samples(samples.len(), dimensions)?;
+                writer.write_record(["parameter", "output", "prcc"])?;
+                for (o, output) in OUTPUTS.iter().enumerate() {
+                    let y: Vec<f64> = results.iter().map(|r| r[o]).collect();
+                    for (p, name) in space.get_names().iter().enumerate() {
+                        writer.write_record([name, *output, &prcc(&samples, &y, p).to_string()])?;
+                    }
+                }
+            }
+            Method::Sobol { .. } => {
+                let samples = samples.len() / (dimensions + 2);
+                writer.write_record(["parameter", "output", "first_order", "total_order"])?;
+                for (o, output) in OUTPUTS.iter().enumerate() {
+                    let y: Vec<f64> = results.iter().map(|r| r[o]).collect();
+                    for (p, name) in space.get_names().iter().enumerate() {
+                        let (first_order, total_order) = sobol_indices(&y, samples, p);
+                        writer.write_record([name, *output, &first_order.to_string(), &total_order.to_string()])?;
+                    }
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Which of the samples are run, leaving out the ones whose config is invalid. Saltelli's scheme compares the
+    /// rows of A, B and every AB_i with the same index, so a Sobol sample is left out of all of them together.
+    fn kept_samples(&self, configs: &[Option<Config>], dimensions: usize) -> Vec<bool> {
+        match self.method {
+            Method::LatinHypercube { .. } => configs.iter().map(Option::is_some).collect(),
+            Method::Sobol { samples } => {
+                let valid: Vec<bool> = (0..samples)
+                    .map(|j| (0..dimensions + 2).all(|matrix| configs[matrix * samples + j].is_some()))
+                    .collect();
+                (0..configs.len()).map(|i| valid[i % samples]).collect()
+            }
+        }
+    }
+
+    fn write_samples(&self, space: &ParameterSpace, samples: &[Vec<f64>], results: &[[f64; 3]]) -> Result<(), Box<dyn Error>> {
+        let mut writer = Writer::from_path(Path::new(&self.output_dir).join("samples.csv"))?;
+        let mut header = vec!["sample".to_string()];
+        header.extend(space.get_names().iter().cloned());
+        header.extend(OUTPUTS.iter().map(|o| o.to_string()));
+        writer.write_record(&header)?;
+        for (i, (values, result)) in samples.iter().zip(results.iter()).enumerate() {
+            let mut record = vec![i.to_string()];
+            record.extend(values.iter().chain(result.iter()).map(|v| v.to_string()));
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn check_prcc_samples(samples: usize, dimensions: usize) -> Result<(), Box<dyn Error>> {
+    if samples <= dimensions + 1 {
+        return Err(format!("PRCC needs more than {} samples for {} parameters, but has {}", dimensions + 1, dimensions,
+                           samples).into());
+    }
+    Ok(())
+}
+
+/// Points in the unit hypercube, with exactly one point in each of the `samples` equal slices of every dimension
+fn latin_hypercube(samples: usize, dimensions: usize, rng: &mut RandomWrapper) -> Vec<Vec<f64>> {
+    let mut points = vec![vec![0.0; dimensions]; samples];
+    for d in 0..dimensions {
+        let mut slices: Vec<usize> = (0..samples).collect();
+        slices.shuffle(rng.get());
+        for (point, slice) in points.iter_mut().zip(slices) {
+            point[d] = (slice as f64 + rng.get().gen::<f64>()) / samples as f64;
+        }
+    }
+    points
+}
+
+/// The points of Saltelli's scheme: the rows of two independent sample matrices A and B, followed by the rows of
+/// each matrix AB_i, which is A with its column i taken from B
+fn saltelli_points(samples: usize, dimensions: usize, rng: &mut RandomWrapper) -> Vec<Vec<f64>> {
+    let base = latin_hypercube(samples, 2 * dimensions, rng);
+    let a: Vec<Vec<f64>> = base.iter().map(|row| row[..dimensions].to_vec()).collect();
+    let b: Vec<Vec<f64>> = base.iter().map(|row| row[dimensions..].to_vec()).collect();
+    let mut points = a.clone();
+    points.extend(b.iter().cloned());
+    for i in 0..dimensions {
+        points.extend(a.iter().zip(b.iter()).map(|(a_row, b_row)| {
+            let mut row = a_row.clone();
+            row[i] = b_row[i];
+            row
+        }));
+    }
+    points
+}
+
+/// The first order (Saltelli 2010) and total (Jansen) Sobol indices of a parameter, from the outputs of the points
+/// given by `saltelli_points`
+fn sobol_indices(y: &[f64], samples: usize, parameter: usize) -> (f64, f64) {
+    let f_a = &y[..samples];
+    let f_b = &y[samples..2 * samples];
+    let f_ab = &y[(2 + parameter) * samples..(3 + parameter) * samples];
+    let variance = variance(&y[..2 * samples]);
+    if variance == 0.0 {
+        return (0.0, 0.0);
+    }
+    let n = samples as f64;
+    let first_order = (0..samples).map(|j| f_b[j] * (f_ab[j] - f_a[j])).sum::<f64>() / n / variance;
+    let total_order = (0..samples).map(|j| (f_a[j] - f_ab[j]).powi(2)).sum::<f64>() / (2.0 * n) / variance;
+    (first_order, total_order)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn variance(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+}
+
+/// Ranks starting from 1, with tied values sharing the mean of their ranks
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap());
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start;
+        while end + 1 < order.len() && values[order[end + 1]] == values[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end) as f64 / 2.0 + 1.0;
+        for i in start..=end {
+            ranks[order[i]] = rank;
+        }
+        start = end + 1;
+    }
+    ranks
+}
+
+fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let (mean_x, mean_y) = (mean(x), mean(y));
+    let covariance: f64 = x.iter().zip(y.iter()).map(|(a, b)| (a - mean_x) * (b - mean_y)).sum();
+    let spread_x: f64 = x.iter().map(|a| (a - mean_x).powi(2)).sum::<f64>().sqrt();
+    let spread_y: f64 = y.iter().map(|b| (b - mean_y).powi(2)).sum::<f64>().sqrt();
+    if spread_x == 0.0 || spread_y == 0.0 {
+        return 0.0;
+    }
+    covariance / (spread_x * spread_y)
+}
+
+/// The partial rank correlation coefficient between a parameter and an output: the correlation of their ranks, once
+/// the linear effect of the ranks of the other parameters is taken out of both
+fn prcc(samples: &[Vec<f64>], y: &[f64], parameter: usize) -> f64 {
+    let dimensions = samples[0].len();
+    let ranked: Vec<Vec<f64>> = (0..dimensions)
+        .map(|d| ranks(&samples.iter().map(|s| s[d]).collect::<Vec<f64>>()))
+        .collect();
+    let others: Vec<Vec<f64>> = (0..samples.len())
+        .map(|i| {
+            let mut row = vec![1.0];
+            row.extend((0..dimensions).filter(|d| *d != parameter).map(|d| ranked[d][i]));
+            row
+        })
+        .collect();
+    let x_residuals = residuals(&others, &ranked[parameter]);
+    let y_residuals = residuals(&others, &ranks(y));
+    correlation(&x_residuals, &y_residuals)
+}
+
+/// What is left of `y` after a least squares fit on the columns of `x`
+fn residuals(x: &[Vec<f64>], y: &[f64]) -> Vec<f64> {
+    let columns = x[0].len();
+    let mut normal = vec![vec![0.0; columns + 1]; columns];
+    for (row, target) in x.iter().zip(y.iter()) {
+        for i in 0..columns {
+            for j in 0..columns {
+                normal[i][j] += row[i] * row[j];
+            }
+            normal[i][columns] += row[i] * target;
+        }
+    }
+    let coefficients = solve(normal);
+    x.iter().zip(y.iter())
+        .map(|(row, target)| target - row.iter().zip(coefficients.iter()).map(|(a, b)| a * b).sum::<f64>())
+        .collect()
+}
+
+/// Solves a linear system given as an augmented matrix, by Gaussian elimination with partial pivoting. Columns that
+/// are linearly dependent on earlier ones get a coefficient of 0.
+fn solve(mut matrix: Vec<Vec<f64>>) -> Vec<f64> {
+    let n = matrix.len();
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|a, b| matrix[*a][column].abs().partial_cmp(&matrix[*b][column].abs()).unwrap())
+            .unwrap();
+        matrix.swap(column, pivot);
+        if matrix[column][column].abs() < 1e-12 {
+            continue;
+        }
+        for row in 0..n {
+            if row != column {
+                let factor = matrix[row][column] / matrix[column][column];
+                let pivot_row = matrix[column].clone();
+                for (value, pivot_value) in matrix[row].iter_mut().zip(pivot_row.iter()).skip(column) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+    (0..n).map(|i| if matrix[i][i].abs() < 1e-12 { 0.0 } else { matrix[i][n] / matrix[i][i] }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_place_one_sample_in_each_slice() {
+        let mut rng = RandomWrapper::with_seed(Some(3));
+        let points = latin_hypercube(10, 3, &mut rng);
+
+        for d in 0..3 {
+            let mut slices: Vec<usize> = points.iter().map(|p| (p[d] * 10.0) as usize).collect();
+            slices.sort();
+            assert_eq!(slices, (0..10).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn should_rank_ties_with_their_mean_rank() {
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0]), vec![3.5, 1.0, 3.5, 2.0]);
+    }
+
+    #[test]
+    fn should_find_the_parameter_that_matters_with_prcc() {
+        let mut rng = RandomWrapper::with_seed(Some(5));
+        let samples = latin_hypercube(200, 3, &mut rng);
+        let y: Vec<f64> = samples.iter().map(|s| 10.0 * s[0] - 2.0 * s[1] + 0.1 * rng.get().gen::<f64>()).collect();
+
+        assert!(prcc(&samples, &y, 0) > 0.95);
+        assert!(prcc(&samples, &y, 1) < -0.8);
+        assert!(prcc(&samples, &y, 2).abs() < 0.3);
+    }
+
+    #[test]
+    fn should_estimate_sobol_indices_of_additive_model() {
+        let mut rng = RandomWrapper::with_seed(Some(7));
+        let samples = 4000;
+        let points = saltelli_points(samples, 2, &mut rng);
+        assert_eq!(points.len(), samples * 4);
+        let y: Vec<f64> = points.iter().map(|p| 4.0 * p[0] + p[1]).collect();
+
+        let (first_order, total_order) = sobol_indices(&y, samples, 0);
+        assert!((first_order - 16.0 / 17.0).abs() < 0.05, "{}", first_order);
+        assert!((total_order - 16.0 / 17.0).abs() < 0.05, "{}", total_order);
+        let (first_order, _) = sobol_indices(&y, samples, 1);
+        assert!((first_order - 1.0 / 17.0).abs() < 0.05, "{}", first_order);
+    }
+
+    #[test]
+    fn should_run_latin_hypercube_sensitivity() {
+        let output_dir = std::env::temp_dir().join("epirust_sensitivity_test");
+        let _ = fs::remove_dir_all(&output_dir);
+        let mut parameters = BTreeMap::new();
+        parameters.insert("disease.regular_transmission_rate".to_string(), (0.01, 0.05));
+        parameters.insert("population.Auto.working_percentage".to_string(), (0.5, 0.9));
+        let sensitivity = SensitivityConfig {
+            config: "config/test/calibration_base.json".to_string(),
+            parameters,
+            method: Method::LatinHypercube { samples: 4 },
+            seed: 1,
+            output_dir: output_dir.to_string_lossy().to_string(),
+            parallelism: Some(2),
+        };
+
+        sensitivity.run().unwrap();
+
+        let samples = fs::read_to_string(output_dir.join("samples.csv")).unwrap();
+        assert_eq!(samples.lines().next().unwrap(), "sample,disease.regular_transmission_rate,\
+            population.Auto.working_percentage,peak_infected,peak_hour,total_deceased");
+        assert_eq!(samples.lines().count(), 5);
+        let indices = fs::read_to_string(output_dir.join("indices.csv")).unwrap();
+        assert_eq!(indices.lines().count(), 1 + 2 * 3);
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn should_read_sensitivity_config() {
+        let sensitivity = read("config/sensitivity.json").unwrap();
+
+        assert_eq!(sensitivity.method, Method::LatinHypercube { samples: 100 });
+        assert_eq!(sensitivity.parameters.len(), 4);
+    }
+
+    #[test]
+    fn should_drop_every_point_of_a_sobol_sample_with_an_invalid_config() {
+        let sensitivity: SensitivityConfig = serde_json::from_str(r#"{"config": "config/default.json",
+            "parameters": {}, "method": {"Sobol": {"samples": 3}}, "output_dir": "out"}"#).unwrap();
+        let config = config::read("config/test/calibration_base.json".to_string()).unwrap();
+        let mut configs = vec![Some(config); 3 * 4];
+        configs[2 * 3 + 1] = None;
+
+        let kept = sensitivity.kept_samples(&configs, 2);
+
+        assert_eq!(kept, [true, false, true].repeat(4));
+    }
+
+    #[test]
+    fn should_drop_samples_with_an_invalid_config() {
+        let output_dir = std::env::temp_dir().join("epirust_sensitivity_invalid_test");
+        let _ = fs::remove_dir_all(&output_dir);
+        let mut parameters = BTreeMap::new();
+        parameters.insert("disease.death_rate".to_string(), (0.5, 1.5));
+        let sensitivity = SensitivityConfig {
+            config: "config/test/calibration_base.json".to_string(),
+            parameters,
+            method: Method::LatinHypercube { samples: 8 },
+            seed: 1,
+            output_dir: output_dir.to_string_lossy().to_string(),
+            parallelism: Some(2),
+        };
+
+        sensitivity.run().unwrap();
+
+        let samples = fs::read_to_string(output_dir.join("samples.csv")).unwrap();
+        let death_rates: Vec<f64> = samples.lines().skip(1)
+            .map(|line| line.split(',').nth(1).unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(death_rates.len(), 4);
+        assert!(death_rates.iter().all(|rate| *rate <= 1.0), "{:?}", death_rates);
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
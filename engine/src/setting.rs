@@ -0,0 +1,161 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::validation::{field, ValidationErrors};
+
+/// Where a citizen is when they get infected. On the grid this is the kind of area they are in, and in a contact network
+/// the layer they meet their contacts in, with the community layer of the travel hours counting as transport.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Setting {
+    Home,
+    Work,
+    School,
+    Transport,
+    Hospital,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 5] = [Setting::Home, Setting::Work, Setting::School, Setting::Transport, Setting::Hospital];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::Home => "home",
+            Setting::Work => "work",
+            Setting::School => "school",
+            Setting::Transport => "transport",
+            Setting::Hospital => "hospital",
+        }
+    }
+}
+
+/// Scales the rate of transmission in each setting, e.g. `{"home": 1.5, "hospital": 0.2}`. A setting left out keeps
+/// the rate of the disease.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TransmissionMultipliers {
+    #[serde(default)]
+    pub home: Option<f64>,
+    #[serde(default)]
+    pub work: Option<f64>,
+    #[serde(default)]
+    pub school: Option<f64>,
+    #[serde(default)]
+    pub transport: Option<f64>,
+    #[serde(default)]
+    pub hospital: Option<f64>,
+}
+
+impl TransmissionMultipliers {
+    pub fn validate(&self, path: &str, errors: &mut ValidationErrors) {
+        for setting in Setting::ALL.iter() {
+            if let Some(multiplier) = self.configured(*setting) {
+                if !(multiplier.is_finite() && multiplier >= 0.0) {
+                    errors.add(field(path, setting.name()), format!("should not be negative, but is {}", multiplier));
+                }
+            }
+        }
+    }
+
+    pub fn multiplier(&self, setting: Setting) -> f64 {
+        self.configured(setting).unwrap_or(1.0)
+    }
+
+    fn configured(&self, setting: Setting) -> Option<f64> {
+        match setting {
+            Setting::Home => self.home,
+            Setting::Work => self.work,
+            Setting::School => self.school,
+            Setting::Transport => self.transport,
+            Setting::Hospital => self.hospital,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{AutoPopulation, Config, Population};
+    use crate::disease::Disease;
+    use crate::listeners::events::counts::Counts;
+    use crate::simulation::Simulation;
+
+    use super::*;
+
+    fn counts_at_end(multipliers: serde_json::Value) -> Counts {
+        let population = Population::Auto(AutoPopulation {
+            number_of_agents: 1000,
+            public_transport_percentage: 0.2,
+            working_percentage: 0.5,
+            age_distribution: None,
+        });
+        let mut config = Config::new(population, Disease::new(0, 50, 60, 0.99, 1.0, 0.0), vec![], 60, 120, vec![], None);
+        config.set_seed(1);
+        let config = config.with_parameters(&[("transmission_multipliers".to_string(), multipliers)]).unwrap();
+
+        let mut simulation = Simulation::builder(config).build().unwrap();
+        while !simulation.is_finished() {
+            simulation.step();
+        }
+        simulation.get_counts()
+    }
+
+    #[test]
+    fn should_count_infections_by_setting() {
+        let counts = counts_at_end(serde_json::json!({}));
+
+        let infections: i32 = Setting::ALL.iter().map(|setting| counts.get_infections_in(*setting)).sum();
+        assert_eq!(infections, 999 - counts.get_susceptible());
+        assert!(counts.get_infections_in(Setting::Work) > 0);
+    }
+
+    #[test]
+    fn should_not_spread_in_settings_without_transmission() {
+        let counts = counts_at_end(serde_json::json!({"work": 0.0, "transport": 0.0}));
+
+        assert_eq!(counts.get_infections_in(Setting::Work), 0);
+        assert_eq!(counts.get_infections_in(Setting::Transport), 0);
+        assert!(counts.get_infections_in(Setting::Home) > 0);
+    }
+
+    #[test]
+    fn should_keep_the_rate_of_settings_without_multiplier() {
+        let multipliers: TransmissionMultipliers = serde_json::from_str(r#"{"home": 1.5, "hospital": 0.2}"#).unwrap();
+
+        assert_eq!(multipliers.multiplier(Setting::Home), 1.5);
+        assert_eq!(multipliers.multiplier(Setting::Hospital), 0.2);
+        assert_eq!(multipliers.multiplier(Setting::Transport), 1.0);
+    }
+
+    #[test]
+    fn should_reject_unknown_settings() {
+        let error = serde_json::from_str::<TransmissionMultipliers>(r#"{"home": 1.5, "shcool": 1.2}"#).unwrap_err();
+
+        assert!(error.to_string().starts_with("unknown field `shcool`"), "{}", error);
+    }
+
+    #[test]
+    fn should_report_negative_multipliers() {
+        let multipliers = TransmissionMultipliers { work: Some(-0.5), ..Default::default() };
+        let mut errors = ValidationErrors::new();
+
+        multipliers.validate("transmission_multipliers", &mut errors);
+
+        let fields: Vec<&str> = errors.get_errors().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["transmission_multipliers.work"]);
+    }
+}
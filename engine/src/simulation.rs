@@ -0,0 +1,251 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::time::Instant;
+
+use kafka_config::KafkaConfig;
+
+use crate::config::Config;
+use crate::disease::Disease;
+use crate::epidemiology_simulation::{Epidemiology, SimulationRun};
+use crate::listeners::events::counts::Counts;
+use crate::listeners::events::summary::Summary;
+use crate::listeners::listener::{Listener, Listeners};
+use crate::run_control::{RunControl, Uncontrolled};
+use crate::validation::ValidationErrors;
+
+/// Puts together a simulation of a config. By default the output only goes to the listeners added here; use
+/// `config_outputs` to also write the `outputs` of the config.
+pub struct SimulationBuilder {
+    config: Config,
+    sim_id: String,
+    listeners: Vec<Box<dyn Listener>>,
+    kafka_config: Option<KafkaConfig>,
+}
+
+impl SimulationBuilder {
+    pub fn sim_id(mut self, sim_id: &str) -> SimulationBuilder {
+        self.sim_id = sim_id.to_string();
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> SimulationBuilder {
+        self.config.set_seed(seed);
+        self
+    }
+
+    pub fn disease(mut self, disease: Disease) -> SimulationBuilder {
+        self.config.set_disease(disease);
+        self
+    }
+
+    pub fn listener(mut self, listener: Box<dyn Listener>) -> SimulationBuilder {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// Also sends the output to the `outputs` of the config, such as CSV files or Kafka topics
+    pub fn config_outputs(mut self, kafka_config: &KafkaConfig) -> SimulationBuilder {
+        self.kafka_config = Some(kafka_config.clone());
+        self
+    }
+
+    /// Validates the config, and sets up the population for the first hour
+    pub fn build(self) -> Result<Simulation, ValidationErrors> {
+        self.config.validate()?;
+        let mut epidemiology = Epidemiology::new(&self.config, self.sim_id);
+        let mut listeners = self.listeners;
+        if let Some(kafka_config) = &self.kafka_config {
+            listeners.extend(epidemiology.create_listeners(&self.config, kafka_config));
+        }
+        let run = epidemiology.start_run(&self.config, Listeners::from(listeners));
+        Ok(Simulation { config: self.config, epidemiology, run, hour: 0 })
+    }
+}
+
+/// A simulation that can be advanced an hour at a time with `step`, or run to the end with `run`
+pub struct Simulation {
+    config: Config,
+    epidemiology: Epidemiology,
+    run: SimulationRun,
+    hour: i32,
+}
+
+impl Simulation {
+    pub fn builder(config: Config) -> SimulationBuilder {
+        SimulationBuilder { config, sim_id: crate::STANDALONE_SIM_ID.to_string(), listeners: Vec::new(), kafka_config: None }
+    }
+
+    /// Simulates the next hour and returns its counts. A finished simulation stays at its last hour.
+    pub fn step(&mut self) -> Counts {
+        if !self.is_finished() {
+            self.hour += 1;
+            self.epidemiology.run_hour(&self.config, &mut self.run, self.hour);
+        }
+        self.run.counts_at_hr
+    }
+
+    /// True once all the hours in the config are simulated, or nobody is infected or quarantined any more
+    pub fn is_finished(&self) -> bool {
+        self.hour >= self.config.get_hours() - 1 || Epidemiology::stop_simulation(self.run.counts_at_hr)
+    }
+
+    pub fn get_hour(&self) -> i32 {
+        self.hour
+    }
+
+    pub fn get_counts(&self) -> Counts {
+        self.run.counts_at_hr
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn get_epidemiology(&self) -> &Epidemiology {
+        &self.epidemiology
+    }
+
+    /// Ends the run, whether or not it is finished, and tells the listeners
+    pub fn finish(mut self) -> Summary {
+        Epidemiology::end_run(&mut self.run);
+        self.run.summary.clone()
+    }
+
+    pub fn run(self) -> Summary {
+        self.run_with_control(&mut Uncontrolled)
+    }
+
+    /// Steps until the simulation is finished or the control stops it
+    pub fn run_with_control(mut self, control: &mut dyn RunControl) -> Summary {
+        let start_time = Instant::now();
+        let total_hours = self.config.get_hours();
+        let mut cancelled = false;
+        control.run_started(total_hours);
+
+        while !self.is_finished() {
+            if !control.should_continue(self.hour + 1) {
+                cancelled = true;
+                break;
+            }
+            self.step();
+
+            if self.hour % 100 == 0 {
+                println!("Throughput: {} iterations/sec; simulation hour {} of {}",
+                         self.hour as f32 / start_time.elapsed().as_secs_f32(), self.hour, total_hours);
+            }
+        }
+        let elapsed_time = start_time.elapsed().as_secs_f32();
+        println!("Number of iterations: {}, Total Time taken {} seconds", self.hour, elapsed_time);
+        println!("Iterations/sec: {}", self.hour as f32 / elapsed_time);
+        let last_hour = self.hour;
+        let summary = self.finish();
+        control.run_ended(last_hour, cancelled);
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::sync::{Arc, Mutex};
+
+    use crate::agent::Citizen;
+    use crate::config::{AutoPopulation, Population};
+    use crate::geography::Point;
+
+    use super::*;
+
+    fn config(number_of_agents: i32, hours: i32) -> Config {
+        let population = Population::Auto(AutoPopulation {
+            number_of_agents,
+            public_transport_percentage: 1.0,
+            working_percentage: 1.0,
+            age_distribution: None,
+        });
+        Config::new(population, Disease::new(5, 20, 40, 0.025, 0.25, 0.035), vec![], 100, hours, vec![], None)
+    }
+
+    /// Records the number of citizen states sent between the start and end of each hour
+    struct HourRecorder {
+        hours: Arc<Mutex<Vec<(i32, usize)>>>,
+        citizens: usize,
+    }
+
+    impl Listener for HourRecorder {
+        fn hour_started(&mut self, _hr: i32) {
+            self.citizens = 0;
+        }
+
+        fn counts_updated(&mut self, _counts: Counts) {}
+
+        fn simulation_ended(&mut self, _summary: &Summary) {}
+
+        fn citizen_state_updated(&mut self, _hr: i32, _citizen: &Citizen, _location: &Point) {
+            self.citizens += 1;
+        }
+
+        fn hour_ended(&mut self, hr: i32) {
+            self.hours.lock().unwrap().push((hr, self.citizens));
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn should_send_every_citizen_between_the_start_and_end_of_each_hour() {
+        let hours = Arc::new(Mutex::new(Vec::new()));
+        let recorder = HourRecorder { hours: hours.clone(), citizens: 0 };
+        let simulation = Simulation::builder(config(10, 5)).listener(Box::new(recorder)).build().unwrap();
+
+        simulation.run();
+
+        assert_eq!(*hours.lock().unwrap(), vec![(1, 10), (2, 10), (3, 10), (4, 10)]);
+    }
+
+    #[test]
+    fn should_step_an_hour_at_a_time() {
+        let mut simulation = Simulation::builder(config(10, 3)).seed(3).build().unwrap();
+
+        assert_eq!(simulation.step().get_hour(), 1);
+        assert_eq!(simulation.step().get_hour(), 2);
+        assert!(simulation.is_finished());
+        assert_eq!(simulation.step().get_hour(), 2);
+        assert_eq!(simulation.finish().get_hours_simulated(), 2);
+    }
+
+    #[test]
+    fn should_repeat_a_run_with_the_same_seed() {
+        let run = |seed| {
+            let mut simulation = Simulation::builder(config(50, 200)).seed(seed).build().unwrap();
+            (0..199).map(|_| simulation.step().get_infected()).collect::<Vec<i32>>()
+        };
+
+        assert_eq!(run(11), run(11));
+    }
+
+    #[test]
+    fn should_not_build_an_invalid_config() {
+        let result = Simulation::builder(config(10, 0)).build();
+
+        assert_eq!(result.err().unwrap().to_string(), "Invalid config:\n  hours: should be positive, but is 0");
+    }
+}
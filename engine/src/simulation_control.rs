@@ -0,0 +1,200 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::time::Duration;
+
+use kafka_config::KafkaConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::message::Message;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+
+use crate::constants;
+use crate::run_control::{RunControl, Status};
+
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    Cancel,
+    Pause,
+    Resume,
+}
+
+#[derive(Debug, Deserialize)]
+struct ControlMessage {
+    sim_id: String,
+    command: Command,
+}
+
+#[derive(Serialize)]
+struct StatusMessage<'a> {
+    sim_id: &'a str,
+    #[serde(flatten)]
+    status: &'a Status,
+}
+
+/// Publishes the lifecycle of the simulations run by this engine on the status topic
+#[derive(Clone)]
+pub struct StatusPublisher {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl StatusPublisher {
+    pub fn new(kafka_config: &KafkaConfig) -> StatusPublisher {
+        StatusPublisher {
+            producer: kafka_config.client_config()
+                .create()
+                .expect("Could not create Kafka Producer"),
+            topic: kafka_config.topics.simulation_status.clone(),
+        }
+    }
+
+    pub fn publish(&self, sim_id: &str, status: Status) {
+        let message = status_message(sim_id, &status);
+        let record: FutureRecord<str, String> = FutureRecord::to(&self.topic)
+            .key(sim_id)
+            .payload(&message);
+        self.producer.send(record, 0);
+    }
+}
+
+/// Stops, pauses or resumes a simulation when the matching command arrives on the control topic, and reports its
+/// progress on the status topic
+pub struct KafkaRunControl<'a> {
+    sim_id: String,
+    consumer: BaseConsumer,
+    status: &'a StatusPublisher,
+    total_hours: i32,
+}
+
+impl KafkaRunControl<'_> {
+    pub fn new<'a>(engine_id: &str, sim_id: String, kafka_config: &KafkaConfig,
+                   status: &'a StatusPublisher) -> KafkaRunControl<'a> {
+        let consumer: BaseConsumer = kafka_config.client_config()
+            .set("group.id", &format!("{}_{}_control", engine_id, sim_id))
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .expect("Consumer creation failed");
+        consumer.subscribe(&[kafka_config.topics.simulation_control.as_str()])
+            .expect("Can't subscribe to the control topic");
+
+        KafkaRunControl { sim_id, consumer, status, total_hours: 0 }
+    }
+
+    fn next_command(&self, timeout: Duration) -> Option<Command> {
+        while let Some(message) = self.consumer.poll(timeout) {
+            match message {
+                Err(e) => println!("Failed to read the control topic: {}", e),
+                Ok(message) => {
+                    let command = message.payload_view::<str>()
+                        .and_then(|payload| payload.ok())
+                        .and_then(|payload| parse_command(&self.sim_id, payload));
+                    if command.is_some() {
+                        return command;
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl RunControl for KafkaRunControl<'_> {
+    fn run_started(&mut self, total_hours: i32) {
+        self.total_hours = total_hours;
+        self.status.publish(&self.sim_id, Status::Running { total_hours });
+    }
+
+    fn should_continue(&mut self, simulation_hour: i32) -> bool {
+        if simulation_hour % constants::NUMBER_OF_HOURS == 0 {
+            self.status.publish(&self.sim_id, Status::Progress { hour: simulation_hour, total_hours: self.total_hours });
+        }
+
+        let mut paused = false;
+        loop {
+            let timeout = if paused { PAUSED_POLL_INTERVAL } else { Duration::from_millis(0) };
+            match self.next_command(timeout) {
+                Some(Command::Cancel) => return false,
+                Some(Command::Pause) if !paused => {
+                    paused = true;
+                    self.status.publish(&self.sim_id, Status::Paused { hour: simulation_hour });
+                }
+                Some(Command::Resume) if paused => {
+                    self.status.publish(&self.sim_id, Status::Resumed { hour: simulation_hour });
+                    return true;
+                }
+                None if !paused => return true,
+                _ => {}
+            }
+        }
+    }
+
+    fn run_ended(&mut self, last_hour: i32, cancelled: bool) {
+        let status = if cancelled {
+            Status::Cancelled { hour: last_hour }
+        } else {
+            Status::Finished { hour: last_hour }
+        };
+        self.status.publish(&self.sim_id, status);
+    }
+}
+
+fn parse_command(sim_id: &str, payload: &str) -> Option<Command> {
+    match serde_json::from_str::<ControlMessage>(payload) {
+        Ok(message) if message.sim_id == sim_id => Some(message.command),
+        Ok(_) => None,
+        Err(e) => {
+            println!("Received a control message, but could not parse it.\nError Details: {}", e);
+            None
+        }
+    }
+}
+
+fn status_message(sim_id: &str, status: &Status) -> String {
+    serde_json::to_string(&StatusMessage { sim_id, status }).expect("Failed to serialize status")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validation::ValidationError;
+
+    use super::*;
+
+    #[test]
+    fn should_parse_commands_for_the_simulation() {
+        assert_eq!(parse_command("1", r#"{"sim_id": "1", "command": "pause"}"#), Some(Command::Pause));
+        assert_eq!(parse_command("1", r#"{"sim_id": "1", "command": "cancel"}"#), Some(Command::Cancel));
+        assert_eq!(parse_command("1", r#"{"sim_id": "2", "command": "resume"}"#), None);
+        assert_eq!(parse_command("1", r#"{"sim_id": "1", "command": "stop"}"#), None);
+    }
+
+    #[test]
+    fn should_serialize_status_with_sim_id() {
+        assert_eq!(status_message("1", &Status::Accepted), r#"{"sim_id":"1","status":"accepted"}"#);
+        assert_eq!(status_message("1", &Status::Progress { hour: 24, total_hours: 100 }),
+                   r#"{"sim_id":"1","status":"progress","hour":24,"total_hours":100}"#);
+        assert_eq!(status_message("1", &Status::Failed { error: "boom".to_string() }),
+                   r#"{"sim_id":"1","status":"failed","error":"boom"}"#);
+        let errors = vec![ValidationError { field: "hours".to_string(), message: "should be positive".to_string() }];
+        assert_eq!(status_message("1", &Status::Invalid { errors }),
+                   r#"{"sim_id":"1","status":"invalid","errors":[{"field":"hours","message":"should be positive"}]}"#);
+    }
+}
@@ -0,0 +1,96 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+/// Daily mobility between regions. `matrix[i][j]` is the fraction of the population of `regions[i]`
+/// that travels to `regions[j]` every day
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct TravelPlan {
+    regions: Vec<String>,
+    matrix: Vec<Vec<f64>>,
+}
+
+impl TravelPlan {
+    pub fn new(regions: Vec<String>, matrix: Vec<Vec<f64>>) -> TravelPlan {
+        TravelPlan { regions, matrix }
+    }
+
+    pub fn get_regions(&self) -> &Vec<String> {
+        &self.regions
+    }
+
+    pub fn get_travel_fraction(&self, from: &str, to: &str) -> f64 {
+        let from_index = self.regions.iter().position(|r| r == from);
+        let to_index = self.regions.iter().position(|r| r == to);
+        match (from_index, to_index) {
+            (Some(i), Some(j)) if i != j => self.matrix[i][j],
+            _ => 0.0
+        }
+    }
+
+    pub fn check(&self, region_names: &[String]) -> Result<(), String> {
+        if self.matrix.len() != self.regions.len() || self.matrix.iter().any(|row| row.len() != self.regions.len()) {
+            return Err(format!("Travel matrix should be {0}x{0}, one row and column per region", self.regions.len()));
+        }
+        if let Some(unknown) = self.regions.iter().find(|r| !region_names.contains(r)) {
+            return Err(format!("Travel plan refers to unknown region {}", unknown));
+        }
+        for (i, row) in self.matrix.iter().enumerate() {
+            let outgoing: f64 = row.iter().enumerate().filter(|(j, _)| *j != i).map(|(_, f)| *f).sum();
+            if row.iter().any(|f| *f < 0.0) || outgoing > 1.0 {
+                return Err(format!("Travel fractions from region {} should be non-negative and add up to at most 1",
+                                   self.regions[i]));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn travel_plan() -> TravelPlan {
+        TravelPlan::new(vec!["pune".to_string(), "mumbai".to_string()],
+                        vec![vec![0.0, 0.1], vec![0.2, 0.0]])
+    }
+
+    #[test]
+    fn should_get_travel_fraction() {
+        let plan = travel_plan();
+
+        assert_eq!(plan.get_travel_fraction("pune", "mumbai"), 0.1);
+        assert_eq!(plan.get_travel_fraction("mumbai", "pune"), 0.2);
+        assert_eq!(plan.get_travel_fraction("pune", "pune"), 0.0);
+        assert_eq!(plan.get_travel_fraction("pune", "nagpur"), 0.0);
+    }
+
+    #[test]
+    fn should_check_regions_and_matrix_shape() {
+        let plan = travel_plan();
+        let regions = vec!["pune".to_string(), "mumbai".to_string()];
+        assert!(plan.check(&regions).is_ok());
+        assert!(plan.check(&["pune".to_string()]).is_err());
+
+        let plan = TravelPlan::new(regions.clone(), vec![vec![0.0, 0.1]]);
+        assert!(plan.check(&regions).is_err());
+
+        let plan = TravelPlan::new(regions.clone(), vec![vec![0.0, 1.1], vec![0.0, 0.0]]);
+        assert!(plan.check(&regions).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::error::Error;
+use std::fmt;
+
+/// A problem with one field of a config, e.g. `disease.death_rate`
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Collects every problem found in a config, so that they can be reported together
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct ValidationErrors {
+    errors: Vec<ValidationError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> ValidationErrors {
+        ValidationErrors { errors: Vec::new() }
+    }
+
+    pub fn add(&mut self, field: String, message: String) {
+        self.errors.push(ValidationError { field, message });
+    }
+
+    pub fn check(&mut self, valid: bool, field: String, message: &str) {
+        if !valid {
+            self.add(field, message.to_string());
+        }
+    }
+
+    pub fn check_fraction(&mut self, value: f64, field: String) {
+        if !(0.0..=1.0).contains(&value) {
+            self.add(field, format!("should be between 0 and 1, but is {}", value));
+        }
+    }
+
+    pub fn check_non_negative(&mut self, value: i32, field: String) {
+        if value < 0 {
+            self.add(field, format!("should not be negative, but is {}", value));
+        }
+    }
+
+    pub fn get_errors(&self) -> &Vec<ValidationError> {
+        &self.errors
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid config:")?;
+        for error in &self.errors {
+            write!(f, "\n  {}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationErrors {}
+
+/// The path of a field nested under `path`, which is empty at the top level of the config
+pub fn field(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_collect_all_errors() {
+        let mut errors = ValidationErrors::new();
+        errors.check_fraction(0.5, field("", "percent"));
+        errors.check_fraction(1.5, field("disease", "death_rate"));
+        errors.check_non_negative(-1, field("interventions[0].Vaccinate", "at_hour"));
+        errors.check(false, "hours".to_string(), "should be positive");
+
+        let errors = errors.into_result().unwrap_err();
+        assert_eq!(errors.get_errors().len(), 3);
+        assert_eq!(errors.to_string(), "Invalid config:\n  \
+            disease.death_rate: should be between 0 and 1, but is 1.5\n  \
+            interventions[0].Vaccinate.at_hour: should not be negative, but is -1\n  \
+            hours: should be positive");
+    }
+
+    #[test]
+    fn should_be_ok_without_errors() {
+        let mut errors = ValidationErrors::new();
+        errors.check_fraction(0.0, "percent".to_string());
+        errors.check_non_negative(0, "hour".to_string());
+
+        assert_eq!(errors.into_result(), Ok(()));
+    }
+}
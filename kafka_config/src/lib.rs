@@ -0,0 +1,306 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+//! Kafka connection settings and topic names, shared by the engine and the orchestrator.
+//!
+//! Settings are read in order of increasing precedence from the defaults, a JSON config file,
+//! `EPIRUST_KAFKA_*` environment variables and command line flags.
+
+#[macro_use]
+extern crate serde_derive;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+
+use clap::{Arg, ArgMatches};
+#[cfg(feature = "rdkafka")]
+use rdkafka::ClientConfig;
+
+const ENV_PREFIX: &str = "EPIRUST_KAFKA_";
+const ENV_TOPIC_PREFIX: &str = "EPIRUST_KAFKA_TOPIC_";
+const ENV_PROPERTY_PREFIX: &str = "EPIRUST_KAFKA_PROPERTY_";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct KafkaConfig {
+    pub bootstrap_servers: String,
+    pub client_id: Option<String>,
+    pub topics: Topics,
+    /// Any other librdkafka properties, e.g. `security.protocol`, `sasl.mechanisms`, `compression.type` or `acks`
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Topics {
+    pub simulation_requests: String,
+    pub counts_updated: String,
+    pub citizen_states_updated: String,
+    pub ticks: String,
+    pub ticks_ack: String,
+    pub travellers: String,
+    pub simulation_control: String,
+    pub simulation_status: String,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        KafkaConfig {
+            bootstrap_servers: "localhost:9092".to_string(),
+            client_id: None,
+            topics: Topics::default(),
+            properties: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Topics {
+            simulation_requests: "simulation_requests".to_string(),
+            counts_updated: "counts_updated".to_string(),
+            citizen_states_updated: "citizen_states_updated".to_string(),
+            ticks: "ticks".to_string(),
+            ticks_ack: "ticks_ack".to_string(),
+            travellers: "travellers".to_string(),
+            simulation_control: "simulation_control".to_string(),
+            simulation_status: "simulation_status".to_string(),
+        }
+    }
+}
+
+impl Topics {
+    fn set(&mut self, name: &str, value: String) -> Result<(), Box<dyn Error>> {
+        match name {
+            "simulation_requests" => self.simulation_requests = value,
+            "counts_updated" => self.counts_updated = value,
+            "citizen_states_updated" => self.citizen_states_updated = value,
+            "ticks" => self.ticks = value,
+            "ticks_ack" => self.ticks_ack = value,
+            "travellers" => self.travellers = value,
+            "simulation_control" => self.simulation_control = value,
+            "simulation_status" => self.simulation_status = value,
+            _ => return Err(format!("Unknown Kafka topic {}", name).into()),
+        }
+        Ok(())
+    }
+}
+
+impl KafkaConfig {
+    pub fn read(filename: &str) -> Result<KafkaConfig, Box<dyn Error>> {
+        let reader = File::open(filename)?;
+        let config: KafkaConfig = serde_json::from_reader(reader)?;
+        Ok(config)
+    }
+
+    /// Reads the config file named by `--kafka-config` or `EPIRUST_KAFKA_CONFIG` (if any), and then applies the
+    /// environment variables and command line flags on top of it
+    pub fn load(matches: &ArgMatches) -> Result<KafkaConfig, Box<dyn Error>> {
+        let config_file = matches.value_of("kafka_config").map(|f| f.to_string())
+            .or_else(|| env::var(format!("{}CONFIG", ENV_PREFIX)).ok());
+        let mut config = match config_file {
+            Some(file) => KafkaConfig::read(&file)?,
+            None => KafkaConfig::default(),
+        };
+        config.apply_env(env::vars())?;
+        config.apply_args(matches)?;
+        Ok(config)
+    }
+
+    /// Applies `EPIRUST_KAFKA_BOOTSTRAP_SERVERS`, `EPIRUST_KAFKA_CLIENT_ID`, `EPIRUST_KAFKA_TOPIC_<NAME>` and
+    /// `EPIRUST_KAFKA_PROPERTY_<NAME>`, where property names are written in upper case with underscores
+    /// in place of dots, e.g. `EPIRUST_KAFKA_PROPERTY_SECURITY_PROTOCOL`
+    pub fn apply_env<I: Iterator<Item=(String, String)>>(&mut self, vars: I) -> Result<(), Box<dyn Error>> {
+        for (key, value) in vars {
+            if let Some(topic) = key.strip_prefix(ENV_TOPIC_PREFIX) {
+                self.topics.set(&topic.to_lowercase(), value)?;
+            } else if let Some(property) = key.strip_prefix(ENV_PROPERTY_PREFIX) {
+                self.properties.insert(property.to_lowercase().replace('_', "."), value);
+            } else if key == format!("{}BOOTSTRAP_SERVERS", ENV_PREFIX) {
+                self.bootstrap_servers = value;
+            } else if key == format!("{}CLIENT_ID", ENV_PREFIX) {
+                self.client_id = Some(value);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_args(&mut self, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+        if let Some(servers) = matches.value_of("kafka_bootstrap_servers") {
+            self.bootstrap_servers = servers.to_string();
+        }
+        if let Some(client_id) = matches.value_of("kafka_client_id") {
+            self.client_id = Some(client_id.to_string());
+        }
+        for topic in matches.values_of("kafka_topic").into_iter().flatten() {
+            let (name, value) = split_key_value(topic)?;
+            self.topics.set(&name, value)?;
+        }
+        for property in matches.values_of("kafka_property").into_iter().flatten() {
+            let (name, value) = split_key_value(property)?;
+            self.properties.insert(name, value);
+        }
+        Ok(())
+    }
+
+    /// A librdkafka client config with the connection settings and properties applied
+    #[cfg(feature = "rdkafka")]
+    pub fn client_config(&self) -> ClientConfig {
+        let mut client_config = ClientConfig::new();
+        client_config.set("bootstrap.servers", &self.bootstrap_servers);
+        if let Some(client_id) = &self.client_id {
+            client_config.set("client.id", client_id);
+        }
+        for (key, value) in self.properties.iter() {
+            client_config.set(key, value);
+        }
+        client_config
+    }
+}
+
+fn split_key_value(arg: &str) -> Result<(String, String), Box<dyn Error>> {
+    let mut parts = arg.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Expected KEY=VALUE, got {}", arg).into()),
+    }
+}
+
+/// Command line flags for the Kafka settings, to be added to the application's clap `App`
+pub fn args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("kafka_config")
+            .long("kafka-config")
+            .value_name("FILE")
+            .help("JSON file with the Kafka connection settings and topic names")
+            .takes_value(true),
+        Arg::with_name("kafka_bootstrap_servers")
+            .long("kafka-bootstrap-servers")
+            .value_name("HOST:PORT,...")
+            .help("Kafka brokers to connect to. Defaults to localhost:9092")
+            .takes_value(true),
+        Arg::with_name("kafka_client_id")
+            .long("kafka-client-id")
+            .value_name("ID")
+            .help("Client id to use when connecting to Kafka")
+            .takes_value(true),
+        Arg::with_name("kafka_topic")
+            .long("kafka-topic")
+            .value_name("NAME=TOPIC")
+            .help("Override a topic name, e.g. counts_updated=my_counts")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("kafka_property")
+            .long("kafka-property")
+            .value_name("KEY=VALUE")
+            .help("Set any librdkafka property, e.g. compression.type=lz4")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::App;
+
+    use super::*;
+
+    fn matches(command_line: Vec<&str>) -> ArgMatches<'static> {
+        App::new("test").args(&args()).get_matches_from(command_line)
+    }
+
+    #[test]
+    fn should_default_to_local_broker() {
+        let config = KafkaConfig::default();
+
+        assert_eq!(config.bootstrap_servers, "localhost:9092");
+        assert_eq!(config.topics.simulation_requests, "simulation_requests");
+        assert_eq!(config.topics.counts_updated, "counts_updated");
+        assert_eq!(config.topics.citizen_states_updated, "citizen_states_updated");
+        assert!(config.properties.is_empty());
+    }
+
+    #[test]
+    fn should_read_partial_config_file() {
+        let config: KafkaConfig = serde_json::from_str(r#"{
+            "bootstrap_servers": "kafka:9093",
+            "topics": {"counts_updated": "counts"},
+            "properties": {"compression.type": "lz4"}
+        }"#).unwrap();
+
+        assert_eq!(config.bootstrap_servers, "kafka:9093");
+        assert_eq!(config.topics.counts_updated, "counts");
+        assert_eq!(config.topics.simulation_requests, "simulation_requests");
+        assert_eq!(config.properties.get("compression.type").unwrap(), "lz4");
+    }
+
+    #[test]
+    fn should_apply_env_vars() {
+        let mut config = KafkaConfig::default();
+        let vars = vec![
+            ("EPIRUST_KAFKA_BOOTSTRAP_SERVERS".to_string(), "a:1,b:2".to_string()),
+            ("EPIRUST_KAFKA_TOPIC_TICKS".to_string(), "my_ticks".to_string()),
+            ("EPIRUST_KAFKA_PROPERTY_SECURITY_PROTOCOL".to_string(), "SASL_SSL".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ];
+
+        config.apply_env(vars.into_iter()).unwrap();
+
+        assert_eq!(config.bootstrap_servers, "a:1,b:2");
+        assert_eq!(config.topics.ticks, "my_ticks");
+        assert_eq!(config.properties.get("security.protocol").unwrap(), "SASL_SSL");
+        assert_eq!(config.properties.len(), 1);
+    }
+
+    #[test]
+    fn should_reject_unknown_topic() {
+        let mut config = KafkaConfig::default();
+        let vars = vec![("EPIRUST_KAFKA_TOPIC_UNKNOWN".to_string(), "x".to_string())];
+
+        assert!(config.apply_env(vars.into_iter()).is_err());
+    }
+
+    #[test]
+    fn should_apply_command_line_flags() {
+        let mut config = KafkaConfig::default();
+        let matches = matches(vec!["test", "--kafka-bootstrap-servers", "kafka:9092", "--kafka-client-id", "engine",
+                                   "--kafka-topic", "counts_updated=counts", "--kafka-property", "acks=all",
+                                   "--kafka-property", "sasl.password=a=b"]);
+
+        config.apply_args(&matches).unwrap();
+
+        assert_eq!(config.bootstrap_servers, "kafka:9092");
+        assert_eq!(config.client_id, Some("engine".to_string()));
+        assert_eq!(config.topics.counts_updated, "counts");
+        assert_eq!(config.properties.get("acks").unwrap(), "all");
+        assert_eq!(config.properties.get("sasl.password").unwrap(), "a=b");
+    }
+
+    #[test]
+    fn should_reject_malformed_property() {
+        let mut config = KafkaConfig::default();
+        let matches = matches(vec!["test", "--kafka-property", "acks"]);
+
+        assert!(config.apply_args(&matches).is_err());
+    }
+}
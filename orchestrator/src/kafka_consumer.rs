@@ -0,0 +1,15 @@
+use kafka_config::KafkaConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+
+/// Consumes tick acknowledgements sent by the engines
+pub fn ticks_ack_consumer(sim_id: &str, kafka_config: &KafkaConfig) -> StreamConsumer {
+    let consumer: StreamConsumer = kafka_config.client_config()
+        .set("group.id", &format!("orchestrator_{}", sim_id))
+        .set("auto.offset.reset", "earliest")
+        .create()
+        .expect("Consumer creation failed");
+
+    consumer.subscribe(&[kafka_config.topics.ticks_ack.as_str()])
+        .expect("Can't subscribe to specified topics");
+    consumer
+}
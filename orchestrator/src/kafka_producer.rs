@@ -1,23 +1,34 @@
+use kafka_config::KafkaConfig;
 use rdkafka::producer::{FutureProducer, FutureRecord, DeliveryFuture};
-use rdkafka::ClientConfig;
+
+use crate::ticks::Tick;
 
 pub struct KafkaProducer {
     producer: FutureProducer,
+    kafka_config: KafkaConfig,
 }
 
 impl KafkaProducer {
-    pub fn new() -> KafkaProducer {
+    pub fn new(kafka_config: &KafkaConfig) -> KafkaProducer {
         KafkaProducer {
-            producer: ClientConfig::new()
-                .set("bootstrap.servers", "localhost:9092")
+            producer: kafka_config.client_config()
                 .create()
-                .expect("Could not create Kafka Producer")
+                .expect("Could not create Kafka Producer"),
+            kafka_config: kafka_config.clone(),
         }
     }
 
     pub fn start_request(&mut self, request: String) -> DeliveryFuture {
-        let record: FutureRecord<String, String> = FutureRecord::to("simulation_requests")
+        let record: FutureRecord<String, String> = FutureRecord::to(&self.kafka_config.topics.simulation_requests)
             .payload(&request);
         self.producer.send(record, 0)
     }
+
+    pub fn send_tick(&mut self, tick: &Tick) -> DeliveryFuture {
+        let payload = serde_json::to_string(tick).expect("Failed to serialize tick");
+        let record: FutureRecord<String, String> = FutureRecord::to(&self.kafka_config.topics.ticks)
+            .key(&tick.sim_id)
+            .payload(&payload);
+        self.producer.send(record, 0)
+    }
 }
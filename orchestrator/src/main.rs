@@ -1,25 +1,78 @@
+extern crate clap;
+extern crate kafka_config;
+#[macro_use]
+extern crate serde_derive;
+
 use std::fs::File;
 use std::io::Read;
+use std::time::{Duration, SystemTime};
+
+use clap::{App, Arg, value_t};
+use kafka_config::KafkaConfig;
+use futures::StreamExt;
+use serde_json::Value;
 
 use crate::kafka_producer::KafkaProducer;
-use std::error::Error;
+use crate::ticks::Tick;
 
+mod kafka_consumer;
 mod kafka_producer;
+mod ticks;
 
 #[tokio::main]
 async fn main() {
-    let n_engines = 2;
-    let hours = 10000;
+    let matches = App::new("EpiRust Orchestrator")
+        .version("0.1")
+        .about("Runs a simulation distributed across EpiRust engines")
+        .arg(Arg::with_name("config")
+            .long("config")
+            .short("c")
+            .value_name("FILE")
+            .help("The simulation request to send to the engines"))
+        .arg(Arg::with_name("ack_timeout")
+            .long("ack-timeout")
+            .value_name("SECONDS")
+            .default_value("300")
+            .help("How long to wait for every engine to acknowledge a tick before failing the simulation"))
+        .args(&kafka_config::args())
+        .get_matches();
+    let kafka_config = KafkaConfig::load(&matches).expect("Failed to read Kafka settings");
+    let config_file = matches.value_of("config").unwrap_or("config/simulation.json");
+    let ack_timeout = Duration::from_secs(value_t!(matches, "ack_timeout", u64).unwrap_or_else(|e| e.exit()));
+
+    let mut request: Value = serde_json::from_str(&read(config_file))
+        .expect("Failed to parse simulation request");
+    let sim_id = format!("sim-{}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis());
+    let engine_configs = request["engine_configs"].as_array_mut().expect("engine_configs should be a list");
+    engine_configs.iter_mut().for_each(|engine_config| {
+        engine_config["config"]["sim_id"] = Value::String(sim_id.clone());
+    });
+    let engines: Vec<String> = engine_configs.iter()
+        .map(|c| c["engine_id"].as_str().expect("engine_id should be a string").to_string())
+        .collect();
+    let hours = engine_configs[0]["config"]["hours"].as_i64().expect("hours should be a number") as i32;
 
-    let mut kafka_producer = KafkaProducer::new();
+    let consumer = kafka_consumer::ticks_ack_consumer(&sim_id, &kafka_config);
+    let mut acks = consumer.start().map(ticks::payload);
+    let mut kafka_producer = KafkaProducer::new(&kafka_config);
 
-    let sim_conf = read("config/simulation.json");
-    kafka_producer.start_request(sim_conf).await.expect("Failed to send simulation request");
+    kafka_producer.start_request(request.to_string()).await
+        .expect("Failed to send simulation request")
+        .expect("Failed to send simulation request");
+    println!("Started simulation {} on engines {:?}", sim_id, engines);
 
-    // for h in hours {
-    //     //send tick to engines
-    //     //wait for acknowledgement
-    // }
+    for hour in 1..hours {
+        let tick = Tick { sim_id: sim_id.clone(), hour };
+        kafka_producer.send_tick(&tick).await
+            .expect("Failed to send tick")
+            .expect("Failed to send tick");
+        ticks::wait_for_acks(&mut acks, &sim_id, hour, &engines, ack_timeout).await
+            .expect("Failed to receive tick acknowledgements");
+        if hour % 100 == 0 {
+            println!("Simulation hour {} of {}", hour, hours);
+        }
+    }
+    println!("Simulation {} ended", sim_id);
 }
 
 fn read(filename: &str) -> String {
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use rdkafka::error::KafkaResult;
+use rdkafka::message::BorrowedMessage;
+use rdkafka::Message;
+use tokio::time;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tick {
+    pub sim_id: String,
+    pub hour: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TickAck {
+    pub sim_id: String,
+    pub engine_id: String,
+    pub hour: i32,
+}
+
+/// The payload of a message on the ack topic, copied so that it can outlive the message
+pub fn payload(message: KafkaResult<BorrowedMessage>) -> Result<String, Box<dyn Error>> {
+    Ok(message?.payload_view::<str>().unwrap_or(Ok(""))?.to_string())
+}
+
+/// Waits until every engine has acknowledged the tick for the given hour. An engine that hasn't acknowledged it within
+/// the timeout has most likely stopped, so the wait fails rather than hanging the run.
+pub async fn wait_for_acks<S>(acks: &mut S, sim_id: &str, hour: i32, engines: &[String], timeout: Duration)
+                              -> Result<(), Box<dyn Error>>
+    where S: Stream<Item=Result<String, Box<dyn Error>>> + Unpin {
+    let mut acknowledged: HashSet<String> = HashSet::new();
+    let wait = async {
+        while acknowledged.len() < engines.len() {
+            let payload = match acks.next().await {
+                Some(payload) => payload?,
+                None => return Err("Tick acknowledgement stream ended".into()),
+            };
+            let ack: TickAck = match serde_json::from_str(&payload) {
+                Ok(ack) => ack,
+                Err(e) => {
+                    println!("Received a message, but could not parse it.\nError Details: {}", e);
+                    continue;
+                }
+            };
+            if ack.sim_id == sim_id && ack.hour == hour && engines.contains(&ack.engine_id) {
+                acknowledged.insert(ack.engine_id);
+            }
+        }
+        Ok(())
+    };
+    match time::timeout(timeout, wait).await {
+        Ok(result) => result,
+        Err(_) => {
+            let missing: Vec<&String> = engines.iter().filter(|engine| !acknowledged.contains(*engine)).collect();
+            Err(format!("No acknowledgement of hour {} from engines {:?} within {:?}", hour, missing, timeout).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    fn ack(engine_id: &str, hour: i32) -> Result<String, Box<dyn Error>> {
+        let ack = TickAck { sim_id: "sim".to_string(), engine_id: engine_id.to_string(), hour };
+        Ok(serde_json::to_string(&ack).unwrap())
+    }
+
+    #[tokio::test]
+    async fn should_wait_for_every_engine() {
+        let engines = vec!["engine1".to_string(), "engine2".to_string()];
+        let mut acks = stream::iter(vec![ack("engine1", 1), ack("engine3", 1), ack("engine2", 1)]);
+
+        let result = wait_for_acks(&mut acks, "sim", 1, &engines, Duration::from_secs(5)).await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_fail_when_an_engine_never_acknowledges() {
+        let engines = vec!["engine1".to_string(), "engine2".to_string()];
+        let mut acks = stream::iter(vec![ack("engine1", 1), ack("engine2", 0)]).chain(stream::pending());
+
+        let result = wait_for_acks(&mut acks, "sim", 1, &engines, Duration::from_millis(50)).await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("engine2") && !error.contains("engine1"), "{}", error);
+    }
+}
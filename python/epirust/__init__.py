@@ -0,0 +1,81 @@
+#  EpiRust
+#  Copyright (c) 2020  ThoughtWorks, Inc.
+#
+#  This program is free software: you can redistribute it and/or modify
+#  it under the terms of the GNU Affero General Public License as published by
+#  the Free Software Foundation, either version 3 of the License, or
+#  (at your option) any later version.
+#
+#  This program is distributed in the hope that it will be useful,
+#  but WITHOUT ANY WARRANTY; without even the implied warranty of
+#  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#  GNU Affero General Public License for more details.
+#
+#  You should have received a copy of the GNU Affero General Public License
+#  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+#
+
+"""Runs EpiRust simulations from Python, and returns their results as pandas data frames."""
+
+import json
+import os
+
+import pandas as pd
+
+from . import _epirust
+
+STATES = _epirust.STATES
+
+
+class Listener:
+    """Receives the events of a run. Subclasses override the events they are interested in; an error raised by any of
+    them stops the run, and is raised by `run`."""
+
+    def simulation_started(self, description):
+        """The engine, sim_id, start time, population, hours, seed and config of the run, as a dict of strings"""
+
+    def hour_started(self, hour):
+        pass
+
+    def counts_updated(self, counts):
+        """The counts of the hour, as a dict"""
+
+    def hour_ended(self, hour):
+        pass
+
+    def simulation_ended(self, summary):
+        """The summary of the run, as a dict"""
+
+
+class Result:
+    """The outcome of a run: the `counts` of every hour, the `citizen_states` of the sampled hours (None unless they
+    were asked for), and the `summary` of the run"""
+
+    def __init__(self, counts, citizen_states, summary):
+        self.counts = counts
+        self.citizen_states = citizen_states
+        self.summary = summary
+
+
+def _config_json(config):
+    if isinstance(config, (str, os.PathLike)):
+        with open(config) as file:
+            return file.read()
+    return json.dumps(config)
+
+
+def run(config, seed=None, citizen_states=False, sample_interval=1, listener=None):
+    """Runs a simulation of `config`, a dict in the format of the config files or the path of one. The state and
+    location of every citizen is recorded every `sample_interval` hours when `citizen_states` is true."""
+    result = _epirust.run(_config_json(config), seed, sample_interval if citizen_states else None, listener)
+    states = result["citizen_states"]
+    if states is not None:
+        states = pd.DataFrame(states)
+        states["state"] = pd.Categorical.from_codes(states["state"], categories=STATES)
+    return Result(pd.DataFrame(result["counts"]), states, result["summary"])
+
+
+def run_ensemble(config, replicates, seed=0, parallelism=None):
+    """Runs `config` `replicates` times with the seeds `seed`, `seed + 1` and so on, on a thread per core unless
+    `parallelism` says otherwise, and returns the counts of every run, with the replicate in the `run` column"""
+    return pd.DataFrame(_epirust.run_ensemble(_config_json(config), replicates, seed, parallelism))
@@ -0,0 +1,67 @@
+#  EpiRust
+#  Copyright (c) 2020  ThoughtWorks, Inc.
+#
+#  This program is free software: you can redistribute it and/or modify
+#  it under the terms of the GNU Affero General Public License as published by
+#  the Free Software Foundation, either version 3 of the License, or
+#  (at your option) any later version.
+#
+#  This program is distributed in the hope that it will be useful,
+#  but WITHOUT ANY WARRANTY; without even the implied warranty of
+#  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#  GNU Affero General Public License for more details.
+#
+#  You should have received a copy of the GNU Affero General Public License
+#  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+#
+
+"""Decodes the citizen states that the engine publishes on Kafka with the `Binary` encoding. The layout of a frame is
+described in `engine/src/listeners/events/citizen_state_encoding.rs`."""
+
+import struct
+
+MAGIC = b"EPCS"
+VERSION = 1
+KEYFRAME = 0
+DELTA = 1
+LEFT_REGION = 255
+
+_HEADER = struct.Struct("<4sBBII")
+_RECORD = struct.Struct("<IBHH")
+
+
+class Decoder:
+    """Rebuilds the state of every citizen from the frames of a run, applied in the order they were published.
+    `citizens` maps the id of each citizen in the region to its `(state, x, y)`."""
+
+    def __init__(self):
+        self.hour = None
+        self.citizens = {}
+        self._has_keyframe = False
+
+    def apply(self, frame):
+        """Applies a frame, returning its hour"""
+        if len(frame) < _HEADER.size:
+            raise ValueError("Not a citizen states frame")
+        magic, version, kind, hour, records = _HEADER.unpack_from(frame)
+        if magic != MAGIC:
+            raise ValueError("Not a citizen states frame")
+        if version != VERSION:
+            raise ValueError("Unsupported citizen states version {}".format(version))
+        if kind not in (KEYFRAME, DELTA):
+            raise ValueError("Unknown kind of frame {}".format(kind))
+        if kind == DELTA and not self._has_keyframe:
+            raise ValueError("A delta frame needs a keyframe before it")
+        if len(frame) != _HEADER.size + _RECORD.size * records:
+            raise ValueError("Expected {} records in the frame for hour {}".format(records, hour))
+
+        if kind == KEYFRAME:
+            self.citizens = {}
+            self._has_keyframe = True
+        for citizen_id, state, x, y in _RECORD.iter_unpack(frame[_HEADER.size:]):
+            if state == LEFT_REGION:
+                self.citizens.pop(citizen_id, None)
+            else:
+                self.citizens[citizen_id] = (state, x, y)
+        self.hour = hour
+        return hour
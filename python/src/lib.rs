@@ -0,0 +1,185 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+//! Python bindings of the engine, for running simulations from notebooks. This is the `epirust._epirust` extension
+//! module, which returns its results as columns of NumPy arrays; the `epirust` package around it turns them into
+//! pandas data frames.
+
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use epirust::ensemble::{self, Run};
+use epirust::{Config, Counts, Simulation, Summary, ValidationErrors};
+use numpy::IntoPyArray;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::python_listener::{to_python, PythonControl, PythonError, PythonListener};
+use crate::recorder::{CitizenStates, Recorder, Recording};
+
+mod python_listener;
+mod recorder;
+
+fn parse_config(config: &str) -> PyResult<Config> {
+    serde_json::from_str(config).map_err(|e| PyValueError::new_err(format!("Invalid config: {}", e)))
+}
+
+fn value_error(errors: ValidationErrors) -> PyErr {
+    PyValueError::new_err(errors.to_string())
+}
+
+/// Runs a simulation of a config, given as JSON, and returns a dict with its `counts` by hour, the `citizen_states`
+/// of every `sample_interval` hours when an interval is given, and the `summary` of the run. The events of the run
+/// are sent to `listener`, if any.
+#[pyfunction]
+#[pyo3(signature = (config, seed=None, sample_interval=None, listener=None))]
+fn run<'py>(py: Python<'py>, config: &str, seed: Option<u64>, sample_interval: Option<i32>,
+            listener: Option<Py<PyAny>>) -> PyResult<Bound<'py, PyDict>> {
+    let config = parse_config(config)?;
+    if sample_interval.is_some_and(|interval| interval <= 0) {
+        return Err(PyValueError::new_err("sample_interval should be positive"));
+    }
+    let recording = Arc::new(Mutex::new(Recording::default()));
+    let error = PythonError::default();
+
+    let summary = py.detach(|| -> Result<Summary, ValidationErrors> {
+        let mut builder = Simulation::builder(config)
+            .listener(Box::new(Recorder::new(recording.clone(), sample_interval)));
+        if let Some(seed) = seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(listener) = listener {
+            builder = builder.listener(Box::new(PythonListener::new(listener, error.clone())));
+        }
+        Ok(builder.build()?.run_with_control(&mut PythonControl::new(error.clone())))
+    }).map_err(value_error)?;
+    if let Some(e) = error.lock().unwrap().take() {
+        return Err(e);
+    }
+
+    let recording = mem::take(&mut *recording.lock().unwrap());
+    let result = PyDict::new(py);
+    let counts = PyDict::new(py);
+    add_counts_columns(&counts, &recording.counts)?;
+    result.set_item("counts", counts)?;
+    result.set_item("citizen_states", recording.citizen_states.map(|states| citizen_states_columns(py, states)).transpose()?)?;
+    result.set_item("summary", to_python(py, &summary)?)?;
+    Ok(result)
+}
+
+/// Runs `replicates` simulations of a config, given as JSON, with the seeds `seed`, `seed + 1` and so on, on a pool
+/// of `parallelism` threads. Returns the counts by hour of every run, with the replicate in the `run` column.
+#[pyfunction]
+#[pyo3(signature = (config, replicates, seed=0, parallelism=None))]
+fn run_ensemble<'py>(py: Python<'py>, config: &str, replicates: usize, seed: u64,
+                     parallelism: Option<usize>) -> PyResult<Bound<'py, PyDict>> {
+    let config = parse_config(config)?;
+    config.validate().map_err(value_error)?;
+    if replicates == 0 || parallelism == Some(0) {
+        return Err(PyValueError::new_err("replicates and parallelism should be positive"));
+    }
+    let runs: Vec<Run> = (0..replicates).map(|i| {
+        let mut config = config.clone();
+        config.set_seed(seed + i as u64);
+        Run { config, output_file: None }
+    }).collect();
+
+    let runs_counts = py.detach(|| ensemble::run_all(&runs, parallelism));
+
+    let run_column: Vec<u32> = runs_counts.iter().enumerate()
+        .flat_map(|(i, counts)| std::iter::repeat_n(i as u32, counts.len()))
+        .collect();
+    let counts = PyDict::new(py);
+    counts.set_item("run", run_column.into_pyarray(py))?;
+    add_counts_columns(&counts, &runs_counts.concat())?;
+    Ok(counts)
+}
+
+type Column = (&'static str, fn(&Counts) -> i32);
+
+const COUNTS_COLUMNS: [Column; 6] = [
+    ("hour", Counts::get_hour),
+    ("susceptible", Counts::get_susceptible),
+    ("infected", Counts::get_infected),
+    ("quarantined", Counts::get_quarantined),
+    ("recovered", Counts::get_recovered),
+    ("deceased", Counts::get_deceased),
+];
+
+fn add_counts_columns(columns: &Bound<PyDict>, counts: &[Counts]) -> PyResult<()> {
+    for (name, metric) in COUNTS_COLUMNS.iter() {
+        let values: Vec<i32> = counts.iter().map(metric).collect();
+        columns.set_item(name, values.into_pyarray(columns.py()))?;
+    }
+    Ok(())
+}
+
+fn citizen_states_columns(py: Python, states: CitizenStates) -> PyResult<Bound<PyDict>> {
+    let columns = PyDict::new(py);
+    columns.set_item("hour", states.hour.into_pyarray(py))?;
+    columns.set_item("citizen_id", states.citizen_id.into_pyarray(py))?;
+    columns.set_item("state", states.state.into_pyarray(py))?;
+    columns.set_item("x", states.x.into_pyarray(py))?;
+    columns.set_item("y", states.y.into_pyarray(py))?;
+    Ok(columns)
+}
+
+#[pymodule]
+fn _epirust(module: &Bound<PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(run, module)?)?;
+    module.add_function(wrap_pyfunction!(run_ensemble, module)?)?;
+    module.add("STATES", recorder::state_names())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    #[test]
+    fn should_decode_the_citizen_states_checked_in_with_the_engine() {
+        let code = "import json, os\n\
+                    decoder = citizen_states.Decoder()\n\
+                    decoded = []\n\
+                    for name in ['keyframe.bin', 'delta.bin']:\n\
+                    \x20   with open(os.path.join(fixtures, name), 'rb') as file:\n\
+                    \x20       hour = decoder.apply(file.read())\n\
+                    \x20   citizens = [{'citizen_id': i, 'state': s, 'x': x, 'y': y}\n\
+                    \x20               for i, (s, x, y) in sorted(decoder.citizens.items())]\n\
+                    \x20   decoded.append({'hour': hour, 'citizens': citizens})\n\
+                    with open(os.path.join(fixtures, 'decoded.json')) as file:\n\
+                    \x20   matches = decoded == json.load(file)\n";
+        Python::attach(|py| {
+            let module = PyModule::from_code(py, &CString::new(include_str!("../epirust/citizen_states.py")).unwrap(),
+                                             &CString::new("citizen_states.py").unwrap(),
+                                             &CString::new("citizen_states").unwrap()).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("citizen_states", module).unwrap();
+            globals.set_item("fixtures", concat!(env!("CARGO_MANIFEST_DIR"), "/../engine/config/test/citizen_states"))
+                .unwrap();
+            py.run(&CString::new(code).unwrap(), Some(&globals), None).unwrap();
+
+            let matches: bool = globals.get_item("matches").unwrap().unwrap().extract().unwrap();
+            assert!(matches);
+        });
+    }
+}
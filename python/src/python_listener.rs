@@ -0,0 +1,188 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use epirust::listeners::events::simulation_started::SimulationStarted;
+use epirust::{Counts, Listener, RunControl, Summary};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::Serialize;
+
+/// The first error raised by a Python callback, or by a signal such as a keyboard interrupt. It stops the run, and is
+/// raised once the run is over.
+pub type PythonError = Arc<Mutex<Option<PyErr>>>;
+
+/// Converts a value to Python, through its JSON representation
+pub fn to_python<'py>(py: Python<'py>, value: &impl Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let json = serde_json::to_string(value).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+/// Sends the events of a run to a Python object. Events without a method of the same name on the object are skipped,
+/// and so are all events after a method raised an error.
+pub struct PythonListener {
+    listener: Py<PyAny>,
+    error: PythonError,
+}
+
+impl PythonListener {
+    pub fn new(listener: Py<PyAny>, error: PythonError) -> PythonListener {
+        PythonListener { listener, error }
+    }
+
+    fn notify<F>(&self, event: &str, argument: F)
+        where F: for<'py> FnOnce(Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        if self.error.lock().unwrap().is_some() {
+            return;
+        }
+        let result = Python::attach(|py| {
+            let listener = self.listener.bind(py);
+            if listener.hasattr(event)? {
+                listener.call_method1(event, (argument(py)?,))?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            *self.error.lock().unwrap() = Some(e);
+        }
+    }
+}
+
+impl Listener for PythonListener {
+    fn simulation_started(&mut self, started: &SimulationStarted) {
+        self.notify("simulation_started", |py| {
+            let description = PyDict::new(py);
+            for (key, value) in started.describe() {
+                description.set_item(key, value)?;
+            }
+            Ok(description.into_any())
+        });
+    }
+
+    fn hour_started(&mut self, hr: i32) {
+        self.notify("hour_started", |py| Ok(hr.into_pyobject(py)?.into_any()));
+    }
+
+    fn counts_updated(&mut self, counts: Counts) {
+        self.notify("counts_updated", |py| to_python(py, &counts));
+    }
+
+    fn simulation_ended(&mut self, summary: &Summary) {
+        self.notify("simulation_ended", |py| to_python(py, summary));
+    }
+
+    fn hour_ended(&mut self, hr: i32) {
+        self.notify("hour_ended", |py| Ok(hr.into_pyobject(py)?.into_any()));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Stops a run once a Python callback raised an error, or when Python received a signal, so that a run can be
+/// interrupted from a notebook
+pub struct PythonControl {
+    error: PythonError,
+}
+
+impl PythonControl {
+    pub fn new(error: PythonError) -> PythonControl {
+        PythonControl { error }
+    }
+}
+
+impl RunControl for PythonControl {
+    fn should_continue(&mut self, _simulation_hour: i32) -> bool {
+        if self.error.lock().unwrap().is_some() {
+            return false;
+        }
+        if let Err(e) = Python::attach(|py| py.check_signals()) {
+            *self.error.lock().unwrap() = Some(e);
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    fn python_object(py: Python, code: &str) -> Py<PyAny> {
+        let globals = PyDict::new(py);
+        py.run(&CString::new(code).unwrap(), Some(&globals), None).unwrap();
+        globals.get_item("listener").unwrap().unwrap().unbind()
+    }
+
+    #[test]
+    fn should_call_the_methods_of_the_python_listener() {
+        let code = "class Listener:\n\
+                    \x20   def __init__(self):\n\
+                    \x20       self.events = []\n\
+                    \x20   def hour_started(self, hour):\n\
+                    \x20       self.events.append(('hour_started', hour))\n\
+                    \x20   def counts_updated(self, counts):\n\
+                    \x20       self.events.append(('counts_updated', counts['infected']))\n\
+                    listener = Listener()\n";
+        let object = Python::attach(|py| python_object(py, code));
+        let error = PythonError::default();
+        let mut listener = PythonListener::new(Python::attach(|py| object.clone_ref(py)), error.clone());
+
+        listener.hour_started(1);
+        listener.counts_updated(Counts::new(10, 3));
+        listener.hour_ended(1);
+
+        assert!(error.lock().unwrap().is_none());
+        Python::attach(|py| {
+            let events: Vec<(String, i32)> = object.bind(py).getattr("events").unwrap().extract().unwrap();
+            assert_eq!(events, vec![("hour_started".to_string(), 1), ("counts_updated".to_string(), 3)]);
+        });
+    }
+
+    #[test]
+    fn should_stop_the_run_once_a_callback_raises() {
+        let code = "class Listener:\n\
+                    \x20   def __init__(self):\n\
+                    \x20       self.calls = 0\n\
+                    \x20   def hour_started(self, hour):\n\
+                    \x20       self.calls += 1\n\
+                    \x20       raise ValueError('bad hour')\n\
+                    listener = Listener()\n";
+        let object = Python::attach(|py| python_object(py, code));
+        let error = PythonError::default();
+        let mut listener = PythonListener::new(Python::attach(|py| object.clone_ref(py)), error.clone());
+        let mut control = PythonControl::new(error.clone());
+
+        assert!(control.should_continue(1));
+        listener.hour_started(1);
+        listener.hour_started(2);
+
+        assert!(!control.should_continue(2));
+        Python::attach(|py| {
+            assert_eq!(error.lock().unwrap().as_ref().unwrap().to_string(), "ValueError: bad hour");
+            let calls: i32 = object.bind(py).getattr("calls").unwrap().extract().unwrap();
+            assert_eq!(calls, 1);
+        });
+    }
+}
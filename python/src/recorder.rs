@@ -0,0 +1,182 @@
+/*
+ * EpiRust
+ * Copyright (c) 2020  ThoughtWorks, Inc.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::any::Any;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use epirust::agent::{Citizen, State};
+use epirust::geography::Point;
+use epirust::listeners::events::citizen_state::CitizenState;
+use epirust::{Counts, Listener, Summary};
+
+/// The names of the states of citizens, in the order of their codes in `CitizenStates::state`
+pub fn state_names() -> Vec<String> {
+    vec![State::Susceptible {}, State::Infected {}, State::Recovered {}, State::Deceased {}]
+        .into_iter()
+        .map(CitizenState::state_str)
+        .collect()
+}
+
+fn state_code(state: State) -> u8 {
+    match state {
+        State::Susceptible {} => 0,
+        State::Infected {} => 1,
+        State::Recovered {} => 2,
+        State::Deceased {} => 3,
+    }
+}
+
+/// The state and location of citizens, one row per citizen and sampled hour, stored by column
+#[derive(Default, Debug, PartialEq)]
+pub struct CitizenStates {
+    pub hour: Vec<i32>,
+    pub citizen_id: Vec<i32>,
+    pub state: Vec<u8>,
+    pub x: Vec<i32>,
+    pub y: Vec<i32>,
+}
+
+impl CitizenStates {
+    fn push(&mut self, hr: i32, citizen: &Citizen, location: &Point) {
+        self.hour.push(hr);
+        self.citizen_id.push(citizen.id);
+        self.state.push(state_code(citizen.state_machine.state));
+        self.x.push(location.x);
+        self.y.push(location.y);
+    }
+
+    fn append(&mut self, mut other: CitizenStates) {
+        self.hour.append(&mut other.hour);
+        self.citizen_id.append(&mut other.citizen_id);
+        self.state.append(&mut other.state);
+        self.x.append(&mut other.x);
+        self.y.append(&mut other.y);
+    }
+}
+
+/// What a `Recorder` has seen of a run
+#[derive(Default)]
+pub struct Recording {
+    pub counts: Vec<Counts>,
+    pub citizen_states: Option<CitizenStates>,
+}
+
+/// Records the counts of every hour, and optionally the citizen states of every `sample_interval` hours. The states
+/// of an hour are buffered, and only added to the shared recording when the hour ends.
+pub struct Recorder {
+    recording: Arc<Mutex<Recording>>,
+    sample_interval: Option<i32>,
+    hour_states: CitizenStates,
+}
+
+impl Recorder {
+    pub fn new(recording: Arc<Mutex<Recording>>, sample_interval: Option<i32>) -> Recorder {
+        if sample_interval.is_some() {
+            recording.lock().unwrap().citizen_states = Some(CitizenStates::default());
+        }
+        Recorder { recording, sample_interval, hour_states: CitizenStates::default() }
+    }
+}
+
+impl Listener for Recorder {
+    fn counts_updated(&mut self, counts: Counts) {
+        self.recording.lock().unwrap().counts.push(counts);
+    }
+
+    fn simulation_ended(&mut self, _summary: &Summary) {}
+
+    fn citizen_state_updated(&mut self, hr: i32, citizen: &Citizen, location: &Point) {
+        if self.sample_interval.is_some_and(|interval| hr % interval == 0) {
+            self.hour_states.push(hr, citizen, location);
+        }
+    }
+
+    fn hour_ended(&mut self, _hr: i32) {
+        if self.hour_states.hour.is_empty() {
+            return;
+        }
+        let hour_states = mem::take(&mut self.hour_states);
+        if let Some(citizen_states) = self.recording.lock().unwrap().citizen_states.as_mut() {
+            citizen_states.append(hour_states);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use epirust::geography::Area;
+    use epirust::random_wrapper::RandomWrapper;
+
+    use super::*;
+
+    fn citizen(id: i32) -> Citizen {
+        let area = Area::new(Point::new(0, 0), Point::new(1, 1));
+        Citizen::new_citizen(id, area, area, Point::new(2, 2), true, true, &mut RandomWrapper::new())
+    }
+
+    fn run_hours(recorder: &mut Recorder, hours: i32, citizens: &[Citizen]) {
+        for hr in 1..=hours {
+            recorder.hour_started(hr);
+            recorder.counts_updated(Counts::new(citizens.len() as i32, 0));
+            citizens.iter().for_each(|citizen| recorder.citizen_state_updated(hr, citizen, &Point::new(hr, citizen.id)));
+            recorder.hour_ended(hr);
+        }
+    }
+
+    #[test]
+    fn should_record_citizen_states_every_sample_interval() {
+        let recording = Arc::new(Mutex::new(Recording::default()));
+        let mut recorder = Recorder::new(recording.clone(), Some(2));
+
+        run_hours(&mut recorder, 5, &[citizen(1), citizen(2)]);
+
+        let recording = recording.lock().unwrap();
+        assert_eq!(recording.counts.len(), 5);
+        assert_eq!(recording.citizen_states, Some(CitizenStates {
+            hour: vec![2, 2, 4, 4],
+            citizen_id: vec![1, 2, 1, 2],
+            state: vec![0, 0, 0, 0],
+            x: vec![2, 2, 4, 4],
+            y: vec![1, 2, 1, 2],
+        }));
+    }
+
+    #[test]
+    fn should_only_record_counts_without_sample_interval() {
+        let recording = Arc::new(Mutex::new(Recording::default()));
+        let mut recorder = Recorder::new(recording.clone(), None);
+
+        run_hours(&mut recorder, 3, &[citizen(1)]);
+
+        let recording = recording.lock().unwrap();
+        assert_eq!(recording.counts.len(), 3);
+        assert!(recording.citizen_states.is_none());
+    }
+
+    #[test]
+    fn should_name_states_in_the_order_of_their_codes() {
+        assert_eq!(state_names(), vec!["s", "i", "r", "d"]);
+        assert_eq!(state_code(State::Deceased {}), 3);
+    }
+}